@@ -0,0 +1,76 @@
+/// How the CPU used a byte it read, for code/data loggers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    //opcode and operand bytes of an executed instruction
+    Code,
+    //operands, pointers and vectors
+    Data,
+    //operands reached through a zero page pointer, `(zp,X)` and `(zp),Y`
+    IndirectData,
+    //the target of an indirect jump
+    IndirectCode,
+}
+
+/// What the CPU is wired to. Only `read` and `write` are required, the rest are for
+/// machines with interrupts, DMA or devices that run alongside the CPU.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, data: u8);
+
+    /// Advances the rest of the machine by the given number of CPU cycles.
+    fn tick(&mut self, _cycles: u16) {}
+
+    /// When true `tick(1)` is called before every access, so accesses happen on the cycle
+    /// they do on hardware, and only the cycles without an access are ticked at the end
+    /// of the instruction. Otherwise the whole instruction is ticked at its end.
+    fn interleaved(&self) -> bool {
+        false
+    }
+
+    /// Returns true once for each NMI, which is edge triggered.
+    fn poll_nmi(&mut self) -> bool {
+        false
+    }
+
+    /// The level of the IRQ line.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Cycles the CPU was halted for DMA since the last call, added to the instruction.
+    fn take_stall(&mut self) -> u16 {
+        0
+    }
+
+    fn mark(&mut self, _addr: u16, _usage: Usage) {}
+}
+
+/// 64 KiB of RAM over the whole address space, for tests and simple machines.
+pub struct FlatMemory {
+    pub memory: Box<[u8; 0x10000]>,
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        FlatMemory::new()
+    }
+}
+
+impl FlatMemory {
+    pub fn new() -> Self {
+        FlatMemory {
+            memory: Box::new([0; 0x10000]),
+        }
+    }
+}
+
+impl Bus for FlatMemory {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+    }
+}
@@ -0,0 +1,106 @@
+use crate::op_codes;
+use crate::{AddressingMode, Variant};
+
+/// A decoded instruction, `text` is in the usual assembler syntax like `LDA $10,X`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl Instruction {
+    pub fn len(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.len())
+    }
+}
+
+/// Decodes the instruction at `addr`, reading memory through `read`.
+pub fn disassemble<F: Fn(u16) -> u8>(read: F, addr: u16) -> Instruction {
+    disassemble_with_labels(read, addr, |_| None)
+}
+
+/// Like `disassemble`, with addresses in operands replaced by the names `label` knows.
+pub fn disassemble_with_labels<F, L>(read: F, addr: u16, label: L) -> Instruction
+where
+    F: Fn(u16) -> u8,
+    L: Fn(u16) -> Option<String>,
+{
+    disassemble_as(Variant::Ricoh2A03, read, addr, label)
+}
+
+/// Like `disassemble_with_labels`, with the instruction set of `variant`.
+pub fn disassemble_as<F, L>(variant: Variant, read: F, addr: u16, label: L) -> Instruction
+where
+    F: Fn(u16) -> u8,
+    L: Fn(u16) -> Option<String>,
+{
+    let code = read(addr);
+    let opcode = match op_codes::opcodes(variant).get(&code) {
+        Some(opcode) => opcode,
+        None => {
+            return Instruction {
+                addr,
+                bytes: vec![code],
+                text: format!(".db ${:02x}", code),
+            }
+        }
+    };
+    let bytes: Vec<u8> = (0..opcode.bytes as u16)
+        .map(|i| read(addr.wrapping_add(i)))
+        .collect();
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = (bytes.get(2).copied().unwrap_or(0) as u16) << 8 | byte as u16;
+
+    let zero_page = || label(byte as u16).unwrap_or_else(|| format!("${:02x}", byte));
+    let absolute = |word: u16| label(word).unwrap_or_else(|| format!("${:04x}", word));
+    let operand = match (&opcode.addressing_mode, opcode.bytes) {
+        (AddressingMode::Immediate, _) => format!("#${:02x}", byte),
+        (AddressingMode::ZeroPage, _) => zero_page(),
+        (AddressingMode::ZeroPage_X, _) => format!("{},X", zero_page()),
+        (AddressingMode::ZeroPage_Y, _) => format!("{},Y", zero_page()),
+        (AddressingMode::Absolute, _) => absolute(word),
+        (AddressingMode::Absolute_X, _) => format!("{},X", absolute(word)),
+        (AddressingMode::Absolute_Y, _) => format!("{},Y", absolute(word)),
+        (AddressingMode::Indirect_X, _) => format!("({},X)", zero_page()),
+        (AddressingMode::Indirect_Y, _) => format!("({}),Y", zero_page()),
+        (AddressingMode::ZeroPage_Indirect, _) => format!("({})", zero_page()),
+        //accumulator forms of the shifts, and of INC and DEC on the 65C02
+        (AddressingMode::NoneAddressing, 1)
+            if matches!(code, 0x0a | 0x4a | 0x2a | 0x6a | 0x1a | 0x3a) =>
+        {
+            "A".to_string()
+        }
+        (AddressingMode::NoneAddressing, 1) => String::new(),
+        //branches are relative to the next instruction
+        (AddressingMode::NoneAddressing, 2) => {
+            absolute(addr.wrapping_add(2).wrapping_add(byte as i8 as u16))
+        }
+        //BBR and BBS test a zero page bit and branch relative to the next instruction
+        (AddressingMode::NoneAddressing, 3) if code & 0x0f == 0x0f => {
+            let offset = bytes.get(2).copied().unwrap_or(0) as i8 as u16;
+            format!(
+                "{},{}",
+                zero_page(),
+                absolute(addr.wrapping_add(3).wrapping_add(offset))
+            )
+        }
+        (AddressingMode::NoneAddressing, _) if code == 0x6c => format!("({})", absolute(word)),
+        (AddressingMode::NoneAddressing, _) if code == 0x7c => format!("({},X)", absolute(word)),
+        (AddressingMode::NoneAddressing, _) => absolute(word),
+    };
+    let text = if operand.is_empty() {
+        opcode.name.to_string()
+    } else {
+        format!("{} {}", opcode.name, operand)
+    };
+    Instruction { addr, bytes, text }
+}
@@ -0,0 +1,973 @@
+//! A 6502 core for the NMOS 6502, the NES's 2A03 and the 65C02, running on anything
+//! that implements `Bus`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+mod bus;
+pub mod disasm;
+pub mod op_codes;
+
+pub use bus::{Bus, FlatMemory, Usage};
+#[cfg(test)]
+mod tests;
+
+const STACK: u16 = 0x0100;
+const STACK_RESET: u8 = 0xfd;
+
+const NMI_VECTOR: u16 = 0xfffa;
+const RESET_VECTOR: u16 = 0xfffc;
+const IRQ_VECTOR: u16 = 0xfffe;
+
+//  7 6 5 4 3 2 1 0
+//  N V _ B D I Z C
+pub const CARRY_FLAG: u8 = 0b0000_0001;
+pub const ZERO_FLAG: u8 = 0b0000_0010;
+pub const INTERRUPT_DISABLE_FLAG: u8 = 0b0000_0100;
+pub const DECIMAL_MODE_FLAG: u8 = 0b0000_1000;
+pub const BREAK_FLAG: u8 = 0b0001_0000;
+pub const BREAK2_FLAG: u8 = 0b0010_0000;
+pub const OVERFLOW_FLAG: u8 = 0b0100_0000;
+pub const NEGATIVE_FLAG: u8 = 0b1000_0000;
+
+pub struct CPU<B: Bus> {
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    pub status: u8,
+    pub program_counter: u16,
+    pub stack_pointer: u8,
+    //cycles executed since power on
+    pub cycles: u64,
+    pub bus: B,
+    pub variant: Variant,
+    //page crossing and branch penalties of the current instruction
+    extra_cycles: u8,
+    //cycles the bus already ran during the current instruction, None when catching up
+    ticked: Option<u16>,
+    //the last step entered NMI or IRQ instead of running an instruction
+    interrupted: bool,
+}
+
+impl<B: Bus + Default> Default for CPU<B> {
+    fn default() -> Self {
+        CPU::new()
+    }
+}
+
+/// The 6502 the core behaves like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    //the NES CPU, SED sets the D flag but ADC and SBC ignore it
+    Ricoh2A03,
+    //with decimal mode, including the NMOS flag results of BCD arithmetic
+    Nmos6502,
+    //WDC 65C02 with the Rockwell bit instructions, BCD results set N and Z properly
+    Cmos65C02,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CpuError {
+    UnknownOpcode { opcode: u8, address: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { opcode, address } => {
+                write!(
+                    f,
+                    "Instruction {:x} at {:04x} does not exist",
+                    opcode, address
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+#[derive(Debug)]
+#[allow(non_camel_case_types)]
+pub enum AddressingMode {
+    Immediate,
+    ZeroPage,
+    ZeroPage_X,
+    ZeroPage_Y,
+    Absolute,
+    Absolute_X,
+    Absolute_Y,
+    Indirect_X,
+    Indirect_Y,
+    //(zp) of the 65C02
+    ZeroPage_Indirect,
+    NoneAddressing,
+}
+
+impl<B: Bus + Default> CPU<B> {
+    pub fn new() -> Self {
+        CPU::with_bus(B::default())
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    /// A 2A03, set `variant` for other CPUs.
+    pub fn with_bus(bus: B) -> Self {
+        CPU {
+            register_a: 0,
+            register_x: 0,
+            register_y: 0,
+            status: 0,
+            program_counter: 0,
+            stack_pointer: STACK_RESET,
+            cycles: 0,
+            bus,
+            variant: Variant::Ricoh2A03,
+            extra_cycles: 0,
+            ticked: None,
+            interrupted: false,
+        }
+    }
+
+    /// Runs until the next BRK, which is how test programs signal they are done.
+    ///
+    /// Panics on CPU errors, use `step` to handle them.
+    pub fn run(&mut self) {
+        while self.bus.read(self.program_counter) != 0x00 {
+            if let Err(e) = self.step() {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    /// Executes a single instruction, or enters a pending interrupt, and returns the cycles it took.
+    pub fn step(&mut self) -> Result<u16, CpuError> {
+        self.ticked = if self.bus.interleaved() {
+            Some(0)
+        } else {
+            None
+        };
+        self.interrupted = false;
+        if self.bus.poll_nmi() {
+            self.interrupted = true;
+            self.interrupt(NMI_VECTOR, false);
+            return Ok(self.finish_step(7));
+        }
+        if self.bus.irq() && self.status & INTERRUPT_DISABLE_FLAG == 0 {
+            self.interrupted = true;
+            self.interrupt(IRQ_VECTOR, false);
+            return Ok(self.finish_step(7));
+        }
+
+        self.execute(op_codes::opcodes(self.variant))
+    }
+
+    /// True when the last `step` entered an NMI or IRQ handler, BRK does not count.
+    pub fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    //decodes with `code_map`, which tests swap for tables with entries missing
+    fn execute(&mut self, code_map: &HashMap<u8, &op_codes::OpCode>) -> Result<u16, CpuError> {
+        let opcode = self.mem_read(self.program_counter);
+        let instruction = code_map.get(&opcode).ok_or(CpuError::UnknownOpcode {
+            opcode,
+            address: self.program_counter,
+        })?;
+        for i in 0..instruction.bytes as u16 {
+            self.bus
+                .mark(self.program_counter.wrapping_add(i), Usage::Code);
+        }
+        self.program_counter = self.program_counter.wrapping_add(1);
+        let program_counter_state = self.program_counter;
+        self.extra_cycles = 0;
+
+        let mode = &instruction.addressing_mode;
+        match opcode {
+            /* ADC */
+            0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => self.adc(mode),
+
+            /* AND */
+            0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => self.and(mode),
+
+            /* ASL */
+            0x0a => self.asl_accumulator(),
+            0x06 | 0x16 | 0x0e | 0x1e => self.asl(mode),
+
+            /* BIT */
+            0x24 | 0x2c => self.bit(mode),
+
+            /* Branching */
+            0x10 => self.branch(self.status & NEGATIVE_FLAG == 0),
+            0x30 => self.branch(self.status & NEGATIVE_FLAG != 0),
+            0x50 => self.branch(self.status & OVERFLOW_FLAG == 0),
+            0x70 => self.branch(self.status & OVERFLOW_FLAG != 0),
+            0x90 => self.branch(self.status & CARRY_FLAG == 0),
+            0xb0 => self.branch(self.status & CARRY_FLAG != 0),
+            0xd0 => self.branch(self.status & ZERO_FLAG == 0),
+            0xf0 => self.branch(self.status & ZERO_FLAG != 0),
+
+            /* BRK */
+            0x00 => {
+                //BRK skips the byte after it, the return address points past it
+                self.program_counter = self.program_counter.wrapping_add(1);
+                self.interrupt(IRQ_VECTOR, true);
+            }
+
+            /* CMP */
+            0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => {
+                self.compare(mode, self.register_a)
+            }
+
+            /* CPX */
+            0xe0 | 0xe4 | 0xec => self.compare(mode, self.register_x),
+
+            /* CPY */
+            0xc0 | 0xc4 | 0xcc => self.compare(mode, self.register_y),
+
+            /* DEC */
+            0xc6 | 0xd6 | 0xce | 0xde => self.dec(mode),
+
+            /* EOR */
+            0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => self.eor(mode),
+
+            /* Flag Instructions */
+            0x18 => self.status &= !CARRY_FLAG,
+            0x38 => self.status |= CARRY_FLAG,
+            0x58 => self.status &= !INTERRUPT_DISABLE_FLAG,
+            0x78 => self.status |= INTERRUPT_DISABLE_FLAG,
+            0xb8 => self.status &= !OVERFLOW_FLAG,
+            0xd8 => self.status &= !DECIMAL_MODE_FLAG,
+            0xf8 => self.status |= DECIMAL_MODE_FLAG,
+
+            /* INC */
+            0xe6 | 0xf6 | 0xee | 0xfe => self.inc(mode),
+
+            /* JMP */
+            0x4c => self.program_counter = self.mem_read_u16(self.program_counter),
+            0x6c => self.jmp_indirect(),
+
+            /* JSR */
+            0x20 => {
+                //the pushed return address is the last byte of the JSR
+                self.stack_push_u16(self.program_counter.wrapping_add(1));
+                self.program_counter = self.mem_read_u16(self.program_counter);
+            }
+
+            /* LDA */
+            0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
+                self.lda(mode);
+            }
+
+            /* LDX */
+            0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => self.ldx(mode),
+
+            /* LDY */
+            0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => self.ldy(mode),
+
+            /* LSR */
+            0x4a => self.lsr_accumulator(),
+            0x46 | 0x56 | 0x4e | 0x5e => self.lsr(mode),
+
+            /* NOP */
+            0xea => {}
+
+            /* ORA */
+            0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => self.ora(mode),
+
+            /* Register Instructions */
+            0xaa => self.tax(),
+            0x8a => self.txa(),
+            0xca => self.dex(),
+            0xe8 => self.inx(),
+            0xa8 => self.tay(),
+            0x98 => self.tya(),
+            0x88 => self.dey(),
+            0xc8 => self.iny(),
+
+            /* ROL */
+            0x2a => self.rol_accumulator(),
+            0x26 | 0x36 | 0x2e | 0x3e => self.rol(mode),
+
+            /* ROR */
+            0x6a => self.ror_accumulator(),
+            0x66 | 0x76 | 0x6e | 0x7e => self.ror(mode),
+
+            /* RTI */
+            0x40 => {
+                self.plp();
+                self.program_counter = self.stack_pop_u16();
+            }
+
+            /* RTS */
+            0x60 => self.program_counter = self.stack_pop_u16().wrapping_add(1),
+
+            /* SBC */
+            0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => self.sbc(mode),
+
+            /* STA */
+            0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => {
+                self.store(mode, self.register_a);
+            }
+
+            /* Stack Instructions */
+            0x9a => self.stack_pointer = self.register_x,
+            0xba => {
+                self.register_x = self.stack_pointer;
+                self.update_zero_and_negative_flags(self.register_x);
+            }
+            0x48 => self.stack_push(self.register_a),
+            0x68 => {
+                self.register_a = self.stack_pop();
+                self.update_zero_and_negative_flags(self.register_a);
+            }
+            0x08 => self.stack_push(self.status | BREAK_FLAG | BREAK2_FLAG),
+            0x28 => self.plp(),
+
+            /* STX */
+            0x86 | 0x96 | 0x8e => self.store(mode, self.register_x),
+
+            /* STY */
+            0x84 | 0x94 | 0x8c => self.store(mode, self.register_y),
+
+            /* 65C02 */
+            0x72 => self.adc(mode),
+            0x32 => self.and(mode),
+            0xd2 => self.compare(mode, self.register_a),
+            0x52 => self.eor(mode),
+            0xb2 => self.lda(mode),
+            0x12 => self.ora(mode),
+            0xf2 => self.sbc(mode),
+            0x92 => self.store(mode, self.register_a),
+            0x89 | 0x34 | 0x3c => self.bit(mode),
+            0x80 => self.branch(true),
+            0x1a => self.modify_accumulator(|_, value| value.wrapping_add(1)),
+            0x3a => self.modify_accumulator(|_, value| value.wrapping_sub(1)),
+            0x7c => self.jmp_indexed_indirect(),
+            0xda => self.stack_push(self.register_x),
+            0x5a => self.stack_push(self.register_y),
+            0xfa => {
+                self.register_x = self.stack_pop();
+                self.update_zero_and_negative_flags(self.register_x);
+            }
+            0x7a => {
+                self.register_y = self.stack_pop();
+                self.update_zero_and_negative_flags(self.register_y);
+            }
+            0x64 | 0x74 | 0x9c | 0x9e => self.store(mode, 0),
+            0x14 | 0x1c => self.test_and_modify_bits(mode, false),
+            0x04 | 0x0c => self.test_and_modify_bits(mode, true),
+            //RMB, SMB, BBR and BBS
+            _ if opcode & 0x0f == 0x07 => self.modify_bit(opcode),
+            _ if opcode & 0x0f == 0x0f => self.branch_on_bit(opcode),
+
+            //in the table but not implemented, left where it is like an unknown opcode
+            _ => {
+                self.program_counter = program_counter_state.wrapping_sub(1);
+                return Err(CpuError::UnknownOpcode {
+                    opcode,
+                    address: self.program_counter,
+                });
+            }
+        }
+
+        if program_counter_state == self.program_counter {
+            self.program_counter = self
+                .program_counter
+                .wrapping_add((instruction.bytes - 1) as u16);
+        }
+
+        Ok(self.finish_step(instruction.cycles + self.extra_cycles))
+    }
+
+    fn finish_step(&mut self, cycles: u8) -> u16 {
+        //the accesses ran their cycles already, what is left are internal and dummy cycles
+        let ticked = self.ticked.take().unwrap_or(0);
+        let cycles = cycles as u16 + self.bus.take_stall();
+        self.cycles += cycles as u64;
+        self.bus.tick(cycles.saturating_sub(ticked));
+        cycles
+    }
+
+    //each access is one CPU cycle, run it before the access when interleaving
+    fn tick_access(&mut self) {
+        if let Some(ticked) = self.ticked.as_mut() {
+            *ticked += 1;
+            self.bus.tick(1);
+        }
+    }
+
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.tick_access();
+        self.bus.read(addr)
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.tick_access();
+        self.bus.write(addr, data);
+    }
+
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
+        let lo = self.mem_read(pos) as u16;
+        let hi = self.mem_read(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    fn mem_write_u16(&mut self, pos: u16, data: u16) {
+        //shifting 8 bits to the right
+        let hi = (data >> 8) as u8;
+        let lo = (data & 0xff) as u8;
+        self.mem_write(pos, lo);
+        self.mem_write(pos.wrapping_add(1), hi);
+    }
+
+    pub fn load_and_run(&mut self, program: Vec<u8>) {
+        self.load(program);
+        self.reset();
+        self.run();
+    }
+
+    pub fn reset(&mut self) {
+        self.register_a = 0;
+        self.register_x = 0;
+        self.register_y = 0;
+        self.stack_pointer = STACK_RESET;
+        self.status = INTERRUPT_DISABLE_FLAG | BREAK2_FLAG;
+
+        self.program_counter = self.read_vector(RESET_VECTOR);
+    }
+
+    pub fn load(&mut self, program: Vec<u8>) {
+        for (i, byte) in program.iter().enumerate() {
+            self.mem_write(0x8000 + i as u16, *byte);
+        }
+        self.mem_write_u16(RESET_VECTOR, 0x8000);
+    }
+
+    fn interrupt(&mut self, vector: u16, break_flag: bool) {
+        self.stack_push_u16(self.program_counter);
+        let mut flags = self.status | BREAK2_FLAG;
+        if break_flag {
+            flags |= BREAK_FLAG;
+        } else {
+            flags &= !BREAK_FLAG;
+        }
+        self.stack_push(flags);
+        self.status |= INTERRUPT_DISABLE_FLAG;
+        if self.variant == Variant::Cmos65C02 {
+            self.status &= !DECIMAL_MODE_FLAG;
+        }
+        self.program_counter = self.read_vector(vector);
+    }
+
+    fn read_vector(&mut self, vector: u16) -> u16 {
+        self.bus.mark(vector, Usage::Data);
+        self.bus.mark(vector + 1, Usage::Data);
+        self.mem_read_u16(vector)
+    }
+
+    //marks the operand read by an instruction in the code/data log
+    fn log_data(&mut self, addr: u16, mode: &AddressingMode) {
+        let usage = match mode {
+            //the operand is part of the instruction
+            AddressingMode::Immediate => return,
+            AddressingMode::Indirect_X
+            | AddressingMode::Indirect_Y
+            | AddressingMode::ZeroPage_Indirect => Usage::IndirectData,
+            _ => Usage::Data,
+        };
+        self.bus.mark(addr, usage);
+    }
+
+    fn stack_push(&mut self, data: u8) {
+        self.mem_write(STACK + self.stack_pointer as u16, data);
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+    }
+
+    fn stack_pop(&mut self) -> u8 {
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
+        self.mem_read(STACK + self.stack_pointer as u16)
+    }
+
+    fn stack_push_u16(&mut self, data: u16) {
+        self.stack_push((data >> 8) as u8);
+        self.stack_push((data & 0xff) as u8);
+    }
+
+    fn stack_pop_u16(&mut self) -> u16 {
+        let lo = self.stack_pop() as u16;
+        let hi = self.stack_pop() as u16;
+        (hi << 8) | lo
+    }
+
+    //reads the operand of instructions that take one more cycle when crossing a page
+    fn read_operand(&mut self, mode: &AddressingMode) -> u8 {
+        let (addr, page_crossed) = self.get_operand_address(mode);
+        if page_crossed {
+            self.extra_cycles += 1;
+        }
+        self.log_data(addr, mode);
+        self.mem_read(addr)
+    }
+
+    fn lda(&mut self, addressing_mode: &AddressingMode) {
+        let value = self.read_operand(addressing_mode);
+        self.register_a = value;
+        self.update_zero_flag(self.register_a);
+        self.update_negative_flag(self.register_a);
+    }
+
+    fn ldx(&mut self, mode: &AddressingMode) {
+        self.register_x = self.read_operand(mode);
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn ldy(&mut self, mode: &AddressingMode) {
+        self.register_y = self.read_operand(mode);
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn store(&mut self, mode: &AddressingMode, value: u8) {
+        let (addr, _) = self.get_operand_address(mode);
+        self.mem_write(addr, value);
+    }
+
+    fn and(&mut self, mode: &AddressingMode) {
+        self.register_a &= self.read_operand(mode);
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn eor(&mut self, mode: &AddressingMode) {
+        self.register_a ^= self.read_operand(mode);
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn ora(&mut self, mode: &AddressingMode) {
+        self.register_a |= self.read_operand(mode);
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn adc(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+        if self.decimal_mode() {
+            self.add_decimal(value);
+            self.fix_decimal_flags();
+        } else {
+            self.add_to_register_a(value);
+        }
+    }
+
+    fn sbc(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+        if self.decimal_mode() {
+            if self.variant == Variant::Cmos65C02 {
+                self.subtract_decimal_cmos(value);
+            } else {
+                self.subtract_decimal(value);
+            }
+            self.fix_decimal_flags();
+        } else {
+            //A - M - (1 - C) is the same as A + !M + C
+            self.add_to_register_a(!value);
+        }
+    }
+
+    fn decimal_mode(&self) -> bool {
+        self.variant != Variant::Ricoh2A03 && self.status & DECIMAL_MODE_FLAG != 0
+    }
+
+    //the 65C02 takes a cycle more to set N and Z from the BCD result
+    fn fix_decimal_flags(&mut self) {
+        if self.variant == Variant::Cmos65C02 {
+            self.update_zero_and_negative_flags(self.register_a);
+            self.extra_cycles += 1;
+        }
+    }
+
+    //NMOS sets Z from the binary sum and N and V from the sum before the high digit is adjusted
+    fn add_decimal(&mut self, value: u8) {
+        let a = self.register_a as u16;
+        let b = value as u16;
+        let carry = (self.status & CARRY_FLAG) as u16;
+        let mut lo = (a & 0x0f) + (b & 0x0f) + carry;
+        if lo > 0x09 {
+            lo = ((lo + 0x06) & 0x0f) + 0x10;
+        }
+        let mut sum = (a & 0xf0) + (b & 0xf0) + lo;
+        self.set_flag(ZERO_FLAG, (a + b + carry) & 0xff == 0);
+        self.set_flag(NEGATIVE_FLAG, sum & 0x80 != 0);
+        self.set_flag(OVERFLOW_FLAG, (a ^ sum) & (b ^ sum) & 0x80 != 0);
+        if sum > 0x9f {
+            sum += 0x60;
+        }
+        self.set_flag(CARRY_FLAG, sum > 0xff);
+        self.register_a = sum as u8;
+    }
+
+    //NMOS sets every flag as the binary subtraction would
+    fn subtract_decimal(&mut self, value: u8) {
+        let a = self.register_a as i16;
+        let b = value as i16;
+        let borrow = 1 - (self.status & CARRY_FLAG) as i16;
+        self.add_to_register_a(!value);
+        let mut lo = (a & 0x0f) - (b & 0x0f) - borrow;
+        if lo < 0 {
+            lo = ((lo - 0x06) & 0x0f) - 0x10;
+        }
+        let mut difference = (a & 0xf0) - (b & 0xf0) + lo;
+        if difference < 0 {
+            difference -= 0x60;
+        }
+        self.register_a = difference as u8;
+    }
+
+    //the 65C02 adjusts the binary difference, C and V are the binary ones, N and Z come
+    //from the BCD result
+    fn subtract_decimal_cmos(&mut self, value: u8) {
+        let a = self.register_a as i16;
+        let b = value as i16;
+        let borrow = 1 - (self.status & CARRY_FLAG) as i16;
+        self.add_to_register_a(!value);
+        let lo = (a & 0x0f) - (b & 0x0f) - borrow;
+        let mut difference = a - b - borrow;
+        if difference < 0 {
+            difference -= 0x60;
+        }
+        if lo < 0 {
+            difference -= 0x06;
+        }
+        self.register_a = difference as u8;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn add_to_register_a(&mut self, value: u8) {
+        let sum = self.register_a as u16 + value as u16 + (self.status & CARRY_FLAG) as u16;
+        let result = sum as u8;
+        self.set_flag(CARRY_FLAG, sum > 0xff);
+        //overflow when both operands have the same sign and the result does not
+        self.set_flag(
+            OVERFLOW_FLAG,
+            (value ^ result) & (self.register_a ^ result) & 0x80 != 0,
+        );
+        self.register_a = result;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn compare(&mut self, mode: &AddressingMode, register: u8) {
+        let value = self.read_operand(mode);
+        self.set_flag(CARRY_FLAG, register >= value);
+        self.update_zero_and_negative_flags(register.wrapping_sub(value));
+    }
+
+    fn bit(&mut self, mode: &AddressingMode) {
+        //BIT abs,X of the 65C02 takes the page crossing cycle like the other reads
+        let value = self.read_operand(mode);
+        self.set_flag(ZERO_FLAG, self.register_a & value == 0);
+        //BIT #imm of the 65C02 only sets Z
+        if let AddressingMode::Immediate = mode {
+            return;
+        }
+        self.set_flag(NEGATIVE_FLAG, value & NEGATIVE_FLAG != 0);
+        self.set_flag(OVERFLOW_FLAG, value & OVERFLOW_FLAG != 0);
+    }
+
+    //TSB and TRB
+    fn test_and_modify_bits(&mut self, mode: &AddressingMode, set: bool) {
+        let (addr, _) = self.get_operand_address(mode);
+        self.log_data(addr, mode);
+        let value = self.mem_read(addr);
+        self.set_flag(ZERO_FLAG, self.register_a & value == 0);
+        let result = if set {
+            value | self.register_a
+        } else {
+            value & !self.register_a
+        };
+        self.mem_write(addr, result);
+    }
+
+    //RMB and SMB, the bit number is in the high nibble and SMB has bit 7 set
+    fn modify_bit(&mut self, opcode: u8) {
+        let (addr, _) = self.get_operand_address(&AddressingMode::ZeroPage);
+        self.log_data(addr, &AddressingMode::ZeroPage);
+        let value = self.mem_read(addr);
+        let bit = 1 << ((opcode >> 4) & 0x07);
+        let result = if opcode & 0x80 != 0 {
+            value | bit
+        } else {
+            value & !bit
+        };
+        self.mem_write(addr, result);
+    }
+
+    //BBR and BBS, the offset follows the zero page address
+    fn branch_on_bit(&mut self, opcode: u8) {
+        let (addr, _) = self.get_operand_address(&AddressingMode::ZeroPage);
+        self.log_data(addr, &AddressingMode::ZeroPage);
+        let value = self.mem_read(addr);
+        let bit_set = value & (1 << ((opcode >> 4) & 0x07)) != 0;
+        if bit_set == (opcode & 0x80 != 0) {
+            self.program_counter = self.program_counter.wrapping_add(1);
+            self.branch(true);
+        }
+    }
+
+    //read-modify-write instructions on memory
+    //returns whether indexing crossed a page
+    fn modify(&mut self, mode: &AddressingMode, operation: fn(&mut Self, u8) -> u8) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
+        self.log_data(addr, mode);
+        let value = self.mem_read(addr);
+        let result = operation(self, value);
+        self.mem_write(addr, result);
+        self.update_zero_and_negative_flags(result);
+        page_crossed
+    }
+
+    //the 65C02's indexed shifts are a cycle faster unless they cross a page, INC and DEC are not
+    fn shift(&mut self, mode: &AddressingMode, operation: fn(&mut Self, u8) -> u8) {
+        if self.modify(mode, operation) && self.variant == Variant::Cmos65C02 {
+            self.extra_cycles += 1;
+        }
+    }
+
+    fn modify_accumulator(&mut self, operation: fn(&mut Self, u8) -> u8) {
+        self.register_a = operation(self, self.register_a);
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn shift_left(&mut self, value: u8) -> u8 {
+        self.set_flag(CARRY_FLAG, value & 0x80 != 0);
+        value << 1
+    }
+
+    fn shift_right(&mut self, value: u8) -> u8 {
+        self.set_flag(CARRY_FLAG, value & 0x01 != 0);
+        value >> 1
+    }
+
+    fn rotate_left(&mut self, value: u8) -> u8 {
+        let carry = self.status & CARRY_FLAG;
+        self.set_flag(CARRY_FLAG, value & 0x80 != 0);
+        (value << 1) | carry
+    }
+
+    fn rotate_right(&mut self, value: u8) -> u8 {
+        let carry = self.status & CARRY_FLAG;
+        self.set_flag(CARRY_FLAG, value & 0x01 != 0);
+        (value >> 1) | (carry << 7)
+    }
+
+    fn asl(&mut self, mode: &AddressingMode) {
+        self.shift(mode, CPU::shift_left);
+    }
+
+    fn asl_accumulator(&mut self) {
+        self.modify_accumulator(CPU::shift_left);
+    }
+
+    fn lsr(&mut self, mode: &AddressingMode) {
+        self.shift(mode, CPU::shift_right);
+    }
+
+    fn lsr_accumulator(&mut self) {
+        self.modify_accumulator(CPU::shift_right);
+    }
+
+    fn rol(&mut self, mode: &AddressingMode) {
+        self.shift(mode, CPU::rotate_left);
+    }
+
+    fn rol_accumulator(&mut self) {
+        self.modify_accumulator(CPU::rotate_left);
+    }
+
+    fn ror(&mut self, mode: &AddressingMode) {
+        self.shift(mode, CPU::rotate_right);
+    }
+
+    fn ror_accumulator(&mut self) {
+        self.modify_accumulator(CPU::rotate_right);
+    }
+
+    fn inc(&mut self, mode: &AddressingMode) {
+        self.modify(mode, |_, value| value.wrapping_add(1));
+    }
+
+    fn dec(&mut self, mode: &AddressingMode) {
+        self.modify(mode, |_, value| value.wrapping_sub(1));
+    }
+
+    fn branch(&mut self, condition: bool) {
+        if !condition {
+            return;
+        }
+        self.extra_cycles += 1;
+        let offset = self.mem_read(self.program_counter) as i8;
+        let next = self.program_counter.wrapping_add(1);
+        let target = next.wrapping_add(offset as u16);
+        if next & 0xff00 != target & 0xff00 {
+            self.extra_cycles += 1;
+        }
+        self.program_counter = target;
+    }
+
+    fn jmp_indirect(&mut self) {
+        let pointer = self.mem_read_u16(self.program_counter);
+        self.jump_through(pointer);
+    }
+
+    //JMP (abs,X) of the 65C02
+    fn jmp_indexed_indirect(&mut self) {
+        let base = self.mem_read_u16(self.program_counter);
+        self.jump_through(base.wrapping_add(self.register_x as u16));
+    }
+
+    fn jump_through(&mut self, pointer: u16) {
+        //6502 bug: the high byte is not fetched from the next page when the pointer is at $xxFF,
+        //the 65C02 fixed it
+        let hi_pointer = if self.variant == Variant::Cmos65C02 {
+            pointer.wrapping_add(1)
+        } else {
+            (pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff)
+        };
+        let lo = self.mem_read(pointer) as u16;
+        let hi = self.mem_read(hi_pointer) as u16;
+        self.bus.mark(pointer, Usage::Data);
+        self.bus.mark(hi_pointer, Usage::Data);
+        self.program_counter = (hi << 8) | lo;
+        self.bus.mark(self.program_counter, Usage::IndirectCode);
+    }
+
+    fn plp(&mut self) {
+        self.status = (self.stack_pop() & !BREAK_FLAG) | BREAK2_FLAG;
+    }
+
+    fn tax(&mut self) {
+        self.register_x = self.register_a;
+        self.update_zero_flag(self.register_x);
+        self.update_negative_flag(self.register_x);
+    }
+
+    fn tay(&mut self) {
+        self.register_y = self.register_a;
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn txa(&mut self) {
+        self.register_a = self.register_x;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn tya(&mut self) {
+        self.register_a = self.register_y;
+        self.update_zero_and_negative_flags(self.register_a);
+    }
+
+    fn inx(&mut self) {
+        self.register_x = self.register_x.wrapping_add(1);
+        self.update_zero_flag(self.register_x);
+        self.update_negative_flag(self.register_x);
+    }
+
+    fn iny(&mut self) {
+        self.register_y = self.register_y.wrapping_add(1);
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn dex(&mut self) {
+        self.register_x = self.register_x.wrapping_sub(1);
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn dey(&mut self) {
+        self.register_y = self.register_y.wrapping_sub(1);
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn set_flag(&mut self, flag: u8, value: bool) {
+        if value {
+            self.status |= flag;
+        } else {
+            self.status &= !flag;
+        }
+    }
+
+    fn update_zero_and_negative_flags(&mut self, value: u8) {
+        self.update_zero_flag(value);
+        self.update_negative_flag(value);
+    }
+
+    fn update_zero_flag(&mut self, value: u8) {
+        //check if register a is 0 and if it is we set zero flag to 1 else we set it to 0
+        if value == 0 {
+            self.status |= 0b0000_0010;
+        } else {
+            self.status &= 0b1111_1101;
+        }
+    }
+
+    fn update_negative_flag(&mut self, value: u8) {
+        //check if the negative bit of register a is set if it is we set the negative bit of the status
+        if value & 0b1000_0000 != 0 {
+            self.status |= 0b1000_0000;
+        } else {
+            self.status &= 0b0111_1111;
+        }
+    }
+
+    /// Returns the effective address and whether indexing crossed a page boundary.
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> (u16, bool) {
+        match mode {
+            AddressingMode::Immediate => (self.program_counter, false),
+            AddressingMode::ZeroPage => (self.mem_read(self.program_counter) as u16, false),
+            AddressingMode::ZeroPage_X => {
+                let pos = self.mem_read(self.program_counter);
+                (pos.wrapping_add(self.register_x) as u16, false)
+            }
+            AddressingMode::ZeroPage_Y => {
+                let pos = self.mem_read(self.program_counter);
+                (pos.wrapping_add(self.register_y) as u16, false)
+            }
+            AddressingMode::Absolute => (self.mem_read_u16(self.program_counter), false),
+            AddressingMode::Absolute_X => {
+                let base = self.mem_read_u16(self.program_counter);
+                let addr = base.wrapping_add(self.register_x as u16);
+                (addr, page_crossed(base, addr))
+            }
+            AddressingMode::Absolute_Y => {
+                let base = self.mem_read_u16(self.program_counter);
+                let addr = base.wrapping_add(self.register_y as u16);
+                (addr, page_crossed(base, addr))
+            }
+            AddressingMode::Indirect_X => {
+                let base = self.mem_read(self.program_counter);
+                //the pointer stays u8 so that it wraps around inside the zero page
+                let pointer: u8 = base.wrapping_add(self.register_x);
+                let lo = self.mem_read(pointer as u16);
+                let hi = self.mem_read(pointer.wrapping_add(1) as u16);
+                ((hi as u16) << 8 | (lo as u16), false)
+            }
+            AddressingMode::Indirect_Y => {
+                let pointer = self.mem_read(self.program_counter);
+                let lo = self.mem_read(pointer as u16);
+                let hi = self.mem_read(pointer.wrapping_add(1) as u16);
+                let base = (hi as u16) << 8 | (lo as u16);
+                let addr = base.wrapping_add(self.register_y as u16);
+                (addr, page_crossed(base, addr))
+            }
+            AddressingMode::ZeroPage_Indirect => {
+                let pointer = self.mem_read(self.program_counter);
+                let lo = self.mem_read(pointer as u16);
+                let hi = self.mem_read(pointer.wrapping_add(1) as u16);
+                ((hi as u16) << 8 | (lo as u16), false)
+            }
+            AddressingMode::NoneAddressing => {
+                panic!("mode {:?} is not supported", mode);
+            }
+        }
+    }
+}
+
+fn page_crossed(base: u16, addr: u16) -> bool {
+    base & 0xff00 != addr & 0xff00
+}
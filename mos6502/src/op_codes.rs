@@ -1,4 +1,4 @@
-use crate::cpu::AddressingMode;
+use crate::{AddressingMode, Variant};
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 
@@ -79,6 +79,10 @@ lazy_static! {
         OpCode::new(0xc1, "CMP", 2, 6, AddressingMode::Indirect_X),
         OpCode::new(0xd1, "CMP", 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
 
+        OpCode::new(0xe0, "CPX", 2, 2, AddressingMode::Immediate),
+        OpCode::new(0xe4, "CPX", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0xec, "CPX", 3, 4, AddressingMode::Absolute),
+
         OpCode::new(0xc0, "CPY", 2, 2, AddressingMode::Immediate),
         OpCode::new(0xc4, "CPY", 2, 3, AddressingMode::ZeroPage),
         OpCode::new(0xcc, "CPY", 3, 4, AddressingMode::Absolute),
@@ -215,6 +219,62 @@ lazy_static! {
 
     ];
 
+    //instructions the 65C02 adds and the ones that take a different number of cycles on it
+    pub static ref CMOS_OPS_CODES: Vec<OpCode> = {
+        let mut codes = vec![
+            OpCode::new(0x72, "ADC", 2, 5, AddressingMode::ZeroPage_Indirect),
+            OpCode::new(0x32, "AND", 2, 5, AddressingMode::ZeroPage_Indirect),
+            OpCode::new(0xd2, "CMP", 2, 5, AddressingMode::ZeroPage_Indirect),
+            OpCode::new(0x52, "EOR", 2, 5, AddressingMode::ZeroPage_Indirect),
+            OpCode::new(0xb2, "LDA", 2, 5, AddressingMode::ZeroPage_Indirect),
+            OpCode::new(0x12, "ORA", 2, 5, AddressingMode::ZeroPage_Indirect),
+            OpCode::new(0xf2, "SBC", 2, 5, AddressingMode::ZeroPage_Indirect),
+            OpCode::new(0x92, "STA", 2, 5, AddressingMode::ZeroPage_Indirect),
+
+            OpCode::new(0x1e, "ASL", 3, 6 /* +1 if page crossed */, AddressingMode::Absolute_X),
+            OpCode::new(0x5e, "LSR", 3, 6 /* +1 if page crossed */, AddressingMode::Absolute_X),
+            OpCode::new(0x3e, "ROL", 3, 6 /* +1 if page crossed */, AddressingMode::Absolute_X),
+            OpCode::new(0x7e, "ROR", 3, 6 /* +1 if page crossed */, AddressingMode::Absolute_X),
+
+            OpCode::new(0x89, "BIT", 2, 2, AddressingMode::Immediate),
+            OpCode::new(0x34, "BIT", 2, 4, AddressingMode::ZeroPage_X),
+            OpCode::new(0x3c, "BIT", 3, 4 /* +1 if page crossed */, AddressingMode::Absolute_X),
+
+            OpCode::new(0x80, "BRA", 2, 2 /*(+1 as the branch always succeeds +1 if to a new page)*/, AddressingMode::NoneAddressing),
+
+            OpCode::new(0x1a, "INC", 1, 2, AddressingMode::NoneAddressing),
+            OpCode::new(0x3a, "DEC", 1, 2, AddressingMode::NoneAddressing),
+
+            OpCode::new(0x6c, "JMP", 3, 6, AddressingMode::NoneAddressing), //without the page bug
+            OpCode::new(0x7c, "JMP", 3, 6, AddressingMode::NoneAddressing), //AddressingMode:(Absolute,X)
+
+            OpCode::new(0xda, "PHX", 1, 3, AddressingMode::NoneAddressing),
+            OpCode::new(0x5a, "PHY", 1, 3, AddressingMode::NoneAddressing),
+            OpCode::new(0xfa, "PLX", 1, 4, AddressingMode::NoneAddressing),
+            OpCode::new(0x7a, "PLY", 1, 4, AddressingMode::NoneAddressing),
+
+            OpCode::new(0x64, "STZ", 2, 3, AddressingMode::ZeroPage),
+            OpCode::new(0x74, "STZ", 2, 4, AddressingMode::ZeroPage_X),
+            OpCode::new(0x9c, "STZ", 3, 4, AddressingMode::Absolute),
+            OpCode::new(0x9e, "STZ", 3, 5, AddressingMode::Absolute_X),
+
+            OpCode::new(0x14, "TRB", 2, 5, AddressingMode::ZeroPage),
+            OpCode::new(0x1c, "TRB", 3, 6, AddressingMode::Absolute),
+            OpCode::new(0x04, "TSB", 2, 5, AddressingMode::ZeroPage),
+            OpCode::new(0x0c, "TSB", 3, 6, AddressingMode::Absolute),
+        ];
+        //Rockwell bit instructions, the bit number is in the high nibble of the opcode
+        for bit in 0..8 {
+            let code = (bit as u8) << 4;
+            codes.push(OpCode::new(code | 0x07, RMB[bit], 2, 5, AddressingMode::ZeroPage));
+            codes.push(OpCode::new(code | 0x87, SMB[bit], 2, 5, AddressingMode::ZeroPage));
+            //zero page address then the branch offset
+            codes.push(OpCode::new(code | 0x0f, BBR[bit], 3, 5 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing));
+            codes.push(OpCode::new(code | 0x8f, BBS[bit], 3, 5 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::NoneAddressing));
+        }
+        codes
+    };
+
     pub static ref OPCODES_MAP : HashMap<u8, &'static OpCode>= {
         let mut map = HashMap::new();
         for code in &*CPU_OPS_CODES {
@@ -222,4 +282,33 @@ lazy_static! {
         }
         map
     };
+
+    pub static ref CMOS_OPCODES_MAP : HashMap<u8, &'static OpCode>= {
+        let mut map = OPCODES_MAP.clone();
+        for code in &*CMOS_OPS_CODES {
+            map.insert(code.code, code);
+        }
+        map
+    };
+}
+
+const RMB: [&str; 8] = [
+    "RMB0", "RMB1", "RMB2", "RMB3", "RMB4", "RMB5", "RMB6", "RMB7",
+];
+const SMB: [&str; 8] = [
+    "SMB0", "SMB1", "SMB2", "SMB3", "SMB4", "SMB5", "SMB6", "SMB7",
+];
+const BBR: [&str; 8] = [
+    "BBR0", "BBR1", "BBR2", "BBR3", "BBR4", "BBR5", "BBR6", "BBR7",
+];
+const BBS: [&str; 8] = [
+    "BBS0", "BBS1", "BBS2", "BBS3", "BBS4", "BBS5", "BBS6", "BBS7",
+];
+
+/// The instructions `variant` has.
+pub fn opcodes(variant: Variant) -> &'static HashMap<u8, &'static OpCode> {
+    match variant {
+        Variant::Ricoh2A03 | Variant::Nmos6502 => &OPCODES_MAP,
+        Variant::Cmos65C02 => &CMOS_OPCODES_MAP,
+    }
 }
@@ -0,0 +1,537 @@
+use crate::*;
+
+fn lda_status_flags(cpu: CPU<FlatMemory>) {
+    assert_eq!(cpu.status & 0b0000_0010, 0b00);
+    assert_eq!(cpu.status & 0b1000_0000, 0);
+}
+
+#[test]
+fn test_0xa9_lda_immediate_load_data() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xa9, 0x05, 0x00]);
+    assert_eq!(cpu.register_a, 0x05);
+    lda_status_flags(cpu);
+}
+
+#[test]
+fn test_0xa5_lda_zero_page() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0xc1, 0x01);
+
+    cpu.load_and_run(vec![0xa5, 0xc1, 0x00]);
+    assert_eq!(cpu.register_a, 0x01);
+    lda_status_flags(cpu);
+}
+
+#[test]
+fn test_0xb5_lda_zero_page_x() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0xc2, 0x01);
+
+    cpu.load_and_run(vec![0xe8, 0xb5, 0xc1, 0x00]);
+
+    assert_eq!(cpu.register_a, 0x01);
+    lda_status_flags(cpu);
+}
+
+#[test]
+fn test_0xad_lda_absolute() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0xc1c2, 0x01);
+
+    cpu.load_and_run(vec![0xad, 0xc2, 0xc1, 0x00]);
+    assert_eq!(cpu.register_a, 0x01);
+    lda_status_flags(cpu);
+}
+
+#[test]
+fn test_0xbd_lda_absolute_x() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0xc1c4, 0x01);
+
+    cpu.load_and_run(vec![0xe8, 0xe8, 0xbd, 0xc2, 0xc1, 0x00]);
+    assert_eq!(cpu.register_a, 0x01);
+    lda_status_flags(cpu);
+}
+
+#[test]
+fn test_0xb9_lda_absolute_y() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0xc1c5, 0x01);
+
+    cpu.load_and_run(vec![0xa0, 0x03, 0xb9, 0xc2, 0xc1, 0x00]);
+    assert_eq!(cpu.register_a, 0x01);
+    lda_status_flags(cpu);
+}
+
+#[test]
+fn test_0xa1_lda_indirect_x() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0x06, 0x01);
+    cpu.mem_write(0x01, 0x03);
+
+    cpu.load_and_run(vec![0xe8, 0xe8, 0xa1, 0x04, 0x00]);
+    assert_eq!(cpu.register_a, 0x03);
+    lda_status_flags(cpu);
+}
+
+#[test]
+fn test_0xb1_lda_indirect_y() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0x04, 0xcc);
+    cpu.mem_write(0x05, 0x00);
+    cpu.mem_write(0xcf, 0x7f);
+
+    cpu.load_and_run(vec![0xa0, 0x03, 0xb1, 0x04, 0x00]);
+    assert_eq!(cpu.register_a, 0x7f);
+    lda_status_flags(cpu);
+}
+
+#[test]
+fn test_0xa9_lda_zero_flag() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
+    assert_eq!(cpu.status & 0b0000_0010, 0b10);
+}
+
+#[test]
+fn test_0xaa_tax_move_a_to_x() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xa9, 0x0a, 0xaa, 0x00]);
+    assert_eq!(cpu.register_x, 10)
+}
+
+#[test]
+fn test_0xaa_txa_zero_flag() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
+    assert_eq!(cpu.status & 0b0000_0010, 0b10);
+}
+
+#[test]
+fn test_lda_txa_inx() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+    assert_eq!(cpu.register_x, 0xc1)
+}
+
+#[test]
+fn test_inx_overflow() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xa9, 0xff, 0xaa, 0xe8, 0xe8, 0x00]);
+    assert_eq!(cpu.register_x, 1)
+}
+
+#[test]
+fn test_inx() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xe8]);
+    assert_eq!(cpu.register_x, 0x01);
+}
+
+#[test]
+fn test_sta_ldx_ldy() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xa9, 0x42, 0x85, 0x10, 0xa6, 0x10, 0xa4, 0x10, 0x00]);
+    assert_eq!(cpu.mem_read(0x10), 0x42);
+    assert_eq!(cpu.register_x, 0x42);
+    assert_eq!(cpu.register_y, 0x42);
+}
+
+#[test]
+fn test_adc_carry_and_overflow() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xa9, 0x50, 0x69, 0x50, 0x00]);
+    assert_eq!(cpu.register_a, 0xa0);
+    assert_eq!(cpu.status & OVERFLOW_FLAG, OVERFLOW_FLAG);
+    assert_eq!(cpu.status & CARRY_FLAG, 0);
+
+    cpu.load_and_run(vec![0xa9, 0xff, 0x69, 0x02, 0x00]);
+    assert_eq!(cpu.register_a, 0x01);
+    assert_eq!(cpu.status & CARRY_FLAG, CARRY_FLAG);
+}
+
+#[test]
+fn test_sbc_borrow() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0x38, 0xa9, 0x05, 0xe9, 0x06, 0x00]);
+    assert_eq!(cpu.register_a, 0xff);
+    assert_eq!(cpu.status & CARRY_FLAG, 0);
+    assert_eq!(cpu.status & NEGATIVE_FLAG, NEGATIVE_FLAG);
+}
+
+#[test]
+fn test_compare_and_branch_loop() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    //LDX #0; loop: INX; CPX #5; BNE loop
+    cpu.load_and_run(vec![0xa2, 0x00, 0xe8, 0xe0, 0x05, 0xd0, 0xfb, 0x00]);
+    assert_eq!(cpu.register_x, 5);
+    assert_eq!(cpu.status & ZERO_FLAG, ZERO_FLAG);
+    assert_eq!(cpu.status & CARRY_FLAG, CARRY_FLAG);
+}
+
+#[test]
+fn test_jsr_rts() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    //JSR $8006; LDX #1; BRK; sub: LDA #2; RTS
+    cpu.load_and_run(vec![0x20, 0x06, 0x80, 0xa2, 0x01, 0x00, 0xa9, 0x02, 0x60]);
+    assert_eq!(cpu.register_a, 2);
+    assert_eq!(cpu.register_x, 1);
+    assert_eq!(cpu.stack_pointer, 0xfd);
+}
+
+#[test]
+fn test_stack_push_pull() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    //LDA #$80; PHA; LDA #0; PLA
+    cpu.load_and_run(vec![0xa9, 0x80, 0x48, 0xa9, 0x00, 0x68, 0x00]);
+    assert_eq!(cpu.register_a, 0x80);
+    assert_eq!(cpu.status & NEGATIVE_FLAG, NEGATIVE_FLAG);
+    assert_eq!(cpu.mem_read(0x01fd), 0x80);
+}
+
+#[test]
+fn test_shifts_and_rotates() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    //LDA #$81; ASL A; ROL A; LSR A; ROR A
+    cpu.load_and_run(vec![0xa9, 0x81, 0x0a, 0x2a, 0x4a, 0x6a, 0x00]);
+    assert_eq!(cpu.register_a, 0x81);
+    assert_eq!(cpu.status & CARRY_FLAG, 0);
+}
+
+#[test]
+fn test_inc_dec_memory() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0x20, 0xff);
+    cpu.load_and_run(vec![0xe6, 0x20, 0xc6, 0x21, 0x00]);
+    assert_eq!(cpu.mem_read(0x20), 0x00);
+    assert_eq!(cpu.mem_read(0x21), 0xff);
+    assert_eq!(cpu.status & NEGATIVE_FLAG, NEGATIVE_FLAG);
+}
+
+#[test]
+fn test_jmp_indirect_page_bug() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0x02ff, 0x00);
+    cpu.mem_write(0x0200, 0x90);
+    cpu.mem_write(0x0300, 0x80);
+    cpu.mem_write(0x9000, 0xe8);
+    cpu.load_and_run(vec![0x6c, 0xff, 0x02]);
+    assert_eq!(cpu.register_x, 1);
+}
+
+#[test]
+fn test_bit() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.mem_write(0x10, 0xc0);
+    cpu.load_and_run(vec![0xa9, 0x01, 0x24, 0x10, 0x00]);
+    assert_eq!(cpu.status & ZERO_FLAG, ZERO_FLAG);
+    assert_eq!(cpu.status & NEGATIVE_FLAG, NEGATIVE_FLAG);
+    assert_eq!(cpu.status & OVERFLOW_FLAG, OVERFLOW_FLAG);
+}
+
+#[test]
+fn test_cycles_page_cross() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load(vec![0xa2, 0x01, 0xbd, 0xff, 0x00, 0xbd, 0x00, 0x01]);
+    cpu.reset();
+    assert_eq!(cpu.step(), Ok(2));
+    assert_eq!(cpu.step(), Ok(5));
+    assert_eq!(cpu.step(), Ok(4));
+    assert_eq!(cpu.cycles, 11);
+}
+
+#[test]
+fn test_brk_jumps_to_irq_vector() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load(vec![0x00]);
+    cpu.mem_write_u16(0xfffe, 0x9000);
+    cpu.reset();
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 0x9000);
+    assert_eq!(cpu.stack_pop() & BREAK_FLAG, BREAK_FLAG);
+    assert_eq!(cpu.stack_pop_u16(), 0x8002);
+}
+
+#[test]
+fn test_unknown_opcode_is_an_error() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load(vec![0xe8, 0x02]);
+    cpu.reset();
+    cpu.step().unwrap();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::UnknownOpcode {
+            opcode: 0x02,
+            address: 0x8001
+        })
+    );
+}
+
+#[test]
+fn test_opcode_without_an_implementation_is_an_error() {
+    //a table that decodes $02, which nothing executes, and has lost LDA #imm
+    let halt = op_codes::OpCode {
+        code: 0x02,
+        name: "KIL",
+        bytes: 1,
+        cycles: 2,
+        addressing_mode: AddressingMode::NoneAddressing,
+    };
+    let mut table = op_codes::opcodes(Variant::Ricoh2A03).clone();
+    table.insert(0x02, &halt);
+    table.remove(&0xa9);
+
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load(vec![0xe8, 0x02, 0xa9, 0x01]);
+    cpu.reset();
+    cpu.execute(&table).unwrap();
+    let error = Err(CpuError::UnknownOpcode {
+        opcode: 0x02,
+        address: 0x8001,
+    });
+    assert_eq!(cpu.execute(&table), error);
+    assert_eq!(cpu.program_counter, 0x8001);
+
+    cpu.program_counter = 0x8002;
+    assert_eq!(
+        cpu.execute(&table),
+        Err(CpuError::UnknownOpcode {
+            opcode: 0xa9,
+            address: 0x8002
+        })
+    );
+}
+
+#[test]
+fn test_decimal_adc() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.variant = Variant::Nmos6502;
+    //SED, CLC, LDA #$58, ADC #$46
+    cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x58, 0x69, 0x46, 0x00]);
+    assert_eq!(cpu.register_a, 0x04);
+    assert_eq!(cpu.status & CARRY_FLAG, CARRY_FLAG);
+
+    //99 + 1 wraps to 00 with Z from the binary sum and N from the unadjusted one
+    cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x00]);
+    assert_eq!(cpu.register_a, 0x00);
+    assert_eq!(
+        cpu.status & (CARRY_FLAG | ZERO_FLAG | OVERFLOW_FLAG | NEGATIVE_FLAG),
+        CARRY_FLAG | NEGATIVE_FLAG
+    );
+
+    //SED, SEC, LDA #$12, ADC #$34
+    cpu.load_and_run(vec![0xf8, 0x38, 0xa9, 0x12, 0x69, 0x34, 0x00]);
+    assert_eq!(cpu.register_a, 0x47);
+    assert_eq!(cpu.status & CARRY_FLAG, 0);
+}
+
+#[test]
+fn test_decimal_sbc() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.variant = Variant::Nmos6502;
+    //SED, SEC, LDA #$46, SBC #$12
+    cpu.load_and_run(vec![0xf8, 0x38, 0xa9, 0x46, 0xe9, 0x12, 0x00]);
+    assert_eq!(cpu.register_a, 0x34);
+    assert_eq!(cpu.status & CARRY_FLAG, CARRY_FLAG);
+
+    //SED, CLC, LDA #$32, SBC #$02 borrows one more
+    cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x32, 0xe9, 0x02, 0x00]);
+    assert_eq!(cpu.register_a, 0x29);
+
+    //SED, SEC, LDA #$12, SBC #$21 wraps to 91 with a borrow
+    cpu.load_and_run(vec![0xf8, 0x38, 0xa9, 0x12, 0xe9, 0x21, 0x00]);
+    assert_eq!(cpu.register_a, 0x91);
+    assert_eq!(cpu.status & (CARRY_FLAG | NEGATIVE_FLAG), NEGATIVE_FLAG);
+}
+
+#[test]
+fn test_2a03_ignores_decimal_flag() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x58, 0x69, 0x46, 0x00]);
+    assert_eq!(cpu.register_a, 0x9e);
+    assert_eq!(cpu.status & DECIMAL_MODE_FLAG, DECIMAL_MODE_FLAG);
+}
+
+fn cmos_cpu() -> CPU<FlatMemory> {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.variant = Variant::Cmos65C02;
+    cpu
+}
+
+#[test]
+fn test_cmos_stack_and_store_zero() {
+    let mut cpu = cmos_cpu();
+    //LDX #$12, LDY #$34, PHX, PHY, PLX, PLY, STZ $10, INC A, INC A, DEC A
+    cpu.mem_write(0x0010, 0xff);
+    cpu.load_and_run(vec![
+        0xa2, 0x12, 0xa0, 0x34, 0xda, 0x5a, 0xfa, 0x7a, 0x64, 0x10, 0x1a, 0x1a, 0x3a, 0x00,
+    ]);
+    assert_eq!((cpu.register_x, cpu.register_y), (0x34, 0x12));
+    assert_eq!(cpu.mem_read(0x0010), 0x00);
+    assert_eq!(cpu.register_a, 0x01);
+}
+
+#[test]
+fn test_cmos_bit_instructions() {
+    let mut cpu = cmos_cpu();
+    cpu.mem_write(0x0010, 0b1010_0000);
+    //LDA #$0f, TSB $10, LDA #$21, TRB $10, SMB0 $11, RMB7 $10, BIT #$00
+    cpu.load_and_run(vec![
+        0xa9, 0x0f, 0x04, 0x10, 0xa9, 0x21, 0x14, 0x10, 0x87, 0x11, 0x77, 0x10, 0x89, 0x00, 0x00,
+    ]);
+    assert_eq!(cpu.mem_read(0x0010), 0b0000_1110);
+    assert_eq!(cpu.mem_read(0x0011), 0b0000_0001);
+    //TRB found bit 5 set, BIT #imm only changes Z
+    assert_eq!(cpu.status & ZERO_FLAG, ZERO_FLAG);
+}
+
+#[test]
+fn test_cmos_branches() {
+    let mut cpu = cmos_cpu();
+    cpu.mem_write(0x0010, 0b0000_0100);
+    cpu.load_and_run(vec![
+        0x80, 0x02, //BRA +2
+        0xa9, 0x01, //LDA #$01, skipped
+        0x2f, 0x10, 0x02, //BBR2 $10,+2, not taken
+        0xaf, 0x10, 0x02, //BBS2 $10,+2
+        0xa9, 0x02, //LDA #$02, skipped
+        0xa2, 0x03, //LDX #$03
+        0x00,
+    ]);
+    assert_eq!((cpu.register_a, cpu.register_x), (0x00, 0x03));
+    assert_eq!(cpu.program_counter, 0x800e);
+}
+
+#[test]
+fn test_cmos_addressing() {
+    let mut cpu = cmos_cpu();
+    cpu.mem_write_u16(0x0010, 0x0300);
+    cpu.mem_write(0x0300, 0x42);
+    //JMP indirect fetches the high byte from the next page
+    cpu.mem_write_u16(0x02ff, 0x8010);
+    cpu.mem_write_u16(0x0304, 0x8020);
+    //LDA ($10), JMP ($02ff), ..., $8010: LDX #$04, JMP ($0300,X), ..., $8020: STA ($10)
+    let mut program = vec![0xb2, 0x10, 0x6c, 0xff, 0x02];
+    program.resize(0x10, 0x00);
+    program.extend([0xa2, 0x04, 0x7c, 0x00, 0x03]);
+    program.resize(0x20, 0x00);
+    program.extend([0xa9, 0x99, 0x92, 0x10, 0x00]);
+    cpu.load_and_run(program);
+    assert_eq!(cpu.mem_read(0x0300), 0x99);
+    assert_eq!(cpu.program_counter, 0x8024);
+}
+
+#[test]
+fn test_cmos_cycles() {
+    let mut cpu = cmos_cpu();
+    //LDX #$01, ASL $10ff,X, ASL $1000,X, SED, ADC #$01, JMP ($0000)
+    cpu.load(vec![
+        0xa2, 0x01, 0x1e, 0xff, 0x10, 0x1e, 0x00, 0x10, 0xf8, 0x69, 0x01, 0x6c, 0x00, 0x00,
+    ]);
+    cpu.reset();
+    assert_eq!(cpu.step(), Ok(2));
+    assert_eq!(cpu.step(), Ok(7));
+    assert_eq!(cpu.step(), Ok(6));
+    assert_eq!(cpu.step(), Ok(2));
+    assert_eq!(cpu.step(), Ok(3));
+    assert_eq!(cpu.step(), Ok(6));
+}
+
+#[test]
+fn test_cmos_decimal_flags_and_interrupts() {
+    let mut cpu = cmos_cpu();
+    //SED, CLC, LDA #$99, ADC #$01, BRK
+    cpu.mem_write_u16(0xfffe, 0x9000);
+    cpu.load(vec![0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x00]);
+    cpu.reset();
+    for _ in 0..4 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.register_a, 0x00);
+    assert_eq!(
+        cpu.status & (CARRY_FLAG | ZERO_FLAG | NEGATIVE_FLAG),
+        CARRY_FLAG | ZERO_FLAG
+    );
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 0x9000);
+    assert_eq!(cpu.status & DECIMAL_MODE_FLAG, 0);
+}
+
+#[test]
+fn test_cmos_bit_absolute_x_page_cross() {
+    let mut cpu = cmos_cpu();
+    //LDX #$20, BIT $10f0,X, BIT $1000,X
+    cpu.load(vec![0xa2, 0x20, 0x3c, 0xf0, 0x10, 0x3c, 0x00, 0x10]);
+    cpu.reset();
+    cpu.step().unwrap();
+    assert_eq!(cpu.step(), Ok(5));
+    assert_eq!(cpu.step(), Ok(4));
+}
+
+#[test]
+fn test_cmos_decimal_sbc_flags() {
+    //SED, SEC, LDA #$00, SBC #$21: the binary difference is $df, the BCD one $79
+    let program = vec![0xf8, 0x38, 0xa9, 0x00, 0xe9, 0x21];
+    let mut cpu = cmos_cpu();
+    cpu.load(program.clone());
+    cpu.reset();
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.step(), Ok(3));
+    assert_eq!(cpu.register_a, 0x79);
+    assert_eq!(cpu.status & (NEGATIVE_FLAG | ZERO_FLAG | CARRY_FLAG), 0);
+
+    //NMOS takes N from the binary difference
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.variant = Variant::Nmos6502;
+    cpu.load(program);
+    cpu.reset();
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.step(), Ok(2));
+    assert_eq!(cpu.register_a, 0x79);
+    assert_eq!(cpu.status & NEGATIVE_FLAG, NEGATIVE_FLAG);
+
+    //SEC, LDA #$50, SBC #$50 is zero either way
+    let mut cpu = cmos_cpu();
+    cpu.load(vec![0xf8, 0x38, 0xa9, 0x50, 0xe9, 0x50]);
+    cpu.reset();
+    for _ in 0..4 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.register_a, 0x00);
+    assert_eq!(
+        cpu.status & (NEGATIVE_FLAG | ZERO_FLAG | CARRY_FLAG),
+        ZERO_FLAG | CARRY_FLAG
+    );
+}
+
+#[test]
+fn test_nmos_has_no_cmos_instructions() {
+    let mut cpu = CPU::with_bus(FlatMemory::new());
+    cpu.variant = Variant::Nmos6502;
+    cpu.load(vec![0x80, 0x02]);
+    cpu.reset();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::UnknownOpcode {
+            opcode: 0x80,
+            address: 0x8000
+        })
+    );
+}
+
+#[test]
+fn test_disassemble_cmos() {
+    let memory = [0x0f, 0x10, 0xfd, 0x7c, 0x00, 0x03, 0xb2, 0x20, 0x1a];
+    let read = |addr: u16| memory.get(addr as usize).copied().unwrap_or(0);
+    let text = |addr| disasm::disassemble_as(Variant::Cmos65C02, read, addr, |_| None).text;
+    assert_eq!(text(0), "BBR0 $10,$0000");
+    assert_eq!(text(3), "JMP ($0300,X)");
+    assert_eq!(text(6), "LDA ($20)");
+    assert_eq!(text(8), "INC A");
+    assert_eq!(disasm::disassemble(read, 0).text, ".db $0f");
+}
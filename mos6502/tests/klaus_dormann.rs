@@ -0,0 +1,84 @@
+//! Klaus Dormann's 6502 functional and decimal tests, run on a flat 64 KiB bus.
+//!
+//! The binaries are not part of the repository. Assemble them from
+//! https://github.com/Klaus2m5/6502_65C02_functional_tests with the default
+//! configuration and put them in `tests/roms/` as `6502_functional_test.bin` (a full 64 KiB
+//! image) and `6502_decimal_test.bin` (loaded at $0200), then run the tests with
+//!
+//!     cargo test --release -p mos6502 --test klaus_dormann -- --ignored
+//!
+//! A functional test assembled with other options traps somewhere else, set
+//! `KLAUS_SUCCESS_ADDR` to the hex address of its success trap.
+
+use mos6502::{CpuError, FlatMemory, Variant, CPU};
+use std::path::PathBuf;
+
+const FUNCTIONAL_SUCCESS_ADDR: u16 = 0x3469;
+//test_case, the number of the test that is running
+const FUNCTIONAL_TEST_CASE: u16 = 0x0200;
+//ERROR, 0 when every result matched
+const DECIMAL_ERROR: u16 = 0x000b;
+//the decimal test takes about 65 million cycles, the functional test about 96 million
+const MAX_INSTRUCTIONS: u64 = 100_000_000;
+
+fn load(name: &str, load_addr: u16) -> CPU<FlatMemory> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/roms")
+        .join(name);
+    let binary = std::fs::read(&path)
+        .unwrap_or_else(|e| panic!("{}: {}, see the top of this file", path.display(), e));
+    let mut bus = FlatMemory::new();
+    let start = load_addr as usize;
+    assert!(start + binary.len() <= 0x10000, "{} does not fit", name);
+    bus.memory[start..start + binary.len()].copy_from_slice(&binary);
+    let mut cpu = CPU::with_bus(bus);
+    cpu.variant = Variant::Nmos6502;
+    cpu
+}
+
+//runs until the program jumps or branches to itself and returns where
+fn run_until_trap(cpu: &mut CPU<FlatMemory>) -> u16 {
+    for _ in 0..MAX_INSTRUCTIONS {
+        let pc = cpu.program_counter;
+        match cpu.step() {
+            Ok(_) if cpu.program_counter == pc => return pc,
+            Ok(_) => {}
+            //the decimal test ends with the 65C02's STP, which the NMOS core does not have
+            Err(CpuError::UnknownOpcode { opcode: 0xdb, .. }) => return pc,
+            Err(e) => panic!("{} after {} cycles", e, cpu.cycles),
+        }
+    }
+    panic!("no trap after {} instructions", MAX_INSTRUCTIONS);
+}
+
+#[test]
+#[ignore = "needs tests/roms/6502_functional_test.bin"]
+fn functional_test() {
+    let mut cpu = load("6502_functional_test.bin", 0x0000);
+    let success = std::env::var("KLAUS_SUCCESS_ADDR")
+        .ok()
+        .map(|addr| u16::from_str_radix(&addr, 16).expect("KLAUS_SUCCESS_ADDR is not hex"))
+        .unwrap_or(FUNCTIONAL_SUCCESS_ADDR);
+    cpu.program_counter = 0x0400;
+
+    let trap = run_until_trap(&mut cpu);
+    assert_eq!(
+        trap, success,
+        "trapped at ${:04x} in test ${:02x}",
+        trap, cpu.bus.memory[FUNCTIONAL_TEST_CASE as usize]
+    );
+}
+
+#[test]
+#[ignore = "needs tests/roms/6502_decimal_test.bin"]
+fn decimal_test() {
+    let mut cpu = load("6502_decimal_test.bin", 0x0200);
+    cpu.program_counter = 0x0200;
+
+    run_until_trap(&mut cpu);
+    assert_eq!(
+        cpu.bus.memory[DECIMAL_ERROR as usize], 0,
+        "wrong result for N1 ${:02x} N2 ${:02x}",
+        cpu.bus.memory[0x0000], cpu.bus.memory[0x0001]
+    );
+}
@@ -0,0 +1,305 @@
+pub mod dmc;
+pub mod noise;
+pub mod pulse;
+#[cfg(test)]
+mod tests;
+pub mod triangle;
+pub mod units;
+
+use crate::region::{Timing, NTSC};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use dmc::Dmc;
+use noise::Noise;
+use pulse::Pulse;
+use triangle::Triangle;
+
+pub const DEFAULT_SAMPLE_RATE: f64 = 44_100.0;
+
+pub struct Apu {
+    pub pulse1: Pulse,
+    pub pulse2: Pulse,
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    pub frame_irq: bool,
+    frame_cycle: u32,
+    odd_cycle: bool,
+    //frame counter steps, noise and DMC periods and the CPU clock of the region
+    timing: &'static Timing,
+
+    sample_rate: f64,
+    sample_timer: f64,
+    sample_sum: f32,
+    sample_count: u32,
+    //DC blocking high-pass filter state
+    filter_input: f32,
+    filter_output: f32,
+    samples: Vec<f32>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            odd_cycle: false,
+            timing: &NTSC,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_timer: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            filter_input: 0.0,
+            filter_output: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Output rate of `take_samples`, the frontend nudges it to keep its audio queue level.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    pub fn set_timing(&mut self, timing: &'static Timing) {
+        self.timing = timing;
+    }
+
+    /// Mono samples in the -1.0..1.0 range produced since the last call.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq_flag
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write_register(addr - 0x4000, data),
+            0x4004..=0x4007 => self.pulse2.write_register(addr - 0x4004, data),
+            0x4008..=0x400b => self.triangle.write_register(addr - 0x4008, data),
+            0x400c..=0x400f => self.noise.write_register(addr - 0x400c, data),
+            0x4010..=0x4013 => self.dmc.write_register(addr - 0x4010, data),
+            0x4015 => {
+                self.pulse1
+                    .length_counter
+                    .set_enabled(data & 0b0000_0001 != 0);
+                self.pulse2
+                    .length_counter
+                    .set_enabled(data & 0b0000_0010 != 0);
+                self.triangle
+                    .length_counter
+                    .set_enabled(data & 0b0000_0100 != 0);
+                self.noise
+                    .length_counter
+                    .set_enabled(data & 0b0000_1000 != 0);
+                self.dmc.set_enabled(data & 0b0001_0000 != 0);
+            }
+            0x4017 => {
+                self.five_step_mode = data & 0b1000_0000 != 0;
+                self.irq_inhibit = data & 0b0100_0000 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.pulse1.length_counter.active() {
+            status |= 0b0000_0001;
+        }
+        if self.pulse2.length_counter.active() {
+            status |= 0b0000_0010;
+        }
+        if self.triangle.length_counter.active() {
+            status |= 0b0000_0100;
+        }
+        if self.noise.length_counter.active() {
+            status |= 0b0000_1000;
+        }
+        if self.dmc.bytes_remaining > 0 {
+            status |= 0b0001_0000;
+        }
+        if self.frame_irq {
+            status |= 0b0100_0000;
+        }
+        if self.dmc.irq_flag {
+            status |= 0b1000_0000;
+        }
+        self.frame_irq = false;
+        status
+    }
+
+    /// Advances the APU by one CPU cycle.
+    pub fn tick(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer(&self.timing.noise_periods);
+        self.dmc.clock_timer(&self.timing.dmc_rates);
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.clock_frame_counter();
+        self.generate_sample();
+    }
+
+    //the last step of each mode wraps the sequence
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        let sequence = &self.timing.frame_sequence;
+        if self.five_step_mode {
+            match sequence.iter().position(|&c| c == self.frame_cycle) {
+                Some(0) | Some(2) => self.clock_quarter_frame(),
+                Some(1) => {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+                Some(4) => {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                    self.frame_cycle = 0;
+                }
+                _ => {}
+            }
+        } else {
+            match sequence[..4].iter().position(|&c| c == self.frame_cycle) {
+                Some(0) | Some(2) => self.clock_quarter_frame(),
+                Some(1) => {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+                Some(3) => {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                    if !self.irq_inhibit {
+                        self.frame_irq = true;
+                    }
+                    self.frame_cycle = 0;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear_counter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length_counter.clock();
+        self.pulse2.length_counter.clock();
+        self.triangle.length_counter.clock();
+        self.noise.length_counter.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    // https://wiki.nesdev.com/w/index.php/APU_Mixer
+    fn mix(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output_level as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+        pulse_out + tnd_out
+    }
+
+    //averages the mixer output over each sample period
+    fn generate_sample(&mut self) {
+        self.sample_sum += self.mix();
+        self.sample_count += 1;
+        self.sample_timer += self.sample_rate;
+        let clock_rate = self.timing.cpu_clock_rate;
+        if self.sample_timer < clock_rate {
+            return;
+        }
+        self.sample_timer -= clock_rate;
+
+        let input = self.sample_sum / self.sample_count as f32;
+        self.sample_sum = 0.0;
+        self.sample_count = 0;
+        self.filter_output = input - self.filter_input + 0.996 * self.filter_output;
+        self.filter_input = input;
+        self.samples.push(self.filter_output.clamp(-1.0, 1.0));
+    }
+}
+
+impl Snapshot for Apu {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.pulse1.save_state(w);
+        self.pulse2.save_state(w);
+        self.triangle.save_state(w);
+        self.noise.save_state(w);
+        self.dmc.save_state(w);
+        w.write_bool(self.five_step_mode);
+        w.write_bool(self.irq_inhibit);
+        w.write_bool(self.frame_irq);
+        w.write_u32(self.frame_cycle);
+        w.write_bool(self.odd_cycle);
+        w.write_f64(self.sample_timer);
+        w.write_f32(self.sample_sum);
+        w.write_u32(self.sample_count);
+        w.write_f32(self.filter_input);
+        w.write_f32(self.filter_output);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.pulse1.load_state(r)?;
+        self.pulse2.load_state(r)?;
+        self.triangle.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.dmc.load_state(r)?;
+        self.five_step_mode = r.read_bool()?;
+        self.irq_inhibit = r.read_bool()?;
+        self.frame_irq = r.read_bool()?;
+        self.frame_cycle = r.read_u32()?;
+        self.odd_cycle = r.read_bool()?;
+        self.sample_timer = r.read_f64()?;
+        self.sample_sum = r.read_f32()?;
+        self.sample_count = r.read_u32()?;
+        self.filter_input = r.read_f32()?;
+        self.filter_output = r.read_f32()?;
+        Ok(())
+    }
+}
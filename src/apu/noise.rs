@@ -0,0 +1,85 @@
+use crate::apu::units::{Envelope, LengthCounter};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub struct Noise {
+    mode: bool,
+    shift_register: u16,
+    pub period_index: u8,
+    timer: u16,
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            mode: false,
+            shift_register: 1,
+            period_index: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length_counter.halt = data & 0b0010_0000 != 0;
+                self.envelope.write(data);
+            }
+            1 => {}
+            2 => {
+                self.mode = data & 0b1000_0000 != 0;
+                self.period_index = data & 0b0000_1111;
+            }
+            _ => {
+                self.length_counter.load(data);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle, `periods` is in CPU cycles.
+    pub fn clock_timer(&mut self, periods: &[u16; 16]) {
+        if self.timer == 0 {
+            self.timer = periods[self.period_index as usize] - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active() || self.shift_register & 1 == 1 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Snapshot for Noise {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.mode);
+        w.write_u16(self.shift_register);
+        w.write_u8(self.period_index);
+        w.write_u16(self.timer);
+        self.envelope.save_state(w);
+        self.length_counter.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.mode = r.read_bool()?;
+        self.shift_register = r.read_u16()?;
+        self.period_index = r.read_u8()?;
+        self.timer = r.read_u16()?;
+        self.envelope.load_state(r)?;
+        self.length_counter.load_state(r)?;
+        Ok(())
+    }
+}
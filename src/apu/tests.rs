@@ -0,0 +1,55 @@
+use crate::apu::Apu;
+
+#[test]
+fn test_length_counter_status() {
+    let mut apu = Apu::new();
+    apu.write_register(0x4003, 0b0000_1000);
+    assert_eq!(apu.read_status() & 1, 0);
+
+    apu.write_register(0x4015, 0b0000_0001);
+    apu.write_register(0x4003, 0b0000_1000);
+    assert_eq!(apu.read_status() & 1, 1);
+
+    apu.write_register(0x4015, 0);
+    assert_eq!(apu.read_status() & 1, 0);
+}
+
+#[test]
+fn test_length_counter_expires() {
+    let mut apu = Apu::new();
+    apu.write_register(0x4015, 0b0000_0001);
+    //length index 3 is 2 half frames
+    apu.write_register(0x4003, 0b0001_1000);
+    for _ in 0..29829 {
+        apu.tick();
+    }
+    assert_eq!(apu.read_status() & 1, 0);
+}
+
+#[test]
+fn test_frame_irq() {
+    let mut apu = Apu::new();
+    for _ in 0..29829 {
+        apu.tick();
+    }
+    assert!(apu.irq());
+    assert_eq!(apu.read_status() & 0b0100_0000, 0b0100_0000);
+    assert!(!apu.irq());
+
+    apu.write_register(0x4017, 0b0100_0000);
+    for _ in 0..29829 {
+        apu.tick();
+    }
+    assert!(!apu.irq());
+}
+
+#[test]
+fn test_sample_rate() {
+    let mut apu = Apu::new();
+    for _ in 0..1_789_773 / 10 {
+        apu.tick();
+    }
+    let samples = apu.take_samples();
+    assert!((4409..=4411).contains(&samples.len()));
+    assert!(apu.take_samples().is_empty());
+}
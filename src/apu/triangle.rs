@@ -0,0 +1,91 @@
+use crate::apu::units::LengthCounter;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Default)]
+pub struct Triangle {
+    sequence: u8,
+    pub timer_period: u16,
+    timer: u16,
+    pub length_counter: LengthCounter,
+    linear_counter: u8,
+    linear_counter_period: u8,
+    linear_counter_reload: bool,
+}
+
+impl Triangle {
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                //the control flag doubles as the length counter halt
+                self.length_counter.halt = data & 0b1000_0000 != 0;
+                self.linear_counter_period = data & 0b0111_1111;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00ff) | (((data & 0b111) as u16) << 8);
+                self.length_counter.load(data);
+                self.linear_counter_reload = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length_counter.active() && self.linear_counter > 0 {
+                self.sequence = (self.sequence + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.length_counter.halt {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        //ultrasonic periods are silenced instead of aliasing
+        if self.timer_period < 2 {
+            return 7;
+        }
+        SEQUENCE[self.sequence as usize]
+    }
+}
+
+impl Snapshot for Triangle {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.sequence);
+        w.write_u16(self.timer_period);
+        w.write_u16(self.timer);
+        self.length_counter.save_state(w);
+        w.write_u8(self.linear_counter);
+        w.write_u8(self.linear_counter_period);
+        w.write_bool(self.linear_counter_reload);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.sequence = r.read_u8()?;
+        self.timer_period = r.read_u16()?;
+        self.timer = r.read_u16()?;
+        self.length_counter.load_state(r)?;
+        self.linear_counter = r.read_u8()?;
+        self.linear_counter_period = r.read_u8()?;
+        self.linear_counter_reload = r.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+//! Runs blargg's test ROMs and reads their results.
+//!
+//! The newer ROMs (instr_test-v5, ppu_vbl_nmi, apu_test, ...) report through PRG-RAM:
+//! $6001-$6003 hold the signature DE B0 61 once the test has started, $6000 is the status
+//! and the text the ROM prints is a null-terminated string from $6004. Status $80 means
+//! the test is running, $81 asks for the reset button to be pressed and anything else is
+//! the result, 0 for a pass.
+
+use crate::cartridge::Rom;
+use crate::nes::Nes;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+const STATUS: u16 = 0x6000;
+const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const TEXT: u16 = 0x6004;
+const RUNNING: u8 = 0x80;
+const RESET_REQUESTED: u8 = 0x81;
+//the ROMs want the reset button held for at least 100 ms
+const RESET_DELAY_FRAMES: u32 = 6;
+
+pub const DEFAULT_MAX_FRAMES: u32 = 60 * 60;
+
+/// How a test ROM finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    //the result code, the number of the test that failed for most ROMs
+    Failed(u8),
+    //still running, or it never wrote the signature, after the frame limit
+    TimedOut,
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    pub outcome: Outcome,
+    //what the ROM printed
+    pub text: String,
+    pub frames: u32,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.outcome {
+            Outcome::Passed => write!(f, "passed")?,
+            Outcome::Failed(code) => write!(f, "failed with code {}", code)?,
+            Outcome::TimedOut => write!(f, "timed out after {} frames", self.frames)?,
+            Outcome::Error(e) => write!(f, "error: {}", e)?,
+        }
+        let text = self.text.trim();
+        if !text.is_empty() {
+            write!(f, "\n{}", text)?;
+        }
+        Ok(())
+    }
+}
+
+/// The status byte and text, None until the ROM has written the signature.
+pub fn read_status(nes: &Nes) -> Option<(u8, String)> {
+    let bus = &nes.cpu.bus;
+    if (0..3).any(|i| bus.peek(STATUS + 1 + i) != SIGNATURE[i as usize]) {
+        return None;
+    }
+    let text = (TEXT..0x8000)
+        .map(|addr| bus.peek(addr))
+        .take_while(|&c| c != 0)
+        .map(|c| c as char)
+        .collect();
+    Some((bus.peek(STATUS), text))
+}
+
+/// Runs `nes` until the ROM reports a result or `max_frames` have passed.
+pub fn run(nes: &mut Nes, max_frames: u32) -> TestResult {
+    let mut reset_at = None;
+    for frame in 1..=max_frames {
+        if let Err(e) = nes.run_frame() {
+            return TestResult {
+                outcome: Outcome::Error(e.to_string()),
+                text: read_status(nes).map(|(_, text)| text).unwrap_or_default(),
+                frames: frame,
+            };
+        }
+        let (status, text) = match read_status(nes) {
+            Some(status) => status,
+            None => continue,
+        };
+        match status {
+            RUNNING => {}
+            RESET_REQUESTED => match reset_at {
+                None => reset_at = Some(frame + RESET_DELAY_FRAMES),
+                Some(at) if frame >= at => {
+                    nes.reset();
+                    reset_at = None;
+                }
+                Some(_) => {}
+            },
+            code => {
+                return TestResult {
+                    outcome: if code == 0 {
+                        Outcome::Passed
+                    } else {
+                        Outcome::Failed(code)
+                    },
+                    text,
+                    frames: frame,
+                }
+            }
+        }
+    }
+    TestResult {
+        outcome: Outcome::TimedOut,
+        text: read_status(nes).map(|(_, text)| text).unwrap_or_default(),
+        frames: max_frames,
+    }
+}
+
+/// Loads the ROM at `path` and runs it, without touching its save file.
+pub fn run_file(path: &Path, max_frames: u32) -> TestResult {
+    match Rom::from_file(path) {
+        Ok(rom) => run(&mut Nes::new(rom), max_frames),
+        Err(e) => TestResult {
+            outcome: Outcome::Error(e),
+            text: String::new(),
+            frames: 0,
+        },
+    }
+}
+
+/// The .nes files at `path`, searching directories recursively, sorted by path.
+pub fn find_roms(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            roms.extend(find_roms(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("nes"))
+        {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
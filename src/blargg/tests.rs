@@ -0,0 +1,93 @@
+use crate::blargg::*;
+use crate::test_util::test_nes;
+
+//writes the signature, `text` and then `status`, and loops
+fn report(text: &str, status: u8) -> Vec<u8> {
+    let mut program = Vec::new();
+    let mut store = |addr: u16, value: u8| {
+        program.extend([0xa9, value, 0x8d, addr as u8, (addr >> 8) as u8]);
+    };
+    store(0x6000, 0x80);
+    for (i, &byte) in [0xde, 0xb0, 0x61].iter().enumerate() {
+        store(0x6001 + i as u16, byte);
+    }
+    for (i, byte) in text.bytes().chain([0]).enumerate() {
+        store(0x6004 + i as u16, byte);
+    }
+    store(0x6000, status);
+    let addr = 0x8000 + program.len() as u16;
+    program.extend([0x4c, addr as u8, (addr >> 8) as u8]);
+    program
+}
+
+#[test]
+fn test_passed() {
+    let mut nes = test_nes(&report("instr_test\n\nPassed\n", 0));
+    assert_eq!(read_status(&nes), None);
+    let result = run(&mut nes, 10);
+    assert!(result.passed());
+    assert_eq!(result.text, "instr_test\n\nPassed\n");
+    assert_eq!(result.frames, 1);
+    assert_eq!(result.to_string(), "passed\ninstr_test\n\nPassed");
+}
+
+#[test]
+fn test_failed() {
+    let result = run(&mut test_nes(&report("BIT\nFailed #3\n", 3)), 10);
+    assert_eq!(result.outcome, Outcome::Failed(3));
+    assert_eq!(result.to_string(), "failed with code 3\nBIT\nFailed #3");
+}
+
+#[test]
+fn test_timed_out() {
+    let result = run(&mut test_nes(&report("", 0x80)), 5);
+    assert_eq!(result.outcome, Outcome::TimedOut);
+    assert_eq!(result.frames, 5);
+    //without the signature the status byte is not trusted
+    let result = run(&mut test_nes(&[0x4c, 0x00, 0x80]), 5);
+    assert_eq!(result.outcome, Outcome::TimedOut);
+}
+
+#[test]
+fn test_reset_requested() {
+    //counts runs at $6100, the first asks for a reset and the second passes
+    let mut program = vec![
+        0xee, 0x00, 0x61, //INC $6100
+        0xad, 0x00, 0x61, //LDA $6100
+        0xc9, 0x02, //CMP #$02
+        0xf0, 0x00, //BEQ over the first report
+    ];
+    let first = relocate(report("", 0x81), 0x8000 + program.len() as u16);
+    program[9] = first.len() as u8;
+    program.extend(first);
+    let second = relocate(report("Passed\n", 0), 0x8000 + program.len() as u16);
+    program.extend(second);
+
+    let mut nes = test_nes(&program);
+    let result = run(&mut nes, 30);
+    assert!(result.passed(), "{}", result);
+    assert_eq!(nes.cpu.bus.peek(0x6100), 2);
+    assert!(result.frames > 6);
+}
+
+//`report` assumes it runs from $8000, moves its closing JMP to `addr`
+fn relocate(mut program: Vec<u8>, addr: u16) -> Vec<u8> {
+    let len = program.len();
+    let target = addr + len as u16 - 3;
+    program[len - 2..].copy_from_slice(&[target as u8, (target >> 8) as u8]);
+    program
+}
+
+#[test]
+fn test_find_roms() {
+    let dir = std::env::temp_dir().join(format!("nes_emulator_{}_blargg", std::process::id()));
+    std::fs::create_dir_all(dir.join("rom_singles")).unwrap();
+    for name in ["b.nes", "rom_singles/a.NES", "readme.txt"] {
+        std::fs::write(dir.join(name), []).unwrap();
+    }
+    assert_eq!(
+        find_roms(&dir).unwrap(),
+        vec![dir.join("b.nes"), dir.join("rom_singles/a.NES")]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
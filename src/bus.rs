@@ -0,0 +1,426 @@
+use crate::apu::Apu;
+use crate::cartridge::save::SaveFile;
+use crate::cartridge::Rom;
+use crate::cdl::{CodeDataLog, PRG_DATA, PRG_PCM};
+use crate::cheats::CheatList;
+use crate::input::InputDevice;
+use crate::joypad::Joypad;
+use crate::ppu::NesPPU;
+use crate::profiler::AccessCounts;
+use crate::region::Region;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use std::io;
+
+//  _______________ $10000
+// | Cartridge     |  PRG-ROM at $8000, PRG-RAM at $6000,
+// |               |  plain memory when no cartridge is inserted
+// |_______________| $4020
+// | APU and I/O   |
+// |_______________| $4000
+// | PPU registers |  mirrored every 8 bytes
+// |_______________| $2000
+// | RAM           |  2 KiB mirrored 4 times
+// |_______________| $0000
+pub struct Bus {
+    cpu_vram: [u8; 2048],
+    memory: [u8; 0x10000],
+    //last value on the data bus, returned for bits no device drives
+    open_bus: u8,
+    //$4016 and $4017, standard controllers unless configured otherwise
+    pub ports: [InputDevice; 2],
+    pub ppu: NesPPU,
+    pub apu: Apu,
+    rom: Option<Rom>,
+    save_file: Option<SaveFile>,
+    //cycles the CPU is halted for OAM and DMC DMA
+    dma_stall: u16,
+    cycles: u64,
+    region: Region,
+    //PPU dots owed as a fraction of `Timing::cpu_cycles`, PAL runs 3.2 dots per CPU cycle
+    ppu_clock: u16,
+    //recorded for the debugger's watchpoints while enabled
+    access_log: Option<Vec<MemoryAccess>>,
+    //PRG half of the code/data log, the PPU keeps the CHR half
+    cdl: Option<CodeDataLog>,
+    //reads and writes per address for the profiler
+    access_counts: Option<Box<AccessCounts>>,
+    pub cheats: CheatList,
+    scheduling: Scheduling,
+}
+
+/// How the PPU and APU are kept in step with the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduling {
+    //every CPU access first runs the PPU and APU for one cycle, so register reads and
+    //writes land on the dot they happen on
+    Cycle,
+    //the PPU and APU catch up after each whole instruction, faster but accesses land early
+    CatchUp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Bus::new()
+    }
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus {
+            cpu_vram: [0; 2048],
+            memory: [0; 0x10000],
+            open_bus: 0,
+            ports: [
+                InputDevice::Joypad(Joypad::new()),
+                InputDevice::Joypad(Joypad::new()),
+            ],
+            ppu: NesPPU::new_empty_rom(),
+            apu: Apu::new(),
+            rom: None,
+            save_file: None,
+            dma_stall: 0,
+            cycles: 0,
+            region: Region::Ntsc,
+            ppu_clock: 0,
+            access_log: None,
+            cdl: None,
+            access_counts: None,
+            cheats: CheatList::new(),
+            scheduling: Scheduling::Cycle,
+        }
+    }
+
+    pub fn with_rom(rom: Rom) -> Self {
+        let mut bus = Bus::new();
+        bus.ppu = NesPPU::new(rom.chr_rom.clone(), rom.screen_mirroring);
+        bus.set_region(rom.region);
+        bus.rom = Some(rom);
+        bus
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Switches the PPU and APU to the timing of `region`.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu_clock = 0;
+        self.ppu.set_timing(region.timing());
+        self.apu.set_timing(region.timing());
+    }
+
+    pub fn scheduling(&self) -> Scheduling {
+        self.scheduling
+    }
+
+    /// Both modes run the same number of cycles per instruction, so they can be switched
+    /// between instructions and save states work with either.
+    pub fn set_scheduling(&mut self, scheduling: Scheduling) {
+        self.scheduling = scheduling;
+    }
+
+    pub fn set_input_device(&mut self, port: usize, device: InputDevice) {
+        self.ports[port] = device;
+    }
+
+    pub fn rom(&self) -> Option<&Rom> {
+        self.rom.as_ref()
+    }
+
+    pub fn rom_mut(&mut self) -> Option<&mut Rom> {
+        self.rom.as_mut()
+    }
+
+    /// Loads battery-backed memory from `save_file` and keeps it to flush changes back.
+    pub fn attach_save_file(&mut self, save_file: SaveFile) -> io::Result<()> {
+        if let Some(rom) = self.rom.as_mut() {
+            save_file.load(rom)?;
+        }
+        self.save_file = Some(save_file);
+        Ok(())
+    }
+
+    pub fn flush_save(&mut self) -> io::Result<()> {
+        match (self.save_file.as_mut(), self.rom.as_mut()) {
+            (Some(save_file), Some(rom)) => save_file.flush(rom),
+            _ => Ok(()),
+        }
+    }
+
+    /// Call once per frame so battery-backed memory gets written out periodically.
+    pub fn end_frame(&mut self) -> io::Result<()> {
+        match (self.save_file.as_mut(), self.rom.as_mut()) {
+            (Some(save_file), Some(rom)) => save_file.end_frame(rom),
+            _ => Ok(()),
+        }
+    }
+
+    /// Clears RAM and powers the PPU and APU back on, the cartridge keeps its memory.
+    pub fn power_cycle(&mut self) {
+        self.cpu_vram = [0; 2048];
+        self.open_bus = 0;
+        self.dma_stall = 0;
+        self.ppu_clock = 0;
+        self.ppu.power_cycle();
+        let sample_rate = self.apu.sample_rate();
+        self.apu = Apu::new();
+        self.apu.set_sample_rate(sample_rate);
+        self.apu.set_timing(self.region.timing());
+    }
+
+    /// Advances the PPU and APU by the given number of CPU cycles.
+    pub fn tick(&mut self, cycles: u16) {
+        for _ in 0..cycles {
+            self.apu.tick();
+            if let Some(addr) = self.apu.dmc.fetch_address() {
+                let data = self.mem_read(addr);
+                self.log_code_data(addr, PRG_PCM);
+                self.apu.dmc.fill_sample_buffer(data);
+                self.dma_stall += 4;
+            }
+        }
+        self.cycles += cycles as u64;
+        let timing = self.region.timing();
+        self.ppu_clock += cycles * timing.ppu_dots;
+        self.ppu.tick(self.ppu_clock / timing.cpu_cycles);
+        self.ppu_clock %= timing.cpu_cycles;
+    }
+
+    pub fn poll_nmi_status(&mut self) -> bool {
+        self.ppu.poll_nmi_interrupt()
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.apu.irq()
+    }
+
+    pub fn take_dma_stall(&mut self) -> u16 {
+        std::mem::replace(&mut self.dma_stall, 0)
+    }
+
+    /// Starts or stops recording every read and write made through `mem_read` and `mem_write`.
+    pub fn set_access_logging(&mut self, enabled: bool) {
+        self.access_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Accesses recorded since the last call.
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        self.access_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Starts or stops counting the reads and writes of every address.
+    pub fn set_access_counting(&mut self, enabled: bool) {
+        self.access_counts = if enabled { Some(Box::default()) } else { None };
+    }
+
+    /// The counts since counting started, which goes on from zero.
+    pub fn take_access_counts(&mut self) -> Option<Box<AccessCounts>> {
+        self.access_counts.as_mut().map(std::mem::take)
+    }
+
+    fn log_access(&mut self, addr: u16, value: u8, write: bool) {
+        if let Some(log) = self.access_log.as_mut() {
+            log.push(MemoryAccess { addr, value, write });
+        }
+        if let Some(counts) = self.access_counts.as_mut() {
+            counts.add(addr, write);
+        }
+    }
+
+    /// Starts logging which ROM bytes are code and data into `log`, or stops with None.
+    pub fn set_code_data_log(&mut self, log: Option<CodeDataLog>) {
+        match log {
+            Some(mut log) => {
+                self.ppu.set_chr_log(Some(std::mem::take(&mut log.chr)));
+                self.cdl = Some(log);
+            }
+            None => {
+                self.ppu.set_chr_log(None);
+                self.cdl = None;
+            }
+        }
+    }
+
+    /// The code/data log so far.
+    pub fn code_data_log(&self) -> Option<CodeDataLog> {
+        let mut log = self.cdl.clone()?;
+        log.chr = self.ppu.chr_log().unwrap_or_default().to_vec();
+        Some(log)
+    }
+
+    /// Marks the byte at `addr` in the code/data log if it is PRG ROM.
+    pub fn log_code_data(&mut self, addr: u16, flags: u8) {
+        if let (Some(offset), Some(cdl)) = (self.prg_rom_offset(addr), self.cdl.as_mut()) {
+            cdl.log_prg(offset, addr, flags);
+        }
+    }
+
+    /// Internal 2 KiB work RAM.
+    pub fn ram(&self) -> &[u8; 2048] {
+        &self.cpu_vram
+    }
+
+    /// Position in PRG ROM of the byte mapped at `addr`, so debug data can be kept per bank.
+    pub fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match (addr, self.rom.as_ref()) {
+            (0x8000..=0xffff, Some(rom)) => Some(rom.prg_rom_offset(addr - 0x8000)),
+            _ => None,
+        }
+    }
+
+    /// CPU address of a PRG ROM byte, the reverse of `prg_rom_offset`.
+    pub fn prg_rom_addr(&self, offset: usize) -> Option<u16> {
+        self.rom.as_ref().and_then(|rom| rom.prg_rom_addr(offset))
+    }
+
+    /// Reads memory without the side effects of I/O registers, which read as open bus.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.cpu_vram[(addr & 0x07ff) as usize],
+            0x2000..=0x401f => self.open_bus,
+            _ => match (addr, self.rom.as_ref()) {
+                (0x6000..=0x7fff, Some(rom)) if !rom.prg_ram.is_empty() => {
+                    rom.read_prg_ram(addr - 0x6000)
+                }
+                (0x8000..=0xffff, Some(rom)) => rom.read_prg_rom(addr - 0x8000),
+                (_, Some(_)) => self.open_bus,
+                (_, None) => self.memory[addr as usize],
+            },
+        }
+    }
+
+    pub fn mem_read(&mut self, addr: u16) -> u8 {
+        let data = match addr {
+            0x0000..=0x1fff => self.cpu_vram[(addr & 0x07ff) as usize],
+            0x2000..=0x3fff => match addr & 0x2007 {
+                0x2002 => self.ppu.read_status(),
+                0x2004 => self.ppu.read_oam_data(),
+                0x2007 => self.ppu.read_data(),
+                //write only registers
+                _ => self.open_bus,
+            },
+            0x4015 => (self.open_bus & 0b0010_0000) | self.apu.read_status(),
+            //input devices only drive the low bits
+            0x4016 => (self.open_bus & 0b1110_0000) | self.ports[0].read(&self.ppu.frame),
+            0x4017 => (self.open_bus & 0b1110_0000) | self.ports[1].read(&self.ppu.frame),
+            0x4000..=0x401f => self.open_bus,
+            _ => match (addr, self.rom.as_ref()) {
+                (0x6000..=0x7fff, Some(rom)) if !rom.prg_ram.is_empty() => {
+                    rom.read_prg_ram(addr - 0x6000)
+                }
+                (0x8000..=0xffff, Some(rom)) => rom.read_prg_rom(addr - 0x8000),
+                (_, Some(_)) => self.open_bus,
+                (_, None) => self.memory[addr as usize],
+            },
+        };
+        let data = self.cheats.apply_read(addr, data);
+        self.open_bus = data;
+        self.log_access(addr, data, false);
+        data
+    }
+
+    pub fn mem_write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
+        let data = self.cheats.apply_write(addr, data);
+        self.log_access(addr, data, true);
+        match addr {
+            0x0000..=0x1fff => self.cpu_vram[(addr & 0x07ff) as usize] = data,
+            0x2000..=0x3fff => {
+                self.ppu.latch_io(data);
+                match addr & 0x2007 {
+                    0x2000 => self.ppu.write_to_ctrl(data),
+                    0x2001 => self.ppu.write_to_mask(data),
+                    0x2003 => self.ppu.write_to_oam_addr(data),
+                    0x2004 => self.ppu.write_to_oam_data(data),
+                    0x2005 => self.ppu.write_to_scroll(data),
+                    0x2006 => self.ppu.write_to_ppu_addr(data),
+                    0x2007 => self.ppu.write_to_data(data),
+                    _ => {}
+                }
+            }
+            0x4014 => {
+                let mut page = [0u8; 256];
+                let base = (data as u16) << 8;
+                for (i, byte) in page.iter_mut().enumerate() {
+                    *byte = self.mem_read(base + i as u16);
+                    self.log_code_data(base + i as u16, PRG_DATA);
+                }
+                self.ppu.write_oam_dma(&page);
+                //one more cycle to align when the DMA starts on an odd cycle
+                self.dma_stall += 513 + (self.cycles % 2) as u16;
+            }
+            0x4016 => {
+                //strobe is shared by both ports
+                for port in self.ports.iter_mut() {
+                    port.write(data);
+                }
+            }
+            0x4000..=0x4017 => self.apu.write_register(addr, data),
+            0x4018..=0x401f => {}
+            _ => match (addr, self.rom.as_mut()) {
+                (0x6000..=0x7fff, Some(rom)) if !rom.prg_ram.is_empty() => {
+                    rom.write_prg_ram(addr - 0x6000, data)
+                }
+                (_, Some(_)) => {
+                    //PRG-ROM is read only
+                }
+                (_, None) => self.memory[addr as usize] = data,
+            },
+        }
+    }
+}
+
+impl Snapshot for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.cpu_vram);
+        w.write_u8(self.open_bus);
+        w.write_u16(self.dma_stall);
+        w.write_u64(self.cycles);
+        //without a cartridge the whole address space is plain memory
+        if self.rom.is_none() {
+            w.write_bytes(&self.memory);
+        }
+        w.write_u8(self.region.timing_bits());
+        w.write_u16(self.ppu_clock);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.read_into(&mut self.cpu_vram)?;
+        self.open_bus = r.read_u8()?;
+        self.dma_stall = r.read_u16()?;
+        self.cycles = r.read_u64()?;
+        if self.rom.is_none() {
+            r.read_into(&mut self.memory)?;
+        }
+        if r.minor_version() >= 1 {
+            let region = Region::from_timing_bits(r.read_u8()?);
+            if region.timing() != self.region.timing() {
+                return Err(format!(
+                    "Save state is for a {:?} console, this one runs {:?}",
+                    region, self.region
+                ));
+            }
+            self.ppu_clock = r.read_u16()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush_save() {
+            eprintln!("Failed to write save file: {}", e);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use crate::cartridge::database::Database;
+use crate::region::Region;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use std::path::Path;
+
+pub mod database;
+pub mod save;
+#[cfg(test)]
+mod tests;
+
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_PAGE_SIZE: usize = 16384;
+const CHR_ROM_PAGE_SIZE: usize = 8192;
+const PRG_RAM_PAGE_SIZE: usize = 8192;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mirroring {
+    Vertical,
+    Horizontal,
+    FourScreen,
+}
+
+pub struct Rom {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub screen_mirroring: Mirroring,
+    pub battery: bool,
+    pub region: Region,
+    pub prg_ram: Vec<u8>,
+    save_dirty: bool,
+}
+
+impl Rom {
+    pub fn new(raw: &[u8]) -> Result<Rom, String> {
+        if raw.len() < HEADER_SIZE || raw[0..4] != NES_TAG {
+            return Err("File is not in iNES file format".to_string());
+        }
+
+        let nes2 = raw[7] & 0b0000_1100 == 0b0000_1000;
+        let mut mapper = ((raw[7] & 0b1111_0000) | (raw[6] >> 4)) as u16;
+        let mut submapper = 0;
+        if nes2 {
+            mapper |= ((raw[8] & 0b0000_1111) as u16) << 8;
+            submapper = raw[8] >> 4;
+        }
+        check_mapper(mapper)?;
+        if raw[4] == 0 {
+            return Err("Header declares no PRG ROM".to_string());
+        }
+
+        let four_screen = raw[6] & 0b0000_1000 != 0;
+        let vertical_mirroring = raw[6] & 0b0000_0001 != 0;
+        let screen_mirroring = match (four_screen, vertical_mirroring) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+        let battery = raw[6] & 0b0000_0010 != 0;
+        //the iNES 1.0 TV system bit is rarely set right, those images are taken as NTSC
+        let region = if nes2 {
+            Region::from_timing_bits(raw[12])
+        } else {
+            Region::Ntsc
+        };
+
+        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
+        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+
+        let skip_trainer = raw[6] & 0b0000_0100 != 0;
+        let prg_rom_start = HEADER_SIZE + if skip_trainer { TRAINER_SIZE } else { 0 };
+        let chr_rom_start = prg_rom_start + prg_rom_size;
+
+        if raw.len() < chr_rom_start + chr_rom_size {
+            return Err("File is shorter than its header declares".to_string());
+        }
+
+        let prg_ram_size = if nes2 {
+            shift_size(raw[10] & 0b0000_1111) + shift_size(raw[10] >> 4)
+        } else {
+            //iNES 1.0 stores the size in 8 KiB units, 0 meaning 8 KiB for compatibility
+            raw[8].max(1) as usize * PRG_RAM_PAGE_SIZE
+        };
+
+        Ok(Rom {
+            prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
+            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+            mapper,
+            submapper,
+            screen_mirroring,
+            battery,
+            region,
+            prg_ram: vec![0; prg_ram_size],
+            save_dirty: false,
+        })
+    }
+
+    /// Loads an iNES file, fixing its header when the ROM database knows the game.
+    /// The database named by `NES_ROM_DATABASE` is checked before the embedded one.
+    pub fn from_file(path: &Path) -> Result<Rom, String> {
+        Rom::from_file_with_database(path, database::external())
+    }
+
+    /// Loads an iNES file like `from_file`, looking the game up in `database` first.
+    pub fn from_file_with_database(
+        path: &Path,
+        database: Option<&Database>,
+    ) -> Result<Rom, String> {
+        let raw = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut rom = Rom::new(&raw)?;
+        let game = database
+            .and_then(|database| database.find(&rom))
+            .or_else(|| database::embedded().find(&rom));
+        if let Some(game) = game {
+            let corrections = game.apply(&mut rom);
+            if !corrections.is_empty() {
+                eprintln!(
+                    "{}: header corrected from the ROM database ({}): {}",
+                    path.display(),
+                    game.name,
+                    corrections.join(", ")
+                );
+            }
+            check_mapper(rom.mapper).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        Ok(rom)
+    }
+
+    pub fn read_prg_ram(&self, addr: u16) -> u8 {
+        self.prg_ram[addr as usize % self.prg_ram.len()]
+    }
+
+    pub fn write_prg_ram(&mut self, addr: u16, data: u8) {
+        let len = self.prg_ram.len();
+        self.prg_ram[addr as usize % len] = data;
+        self.save_dirty |= self.battery;
+    }
+
+    pub fn read_prg_rom(&self, addr: u16) -> u8 {
+        self.prg_rom[self.prg_rom_offset(addr)]
+    }
+
+    /// Offset into `prg_rom` of the byte at $8000 + `addr`.
+    pub fn prg_rom_offset(&self, addr: u16) -> usize {
+        //NROM: a single 16 KiB bank is mirrored into both halves of $8000-$FFFF
+        addr as usize % self.prg_rom.len()
+    }
+
+    /// CPU address PRG ROM byte `offset` is mapped at, the upper mirror for 16 KiB images.
+    pub fn prg_rom_addr(&self, offset: usize) -> Option<u16> {
+        let window = self.prg_rom.len().min(0x8000);
+        if offset >= self.prg_rom.len() {
+            return None;
+        }
+        Some((0x10000 - window + offset % window) as u16)
+    }
+
+    /// CRC32 of PRG and CHR ROM without the header, the checksum ROM databases are keyed by.
+    pub fn crc32(&self) -> u32 {
+        let crc = self
+            .prg_rom
+            .iter()
+            .chain(&self.chr_rom)
+            .fold(!0u32, |crc, &byte| {
+                (0..8).fold(crc ^ byte as u32, |crc, _| {
+                    (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+                })
+            });
+        !crc
+    }
+
+    /// SHA-1 of PRG and CHR ROM, which the ROM database uses to confirm a CRC32 match.
+    pub fn sha1(&self) -> [u8; 20] {
+        let mut data = self.prg_rom.clone();
+        data.extend_from_slice(&self.chr_rom);
+        sha1(&data)
+    }
+
+    /// MD5 of PRG and CHR ROM, the checksum FCEUX stores in movies.
+    pub fn md5(&self) -> [u8; 16] {
+        let mut data = self.prg_rom.clone();
+        data.extend_from_slice(&self.chr_rom);
+        md5(&data)
+    }
+
+    /// True when the battery-backed memory changed since it was last saved.
+    pub fn save_dirty(&self) -> bool {
+        self.save_dirty
+    }
+
+    /// Call once the exported save data has been written.
+    pub fn mark_saved(&mut self) {
+        self.save_dirty = false;
+    }
+
+    /// Battery-backed memory as stored in a `.sav` file, the whole PRG-RAM.
+    /// Empty if the cartridge has no battery.
+    pub fn export_save_data(&self) -> Vec<u8> {
+        if !self.battery {
+            return Vec::new();
+        }
+        self.prg_ram.clone()
+    }
+
+    pub fn import_save_data(&mut self, data: &[u8]) -> Result<(), String> {
+        if !self.battery {
+            return Err("Cartridge has no battery-backed memory".to_string());
+        }
+        if data.len() != self.prg_ram.len() {
+            return Err(format!(
+                "Save data is {} bytes, expected {}",
+                data.len(),
+                self.prg_ram.len()
+            ));
+        }
+        self.prg_ram.copy_from_slice(data);
+        self.save_dirty = false;
+        Ok(())
+    }
+}
+
+//NROM has no mapper registers, the mapper number only guards against loading
+//a state made with another cartridge
+impl Snapshot for Rom {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.mapper);
+        w.write_bytes(&self.prg_ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let mapper = r.read_u16()?;
+        if mapper != self.mapper {
+            return Err(format!(
+                "Save state is for mapper {}, the cartridge uses mapper {}",
+                mapper, self.mapper
+            ));
+        }
+        r.read_into(&mut self.prg_ram)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xefcd_ab89,
+        0x98ba_dcfe,
+        0x1032_5476,
+        0xc3d2_e1f0,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (total, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *total = total.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    let shifts: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let constants: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+    let mut h: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_le_bytes());
+
+    for block in message.chunks(64) {
+        let mut m = [0u32; 16];
+        for (word, bytes) in m.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = h;
+        for i in 0..64 {
+            let (f, g) = match i {
+                0..=15 => ((b & c) | (!b & d), i),
+                16..=31 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                32..=47 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(constants[i])
+                .wrapping_add(m[g])
+                .rotate_left(shifts[i / 16 * 4 + i % 4]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (total, value) in h.iter_mut().zip([a, b, c, d]) {
+            *total = total.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (bytes, word) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+//anything else would run as NROM and misbehave
+fn check_mapper(mapper: u16) -> Result<(), String> {
+    if mapper != 0 {
+        return Err(format!(
+            "Mapper {} is not supported, only NROM (mapper 0) is",
+            mapper
+        ));
+    }
+    Ok(())
+}
+
+fn shift_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
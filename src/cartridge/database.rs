@@ -0,0 +1,238 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::region::Region;
+use lazy_static::lazy_static;
+use std::path::Path;
+
+//the NES 2.0 database export built into the emulator
+const EMBEDDED_DATABASE: &str = include_str!("nes20db.xml");
+
+/// Environment variable naming an nes20db.xml checked before the embedded database.
+pub const DATABASE_VAR: &str = "NES_ROM_DATABASE";
+
+lazy_static! {
+    static ref EMBEDDED: Database = Database::parse(EMBEDDED_DATABASE).unwrap_or_else(|e| {
+        eprintln!("Embedded ROM database is invalid: {}", e);
+        Database::default()
+    });
+    static ref EXTERNAL: Option<Database> = std::env::var_os(DATABASE_VAR).and_then(|path| {
+        Database::open(Path::new(&path))
+            .map_err(|e| eprintln!("{}: {}", DATABASE_VAR, e))
+            .ok()
+    });
+}
+
+/// The database compiled into the emulator.
+pub fn embedded() -> &'static Database {
+    &EMBEDDED
+}
+
+/// The database named by `NES_ROM_DATABASE`, if it is set and could be read.
+pub fn external() -> Option<&'static Database> {
+    EXTERNAL.as_ref()
+}
+
+/// The header a game's ROM should have.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Game {
+    //from the comment the database puts in every entry
+    pub name: String,
+    //of PRG and CHR ROM together
+    pub crc32: u32,
+    pub sha1: Option<[u8; 20]>,
+    pub mapper: u16,
+    pub submapper: u8,
+    //None when the mapper controls mirroring
+    pub mirroring: Option<Mirroring>,
+    pub battery: bool,
+    //volatile and battery-backed together
+    pub prg_ram_size: usize,
+    pub region: Option<Region>,
+}
+
+impl Game {
+    /// Fixes the parts of `rom`'s header that disagree with the database, returns what changed.
+    pub fn apply(&self, rom: &mut Rom) -> Vec<String> {
+        let mut corrections = Vec::new();
+        if rom.mapper != self.mapper || rom.submapper != self.submapper {
+            corrections.push(format!(
+                "mapper {}.{} -> {}.{}",
+                rom.mapper, rom.submapper, self.mapper, self.submapper
+            ));
+            rom.mapper = self.mapper;
+            rom.submapper = self.submapper;
+        }
+        if let Some(mirroring) = self.mirroring.filter(|&m| m != rom.screen_mirroring) {
+            corrections.push(format!(
+                "mirroring {:?} -> {:?}",
+                rom.screen_mirroring, mirroring
+            ));
+            rom.screen_mirroring = mirroring;
+        }
+        if rom.battery != self.battery {
+            corrections.push(format!("battery {} -> {}", rom.battery, self.battery));
+            rom.battery = self.battery;
+        }
+        if rom.prg_ram.len() != self.prg_ram_size {
+            corrections.push(format!(
+                "PRG-RAM {} -> {} bytes",
+                rom.prg_ram.len(),
+                self.prg_ram_size
+            ));
+            rom.prg_ram = vec![0; self.prg_ram_size];
+        }
+        if let Some(region) = self.region.filter(|&r| r != rom.region) {
+            corrections.push(format!("region {:?} -> {:?}", rom.region, region));
+            rom.region = region;
+        }
+        corrections
+    }
+}
+
+/// Known good headers in the XML format of the NES 2.0 database (nes20db.xml).
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    games: Vec<Game>,
+}
+
+impl Database {
+    /// Reads the `<game>` entries. Only the elements needed to fix headers are used: `rom`,
+    /// `pcb`, `prgram`, `prgnvram` and `console`. CHR-RAM sizes are ignored, the PPU
+    /// always has the 8 KiB a cartridge without CHR ROM can address.
+    pub fn parse(xml: &str) -> Result<Database, String> {
+        let mut games = Vec::new();
+        let mut game: Option<Game> = None;
+        let mut has_rom = false;
+        let mut rest = xml;
+        let mut line = 1;
+        while let Some(start) = rest.find('<') {
+            //counted as the text is consumed, full exports have tens of thousands of lines
+            line += rest[..start].matches('\n').count();
+            let error = move |e: &str| format!("line {}: {}", line, e);
+            rest = &rest[start..];
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                let end = comment
+                    .find("-->")
+                    .ok_or_else(|| error("unterminated comment"))?;
+                if let Some(game) = game.as_mut().filter(|game| game.name.is_empty()) {
+                    game.name = comment[..end].trim().to_string();
+                }
+                line += comment[..end].matches('\n').count();
+                rest = &comment[end + 3..];
+                continue;
+            }
+            let end = rest.find('>').ok_or_else(|| error("unterminated tag"))?;
+            let tag = rest[1..end].trim_end_matches('/');
+            line += tag.matches('\n').count();
+            rest = &rest[end + 1..];
+            let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+            let attribute = |key: &str| attribute(attributes, key);
+            let number = |key: &str| match attribute(key) {
+                Some(value) => value
+                    .parse::<usize>()
+                    .map(Some)
+                    .map_err(|_| error(&format!("invalid {} {}", key, value))),
+                None => Ok(None),
+            };
+
+            match (name, game.as_mut()) {
+                ("game", None) => {
+                    game = Some(Game::default());
+                    has_rom = false;
+                }
+                ("game", Some(_)) => return Err(error("nested game")),
+                ("/game", Some(_)) => {
+                    if !has_rom {
+                        return Err(error("game without a rom element"));
+                    }
+                    games.extend(game.take());
+                }
+                ("/game", None) => return Err(error("unexpected </game>")),
+                ("rom", Some(game)) => {
+                    let crc32 = attribute("crc32").ok_or_else(|| error("rom without crc32"))?;
+                    game.crc32 = u32::from_str_radix(crc32, 16)
+                        .map_err(|_| error(&format!("invalid crc32 {}", crc32)))?;
+                    game.sha1 = match attribute("sha1") {
+                        Some(sha1) => Some(
+                            parse_sha1(sha1)
+                                .ok_or_else(|| error(&format!("invalid sha1 {}", sha1)))?,
+                        ),
+                        None => None,
+                    };
+                    has_rom = true;
+                }
+                ("pcb", Some(game)) => {
+                    game.mapper = number("mapper")?.unwrap_or(0) as u16;
+                    game.submapper = number("submapper")?.unwrap_or(0) as u8;
+                    game.battery = number("battery")?.unwrap_or(0) != 0;
+                    game.mirroring = match attribute("mirroring") {
+                        Some("H") => Some(Mirroring::Horizontal),
+                        Some("V") => Some(Mirroring::Vertical),
+                        Some("4") => Some(Mirroring::FourScreen),
+                        _ => None,
+                    };
+                }
+                ("prgram" | "prgnvram", Some(game)) => {
+                    game.prg_ram_size += number("size")?.unwrap_or(0);
+                }
+                ("console", Some(game)) => {
+                    game.region = number("region")?.map(|r| Region::from_timing_bits(r as u8));
+                }
+                _ => {}
+            }
+        }
+        if game.is_some() {
+            return Err("unterminated game".to_string());
+        }
+        Ok(Database { games })
+    }
+
+    /// Reads a database file, usually a full nes20db.xml export.
+    pub fn open(path: &Path) -> Result<Database, String> {
+        let xml =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Database::parse(&xml).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+
+    /// The entry for `rom`, matched by CRC32 and confirmed with SHA-1 when the entry has one.
+    pub fn find(&self, rom: &Rom) -> Option<&Game> {
+        let crc32 = rom.crc32();
+        let mut sha1 = None;
+        self.games
+            .iter()
+            .filter(|game| game.crc32 == crc32)
+            .find(|game| {
+                game.sha1
+                    .is_none_or(|expected| *sha1.get_or_insert_with(|| rom.sha1()) == expected)
+            })
+    }
+}
+
+//value of `key="value"` in the attributes of a tag
+fn attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    let mut parts = attributes.split('"');
+    while let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+        if name.trim().strip_suffix('=').map(str::trim_end) == Some(key) {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    let mut sha1 = [0; 20];
+    for (byte, digits) in sha1.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(sha1)
+}
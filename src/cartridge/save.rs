@@ -0,0 +1,74 @@
+use crate::cartridge::Rom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+//~5 seconds at 60 fps, so a crash loses at most a few seconds of progress
+pub const DEFAULT_FLUSH_INTERVAL: u32 = 300;
+
+/// A `.sav` file holding the battery-backed memory of a cartridge.
+pub struct SaveFile {
+    path: PathBuf,
+    flush_interval: u32,
+    frames_since_flush: u32,
+}
+
+impl SaveFile {
+    pub fn new(path: PathBuf) -> Self {
+        SaveFile {
+            path,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            frames_since_flush: 0,
+        }
+    }
+
+    /// The save file that lives next to the ROM, e.g. `zelda.nes` -> `zelda.sav`.
+    pub fn for_rom(rom_path: &Path) -> Self {
+        SaveFile::new(rom_path.with_extension("sav"))
+    }
+
+    pub fn with_flush_interval(mut self, frames: u32) -> Self {
+        self.flush_interval = frames;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the save into the cartridge. A missing file is not an error, the game just starts fresh.
+    pub fn load(&self, rom: &mut Rom) -> io::Result<()> {
+        if !rom.battery {
+            return Ok(());
+        }
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        rom.import_save_data(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the save if the battery-backed memory changed since the last flush.
+    pub fn flush(&mut self, rom: &mut Rom) -> io::Result<()> {
+        self.frames_since_flush = 0;
+        if !rom.battery || !rom.save_dirty() {
+            return Ok(());
+        }
+        //stays dirty when the write fails so the next flush tries again
+        fs::write(&self.path, rom.export_save_data())?;
+        rom.mark_saved();
+        Ok(())
+    }
+
+    /// Call once per frame, flushes every `flush_interval` frames.
+    pub fn end_frame(&mut self, rom: &mut Rom) -> io::Result<()> {
+        self.frames_since_flush += 1;
+        if self.frames_since_flush >= self.flush_interval {
+            self.flush(rom)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::bus::Bus;
+use crate::cartridge::database::Database;
+use crate::cartridge::save::SaveFile;
+use crate::cartridge::{Mirroring, Rom};
+use crate::region::Region;
+use crate::test_util::temp_path;
+
+fn test_rom(flags_6: u8, prg_ram_pages: u8) -> Vec<u8> {
+    let mut raw = vec![
+        0x4E,
+        0x45,
+        0x53,
+        0x1A,
+        0x01,
+        0x01,
+        flags_6,
+        0x00,
+        prg_ram_pages,
+    ];
+    raw.extend(vec![0x00; 7]);
+    raw.extend(vec![0xEA; 16384]);
+    raw.extend(vec![0x00; 8192]);
+    raw
+}
+
+#[test]
+fn test_parse_header() {
+    let rom = Rom::new(&test_rom(0b0000_0011, 0)).unwrap();
+    assert_eq!(rom.mapper, 0);
+    assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+    assert!(rom.battery);
+    assert_eq!(rom.prg_rom.len(), 16384);
+    assert_eq!(rom.chr_rom.len(), 8192);
+    assert_eq!(rom.prg_ram.len(), 8192);
+}
+
+#[test]
+fn test_invalid_header() {
+    assert!(Rom::new(&[0x00; 16]).is_err());
+    assert!(Rom::new(&test_rom(0, 0)[..100]).is_err());
+}
+
+#[test]
+fn test_unsupported_mapper() {
+    //MMC1 with a battery, it used to load and run as NROM
+    let err = Rom::new(&test_rom(0b0001_0010, 0)).err().unwrap();
+    assert!(err.contains("Mapper 1"), "{}", err);
+
+    //the upper nibble of the mapper number is in flags 7
+    let mut raw = test_rom(0, 0);
+    raw[7] = 0x40;
+    assert!(Rom::new(&raw).is_err());
+}
+
+#[test]
+fn test_no_prg_rom() {
+    let mut raw = test_rom(0, 0);
+    raw[4] = 0;
+    assert_eq!(
+        Rom::new(&raw).err(),
+        Some("Header declares no PRG ROM".to_string())
+    );
+}
+
+#[test]
+fn test_export_import_save_data() {
+    let mut rom = Rom::new(&test_rom(0b0000_0010, 0)).unwrap();
+    rom.write_prg_ram(0x0010, 0x42);
+    assert!(rom.save_dirty());
+
+    let data = rom.export_save_data();
+    assert!(rom.save_dirty());
+    rom.mark_saved();
+    assert!(!rom.save_dirty());
+    assert_eq!(data.len(), 8192);
+    assert_eq!(data[0x10], 0x42);
+
+    let mut other = Rom::new(&test_rom(0b0000_0010, 0)).unwrap();
+    other.import_save_data(&data).unwrap();
+    assert_eq!(other.read_prg_ram(0x0010), 0x42);
+    assert!(other.import_save_data(&data[..10]).is_err());
+}
+
+#[test]
+fn test_no_battery_no_save_data() {
+    let mut rom = Rom::new(&test_rom(0, 0)).unwrap();
+    rom.write_prg_ram(0x0010, 0x42);
+    assert!(!rom.save_dirty());
+    assert!(rom.export_save_data().is_empty());
+}
+
+#[test]
+fn test_save_file_round_trip() {
+    let path = temp_path("round_trip.sav");
+    {
+        let mut bus = Bus::with_rom(Rom::new(&test_rom(0b0000_0010, 0)).unwrap());
+        bus.attach_save_file(SaveFile::new(path.clone())).unwrap();
+        bus.mem_write(0x6000, 0x99);
+        //dropping the bus flushes the save
+    }
+
+    let mut bus = Bus::with_rom(Rom::new(&test_rom(0b0000_0010, 0)).unwrap());
+    bus.attach_save_file(SaveFile::new(path.clone())).unwrap();
+    assert_eq!(bus.mem_read(0x6000), 0x99);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_save_file_periodic_flush() {
+    let path = temp_path("periodic.sav");
+    let mut rom = Rom::new(&test_rom(0b0000_0010, 0)).unwrap();
+    let mut save_file = SaveFile::new(path.clone()).with_flush_interval(2);
+
+    rom.write_prg_ram(0, 0x01);
+    save_file.end_frame(&mut rom).unwrap();
+    assert!(!path.exists());
+    save_file.end_frame(&mut rom).unwrap();
+    assert!(path.exists());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_failed_flush_stays_dirty() {
+    let path = temp_path("no_such_dir").join("failed.sav");
+    let mut rom = Rom::new(&test_rom(0b0000_0010, 0)).unwrap();
+    let mut save_file = SaveFile::new(path);
+
+    rom.write_prg_ram(0, 0x01);
+    assert!(save_file.flush(&mut rom).is_err());
+    assert!(rom.save_dirty());
+}
+
+#[test]
+fn test_crc32_covers_prg_and_chr() {
+    let mut rom = Rom::new(&test_rom(0, 0)).unwrap();
+    rom.prg_rom = b"12345".to_vec();
+    rom.chr_rom = b"6789".to_vec();
+    assert_eq!(rom.crc32(), 0xcbf4_3926);
+}
+
+#[test]
+fn test_sha1() {
+    let mut rom = Rom::new(&test_rom(0, 0)).unwrap();
+    rom.prg_rom = b"ab".to_vec();
+    rom.chr_rom = b"c".to_vec();
+    assert_eq!(hex(&rom.sha1()), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    rom.prg_rom = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq".to_vec();
+    rom.chr_rom.clear();
+    assert_eq!(hex(&rom.sha1()), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+}
+
+#[test]
+fn test_md5() {
+    let mut rom = Rom::new(&test_rom(0, 0)).unwrap();
+    rom.prg_rom.clear();
+    rom.chr_rom.clear();
+    assert_eq!(hex(&rom.md5()), "d41d8cd98f00b204e9800998ecf8427e");
+    rom.prg_rom = b"The quick brown fox jumps over ".to_vec();
+    rom.chr_rom = b"the lazy dog".to_vec();
+    assert_eq!(hex(&rom.md5()), "9e107d9d372bb6826bd81d3542a419d6");
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn test_nes2_region() {
+    assert_eq!(Rom::new(&test_rom(0, 0)).unwrap().region, Region::Ntsc);
+    let mut raw = test_rom(0, 0);
+    raw[7] = 0b0000_1000;
+    raw[12] = 0x03;
+    assert_eq!(Rom::new(&raw).unwrap().region, Region::Dendy);
+}
+
+#[test]
+fn test_database_corrects_header() {
+    let mut rom = Rom::new(&test_rom(0b0000_0001, 0)).unwrap();
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<nes20db>
+<game>
+  <!-- Test Game (Europe) -->
+  <rom size="24576" crc32="{:08X}" sha1="{}"/>
+  <pcb mapper="3" submapper="0" mirroring="H" battery="1"/>
+  <prgnvram size="2048"/>
+  <console type="0" region="1"/>
+</game>
+<game>
+  <!-- Other Game -->
+  <rom size="16" crc32="0BADF00D"/>
+  <pcb mapper="0" mirroring="V" battery="0"/>
+</game>
+</nes20db>
+"#,
+        rom.crc32(),
+        hex(&rom.sha1()).to_uppercase()
+    );
+    let database = Database::parse(&xml).unwrap();
+    assert_eq!(database.len(), 2);
+
+    let game = database.find(&rom).unwrap();
+    assert_eq!(game.name, "Test Game (Europe)");
+    assert_eq!(
+        game.apply(&mut rom),
+        vec![
+            "mapper 0.0 -> 3.0",
+            "mirroring Vertical -> Horizontal",
+            "battery false -> true",
+            "PRG-RAM 8192 -> 2048 bytes",
+            "region Ntsc -> Pal",
+        ]
+    );
+    assert_eq!(rom.mapper, 3);
+    assert_eq!(rom.screen_mirroring, Mirroring::Horizontal);
+    assert_eq!(rom.prg_ram.len(), 2048);
+    assert_eq!(rom.region, Region::Pal);
+    assert!(game.apply(&mut rom).is_empty());
+}
+
+#[test]
+fn test_database_file_corrects_header() {
+    //a vertical-mirroring NROM game dumped with horizontal mirroring and a battery
+    let raw = test_rom(0b0000_0010, 0);
+    let rom = Rom::new(&raw).unwrap();
+    let rom_path = temp_path("database_file.nes");
+    let database_path = temp_path("database_file.xml");
+    std::fs::write(&rom_path, &raw).unwrap();
+    //laid out like an entry of a full nes20db.xml export
+    std::fs::write(
+        &database_path,
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<nes20db>
+<game>
+<!-- 1\Test Game (USA).nes -->
+<prgrom size="16384" crc32="{:08X}" sha1="{}"/>
+<chrrom size="8192"/>
+<rom size="24576" crc32="{:08X}" sha1="{}"/>
+<pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+<console type="0" region="0"/>
+<expansion type="1"/>
+</game>
+</nes20db>
+"#,
+            0,
+            "00".repeat(20),
+            rom.crc32(),
+            hex(&rom.sha1()).to_uppercase()
+        ),
+    )
+    .unwrap();
+
+    let database = Database::open(&database_path).unwrap();
+    let rom = Rom::from_file_with_database(&rom_path, Some(&database)).unwrap();
+    assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+    assert!(!rom.battery);
+    assert!(rom.prg_ram.is_empty());
+
+    let rom = Rom::from_file_with_database(&rom_path, None).unwrap();
+    assert_eq!(rom.screen_mirroring, Mirroring::Horizontal);
+    assert!(Database::open(&temp_path("missing.xml")).is_err());
+
+    std::fs::remove_file(rom_path).unwrap();
+    std::fs::remove_file(database_path).unwrap();
+}
+
+#[test]
+fn test_database_sha1_must_match() {
+    //same CRC32 but another SHA-1
+    let rom = Rom::new(&test_rom(0, 0)).unwrap();
+    let xml = format!(
+        "<game><rom crc32=\"{:08x}\" sha1=\"{}\"/></game>",
+        rom.crc32(),
+        "00".repeat(20)
+    );
+    assert!(Database::parse(&xml).unwrap().find(&rom).is_none());
+}
+
+#[test]
+fn test_database_errors() {
+    assert_eq!(
+        Database::parse("<nes20db>\n<game>\n<pcb mapper=\"1\"/>\n</game>").unwrap_err(),
+        "line 4: game without a rom element"
+    );
+    assert_eq!(
+        Database::parse("<game>\n<rom crc32=\"xyz\"/>").unwrap_err(),
+        "line 2: invalid crc32 xyz"
+    );
+    assert!(Database::parse("<game><rom crc32=\"1\"/>").is_err());
+}
@@ -0,0 +1,93 @@
+//! Code/Data Logger in the FCEUX .cdl format.
+//!
+//! The file is one flag byte per PRG ROM byte followed by one per CHR ROM byte. PRG bytes
+//! are `xPdcAADC`: C code, D data, AA the 8 KiB CPU window ($8000, $A000, $C000, $E000)
+//! the byte was accessed through, c code reached by an indirect jump, d data read through
+//! a pointer and P DMC sample data. CHR bytes are `xxxxxxRD`: D drawn by the renderer and
+//! R read through $2007. CHR-RAM is not logged.
+
+use crate::cartridge::Rom;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+pub const PRG_CODE: u8 = 0b0000_0001;
+pub const PRG_DATA: u8 = 0b0000_0010;
+const PRG_WINDOW_SHIFT: u8 = 2;
+pub const PRG_INDIRECT_CODE: u8 = 0b0001_0000;
+pub const PRG_INDIRECT_DATA: u8 = 0b0010_0000;
+pub const PRG_PCM: u8 = 0b0100_0000;
+
+pub const CHR_DRAWN: u8 = 0b0000_0001;
+pub const CHR_READ: u8 = 0b0000_0010;
+
+/// Access flags for every byte of a cartridge's ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeDataLog {
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    /// An empty log sized for `rom`.
+    pub fn new(rom: &Rom) -> Self {
+        CodeDataLog {
+            prg: vec![0; rom.prg_rom.len()],
+            chr: vec![0; rom.chr_rom.len()],
+        }
+    }
+
+    /// Reads a .cdl file made for `rom`.
+    pub fn parse(rom: &Rom, data: &[u8]) -> Result<Self, String> {
+        let mut log = CodeDataLog::new(rom);
+        if data.len() != log.prg.len() + log.chr.len() {
+            return Err(format!(
+                "Code/data log is {} bytes, the cartridge needs {}",
+                data.len(),
+                log.prg.len() + log.chr.len()
+            ));
+        }
+        let (prg, chr) = data.split_at(log.prg.len());
+        log.prg.copy_from_slice(prg);
+        log.chr.copy_from_slice(chr);
+        Ok(log)
+    }
+
+    /// Loads `path` to keep adding to it, or starts a new log when it does not exist yet.
+    pub fn open(rom: &Rom, path: &Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(data) => {
+                CodeDataLog::parse(rom, &data).map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(CodeDataLog::new(rom)),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.prg.clone();
+        data.extend_from_slice(&self.chr);
+        data
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Marks the PRG ROM byte at `offset`, seen by the CPU at `addr`.
+    pub fn log_prg(&mut self, offset: usize, addr: u16, flags: u8) {
+        let window = (((addr >> 13) & 0x03) as u8) << PRG_WINDOW_SHIFT;
+        if let Some(byte) = self.prg.get_mut(offset) {
+            *byte |= flags | window;
+        }
+    }
+
+    /// Bytes of PRG ROM logged as code and as data.
+    pub fn prg_coverage(&self) -> (usize, usize) {
+        let count = |flag: u8| self.prg.iter().filter(|&&b| b & flag != 0).count();
+        (count(PRG_CODE), count(PRG_DATA))
+    }
+}
@@ -0,0 +1,108 @@
+use crate::cartridge::Rom;
+use crate::cdl::*;
+use crate::test_util::{temp_path, test_nes_with, test_rom_with};
+
+//8000 LDA $8100
+//8003 LDA #$00
+//8005 STA $10
+//8007 LDA #$81
+//8009 STA $11
+//800b LDY #$02
+//800d LDA ($10),Y
+//800f JMP ($8110)
+//8020 LDA #$00
+//8022 STA $2006
+//8025 STA $2006
+//8028 LDA $2007
+//802b JMP $802b
+const PROGRAM: [(usize, &[u8]); 3] = [
+    (
+        0x00,
+        &[
+            0xad, 0x00, 0x81, 0xa9, 0x00, 0x85, 0x10, 0xa9, 0x81, 0x85, 0x11, 0xa0, 0x02, 0xb1,
+            0x10, 0x6c, 0x10, 0x81,
+        ],
+    ),
+    (
+        0x20,
+        &[
+            0xa9, 0x00, 0x8d, 0x06, 0x20, 0x8d, 0x06, 0x20, 0xad, 0x07, 0x20, 0x4c, 0x2b, 0x80,
+        ],
+    ),
+    (0x110, &[0x20, 0x80]),
+];
+
+#[test]
+fn test_logs_code_and_data() {
+    let mut nes = test_nes_with(&PROGRAM);
+    nes.cpu
+        .bus
+        .set_code_data_log(Some(CodeDataLog::new(nes.cpu.bus.rom().unwrap())));
+    for _ in 0..14 {
+        nes.step_instruction().unwrap();
+    }
+    let log = nes.cpu.bus.code_data_log().unwrap();
+    assert_eq!(log.prg.len(), 16384);
+    assert_eq!(log.chr.len(), 8192);
+
+    assert_eq!(&log.prg[0..3], &[PRG_CODE; 3]);
+    assert_eq!(log.prg[0x100], PRG_DATA);
+    assert_eq!(log.prg[0x102], PRG_DATA | PRG_INDIRECT_DATA);
+    assert_eq!(&log.prg[0x110..0x112], &[PRG_DATA; 2]);
+    assert_eq!(log.prg[0x20], PRG_CODE | PRG_INDIRECT_CODE);
+    assert_eq!(log.prg[0x12], 0);
+    //logging started after the reset vector was read
+    assert_eq!(log.prg[0x3ffc], 0);
+    assert_eq!(log.prg_coverage(), (18 + 14, 4));
+
+    assert_eq!(log.chr[0], CHR_READ);
+    assert_eq!(log.chr[1], 0);
+}
+
+#[test]
+fn test_cpu_window_bits() {
+    let rom = Rom::new(&test_rom_with(&PROGRAM)).unwrap();
+    let mut log = CodeDataLog::new(&rom);
+    log.log_prg(0x10, 0xc010, PRG_CODE);
+    log.log_prg(0x20, 0xe020, PRG_DATA);
+    assert_eq!(log.prg[0x10], PRG_CODE | 0b1000);
+    assert_eq!(log.prg[0x20], PRG_DATA | 0b1100);
+}
+
+#[test]
+fn test_rendering_marks_chr() {
+    let mut nes = test_nes_with(&PROGRAM);
+    nes.cpu
+        .bus
+        .set_code_data_log(Some(CodeDataLog::new(nes.cpu.bus.rom().unwrap())));
+    //background on, nametables are all tile 0
+    nes.cpu.bus.mem_write(0x2001, 0b0000_1010);
+    nes.run_frame().unwrap();
+    nes.run_frame().unwrap();
+    let log = nes.cpu.bus.code_data_log().unwrap();
+    assert!(log.chr[..16].iter().all(|&b| b & CHR_DRAWN != 0));
+    assert_eq!(log.chr[16], 0);
+}
+
+#[test]
+fn test_log_accumulates_across_sessions() {
+    let path = temp_path("accumulate.cdl");
+    let mut nes = test_nes_with(&PROGRAM);
+    nes.start_code_data_log(&path).unwrap();
+    nes.step_instruction().unwrap();
+    nes.save_code_data_log(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 16384 + 8192);
+
+    let mut nes = test_nes_with(&PROGRAM);
+    nes.start_code_data_log(&path).unwrap();
+    for _ in 0..2 {
+        nes.step_instruction().unwrap();
+    }
+    let log = nes.cpu.bus.code_data_log().unwrap();
+    assert_eq!(log.prg[0x100], PRG_DATA);
+    assert_eq!(log.prg[0x03], PRG_CODE);
+
+    let rom = Rom::new(&test_rom_with(&PROGRAM)).unwrap();
+    assert!(CodeDataLog::parse(&rom, &[0; 100]).is_err());
+    std::fs::remove_file(path).unwrap();
+}
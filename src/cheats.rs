@@ -0,0 +1,275 @@
+//! Cheat codes applied on the CPU bus, and a RAM search to find new ones.
+//!
+//! Game Genie codes patch PRG ROM reads: six letters replace the byte at an address, eight
+//! letters only when the ROM holds the compare byte there. Raw codes are `ADDR:VALUE`,
+//! `ADDR?COMPARE:VALUE` or the Pro Action Replay form `AAAAVV`. Without a compare byte they
+//! freeze RAM: reads return the value and writes store it instead of what the game wrote.
+//! Cheats are kept per ROM in `cheats/<crc32>.cht` next to the ROM file.
+
+use crate::cartridge::Rom;
+use crate::headless::parse_hex;
+use crate::nes::Nes;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    //as entered, in upper case
+    pub code: String,
+    pub description: String,
+    pub enabled: bool,
+    pub addr: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl Cheat {
+    /// Decodes a Game Genie or raw code, enabled and without a description.
+    pub fn parse(code: &str) -> Result<Cheat, String> {
+        let code = code.trim().to_ascii_uppercase();
+        let (addr, value, compare) = if code.chars().all(|c| GAME_GENIE_LETTERS.contains(c)) {
+            decode_game_genie(&code)?
+        } else {
+            decode_raw(&code)?
+        };
+        Ok(Cheat {
+            code,
+            description: String::new(),
+            enabled: true,
+            addr: mirror(addr),
+            value,
+            compare,
+        })
+    }
+
+    fn applies(&self, addr: u16, data: u8) -> bool {
+        self.enabled && self.addr == mirror(addr) && self.compare.is_none_or(|c| c == data)
+    }
+}
+
+//decodes the letters of a 6 or 8 letter Game Genie code
+fn decode_game_genie(code: &str) -> Result<(u16, u8, Option<u8>), String> {
+    let n: Vec<u16> = code
+        .chars()
+        .filter_map(|c| GAME_GENIE_LETTERS.find(c).map(|i| i as u16))
+        .collect();
+    if n.len() != 6 && n.len() != 8 {
+        return Err(format!("Game Genie code {} must have 6 or 8 letters", code));
+    }
+    let addr = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+    let low = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+    if n.len() == 6 {
+        return Ok((addr, (low | (n[5] & 8)) as u8, None));
+    }
+    let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+    Ok((addr, (low | (n[7] & 8)) as u8, Some(compare as u8)))
+}
+
+//ADDR:VALUE, ADDR?COMPARE:VALUE, AAAAVV or AAAA-VV
+fn decode_raw(code: &str) -> Result<(u16, u8, Option<u8>), String> {
+    let invalid = || format!("Invalid cheat code {}", code);
+    let byte = |s: &str| {
+        parse_hex(s)
+            .filter(|&v| v <= 0xff)
+            .map(|v| v as u8)
+            .ok_or_else(invalid)
+    };
+    let (target, value) = match code.split_once([':', '-']) {
+        Some(parts) => parts,
+        None if code.len() == 6 && code.is_char_boundary(4) => code.split_at(4),
+        None => return Err(invalid()),
+    };
+    let (addr, compare) = match target.split_once('?') {
+        Some((addr, compare)) => (addr, Some(byte(compare)?)),
+        None => (target, None),
+    };
+    let addr = parse_hex(addr).ok_or_else(invalid)?;
+    Ok((addr, byte(value)?, compare))
+}
+
+//RAM is mirrored 4 times
+fn mirror(addr: u16) -> u16 {
+    if addr < 0x2000 {
+        addr & 0x07ff
+    } else {
+        addr
+    }
+}
+
+/// The cheats of one game, the bus runs every read and RAM write through it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheatList {
+    pub cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    pub fn new() -> Self {
+        CheatList::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    /// What the CPU reads at `addr` when the hardware returns `data`.
+    pub fn apply_read(&self, addr: u16, data: u8) -> u8 {
+        match self.cheats.iter().find(|cheat| cheat.applies(addr, data)) {
+            Some(cheat) => cheat.value,
+            None => data,
+        }
+    }
+
+    /// What gets stored when the CPU writes `data` to `addr`, frozen RAM keeps its value.
+    pub fn apply_write(&self, addr: u16, data: u8) -> u8 {
+        if !matches!(addr, 0x0000..=0x1fff | 0x6000..=0x7fff) {
+            return data;
+        }
+        match self
+            .cheats
+            .iter()
+            .find(|cheat| cheat.compare.is_none() && cheat.applies(addr, data))
+        {
+            Some(cheat) => cheat.value,
+            None => data,
+        }
+    }
+
+    /// Reads a cheat file: one `on|off CODE [DESCRIPTION]` per line, `#` starts a comment.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut list = CheatList::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: String| format!("line {}: {}", number + 1, e);
+            let mut words = line.splitn(3, char::is_whitespace);
+            let enabled = match words.next() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => return Err(error("expected on or off".to_string())),
+            };
+            let code = words.next().unwrap_or_default();
+            let mut cheat = Cheat::parse(code).map_err(error)?;
+            cheat.enabled = enabled;
+            cheat.description = words.next().unwrap_or_default().trim().to_string();
+            list.add(cheat);
+        }
+        Ok(list)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for cheat in self.cheats.iter() {
+            let state = if cheat.enabled { "on" } else { "off" };
+            write!(out, "{} {}", state, cheat.code).unwrap();
+            if !cheat.description.is_empty() {
+                write!(out, " {}", cheat.description).unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Loads the cheats saved at `path`, an empty list when there are none yet.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => CheatList::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(CheatList::new()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_text())
+    }
+}
+
+/// Where the cheats for `rom`, loaded from `rom_path`, are saved.
+pub fn cheat_file(rom_path: &Path, rom: &Rom) -> PathBuf {
+    rom_path
+        .with_file_name("cheats")
+        .join(format!("{:08x}.cht", rom.crc32()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Equal(u8),
+    //compared with the last snapshot
+    Less,
+    Greater,
+    Changed,
+    Unchanged,
+}
+
+impl SearchFilter {
+    fn matches(self, previous: u8, current: u8) -> bool {
+        match self {
+            SearchFilter::Equal(value) => current == value,
+            SearchFilter::Less => current < previous,
+            SearchFilter::Greater => current > previous,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Unchanged => current == previous,
+        }
+    }
+}
+
+/// Narrows down the RAM addresses that could hold a value by filtering snapshots.
+#[derive(Debug, Clone)]
+pub struct RamSearch {
+    //addresses still matching with their value at the last snapshot
+    candidates: Vec<(u16, u8)>,
+}
+
+impl RamSearch {
+    /// Starts with all of RAM and the cartridge's PRG-RAM.
+    pub fn new(nes: &Nes) -> Self {
+        let prg_ram = nes.cpu.bus.rom().is_some_and(|rom| !rom.prg_ram.is_empty());
+        let mut addrs: Vec<u16> = (0x0000..0x0800).collect();
+        if prg_ram {
+            addrs.extend(0x6000..0x8000);
+        }
+        RamSearch {
+            candidates: addrs
+                .into_iter()
+                .map(|addr| (addr, nes.cpu.bus.peek(addr)))
+                .collect(),
+        }
+    }
+
+    /// Keeps the addresses matching `filter` and takes a new snapshot of them.
+    pub fn filter(&mut self, nes: &Nes, filter: SearchFilter) {
+        self.candidates.retain_mut(|(addr, value)| {
+            let current = nes.cpu.bus.peek(*addr);
+            let keep = filter.matches(*value, current);
+            *value = current;
+            keep
+        });
+    }
+
+    /// Remaining addresses with their value at the last snapshot.
+    pub fn candidates(&self) -> &[(u16, u8)] {
+        &self.candidates
+    }
+}
@@ -0,0 +1,129 @@
+use crate::cheats::*;
+use crate::test_util::{temp_path, test_nes};
+
+#[test]
+fn test_decode_game_genie() {
+    let cheat = Cheat::parse("GOSSIP").unwrap();
+    assert_eq!(
+        (cheat.addr, cheat.value, cheat.compare),
+        (0xd1dd, 0x14, None)
+    );
+
+    let cheat = Cheat::parse("zexpygla").unwrap();
+    assert_eq!(cheat.code, "ZEXPYGLA");
+    assert_eq!(
+        (cheat.addr, cheat.value, cheat.compare),
+        (0x94a7, 0x02, Some(0x03))
+    );
+
+    assert!(Cheat::parse("GOSSI").is_err());
+}
+
+#[test]
+fn test_decode_raw() {
+    let cheat = Cheat::parse("0075:09").unwrap();
+    assert_eq!(
+        (cheat.addr, cheat.value, cheat.compare),
+        (0x0075, 0x09, None)
+    );
+    let cheat = Cheat::parse("c000?ea:60").unwrap();
+    assert_eq!(
+        (cheat.addr, cheat.value, cheat.compare),
+        (0xc000, 0x60, Some(0xea))
+    );
+    //Pro Action Replay form, RAM mirrors fold onto $0000-$07ff
+    let cheat = Cheat::parse("087509").unwrap();
+    assert_eq!((cheat.addr, cheat.value), (0x0075, 0x09));
+    assert_eq!(Cheat::parse("0875-09").unwrap().addr, 0x0075);
+
+    assert!(Cheat::parse("0075:123").is_err());
+    assert!(Cheat::parse("0075").is_err());
+    assert!(Cheat::parse("XYZ:01").is_err());
+}
+
+#[test]
+fn test_game_genie_patches_rom_reads() {
+    let mut nes = test_nes(&[]);
+    nes.cpu.bus.cheats.add(Cheat::parse("8005:a9").unwrap());
+    nes.cpu.bus.cheats.add(Cheat::parse("8006?00:60").unwrap());
+    assert_eq!(nes.cpu.bus.mem_read(0x8005), 0xa9);
+    //the compare byte does not match the ROM
+    assert_eq!(nes.cpu.bus.mem_read(0x8006), 0xea);
+    assert_eq!(nes.cpu.bus.peek(0x8005), 0xea);
+
+    nes.cpu.bus.cheats.cheats[1].compare = Some(0xea);
+    assert_eq!(nes.cpu.bus.mem_read(0x8006), 0x60);
+    nes.cpu.bus.cheats.cheats[1].enabled = false;
+    assert_eq!(nes.cpu.bus.mem_read(0x8006), 0xea);
+}
+
+#[test]
+fn test_freeze_ram() {
+    let mut nes = test_nes(&[]);
+    nes.cpu.bus.cheats.add(Cheat::parse("0010:42").unwrap());
+    nes.cpu.bus.mem_write(0x0010, 0x05);
+    assert_eq!(nes.cpu.bus.peek(0x0010), 0x42);
+    assert_eq!(nes.cpu.bus.mem_read(0x0810), 0x42);
+    nes.cpu.bus.mem_write(0x0011, 0x05);
+    assert_eq!(nes.cpu.bus.mem_read(0x0011), 0x05);
+}
+
+#[test]
+fn test_cheat_file_round_trip() {
+    let text = "# lives\non SXIOPO Infinite lives\noff 0075:09\n";
+    let list = CheatList::parse(text).unwrap();
+    assert_eq!(list.cheats.len(), 2);
+    assert_eq!(list.cheats[0].description, "Infinite lives");
+    assert!(list.cheats[0].enabled);
+    assert!(!list.cheats[1].enabled);
+    assert_eq!(list.to_text(), "on SXIOPO Infinite lives\noff 0075:09\n");
+
+    let path = temp_path("cheats").join("game.cht");
+    assert!(CheatList::open(&path).unwrap().is_empty());
+    list.save(&path).unwrap();
+    assert_eq!(CheatList::open(&path).unwrap(), list);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+    assert_eq!(
+        CheatList::parse("on SXIOPO\nmaybe 0075:09").unwrap_err(),
+        "line 2: expected on or off"
+    );
+}
+
+#[test]
+fn test_cheat_file_is_keyed_by_rom() {
+    let nes = test_nes(&[]);
+    let rom = nes.cpu.bus.rom().unwrap();
+    assert_eq!(
+        cheat_file(Path::new("roms/game.nes"), rom),
+        Path::new("roms/cheats").join(format!("{:08x}.cht", rom.crc32()))
+    );
+}
+
+#[test]
+fn test_ram_search() {
+    let mut nes = test_nes(&[]);
+    nes.cpu.bus.mem_write(0x0040, 3);
+    nes.cpu.bus.mem_write(0x0041, 3);
+    let mut search = RamSearch::new(&nes);
+    //RAM and the 8 KiB of PRG-RAM iNES 1.0 headers get
+    assert_eq!(search.candidates().len(), 0x800 + 0x2000);
+
+    search.filter(&nes, SearchFilter::Equal(3));
+    assert_eq!(search.candidates(), &[(0x0040, 3), (0x0041, 3)]);
+
+    nes.cpu.bus.mem_write(0x0040, 2);
+    search.filter(&nes, SearchFilter::Unchanged);
+    assert_eq!(search.candidates(), &[(0x0041, 3)]);
+
+    let mut search = RamSearch::new(&nes);
+    nes.cpu.bus.mem_write(0x0040, 1);
+    nes.cpu.bus.mem_write(0x0041, 4);
+    let mut greater = search.clone();
+    search.filter(&nes, SearchFilter::Less);
+    assert_eq!(search.candidates(), &[(0x0040, 1)]);
+    greater.filter(&nes, SearchFilter::Greater);
+    assert_eq!(greater.candidates(), &[(0x0041, 4)]);
+    greater.filter(&nes, SearchFilter::Changed);
+    assert!(greater.candidates().is_empty());
+}
@@ -1,204 +1,77 @@
-use std::collections::HashMap;
+//! The NES side of the 6502 core in the mos6502 crate: a 2A03 on the console's bus.
 
-mod op_codes;
-mod tests;
-
-pub struct CPU {
-    pub register_a: u8,
-    pub register_x: u8,
-    pub register_y: u8,
-    pub status: u8,
-    pub program_counter: u16,
-    memory: [u8; 0xFFFF],
-}
-
-impl Default for CPU {
-    fn default() -> Self {
-        CPU::new()
-    }
-}
-
-#[derive(Debug)]
-#[allow(non_camel_case_types)]
-pub enum AddressingMode {
-    Immediate,
-    ZeroPage,
-    ZeroPage_X,
-    ZeroPage_Y,
-    Absolute,
-    Absolute_X,
-    Absolute_Y,
-    Indirect_X,
-    Indirect_Y,
-    NoneAddressing,
-}
-
-impl CPU {
-    pub fn new() -> Self {
-        CPU {
-            register_a: 0,
-            register_x: 0,
-            register_y: 0,
-            status: 0,
-            program_counter: 0,
-            memory: [0; 0xFFFF],
-        }
-    }
-
-    // todo: implement addressing mode usage
-    pub fn run(&mut self) {
-        let code_map: &HashMap<u8, &'static op_codes::OpCode> = &*op_codes::OPCODES_MAP;
-        loop {
-            let opcode = self.mem_read(self.program_counter);
-            let instruction = code_map
-                .get(&opcode)
-                .unwrap_or_else(|| panic!("Instruction {:x} does not exist", opcode));
-            self.program_counter += 1;
-            let program_counter_state = self.program_counter;
-
-            match opcode {
-                0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
-                    self.lda(&instruction.addressing_mode);
-                }
-
-                /* STA */
-                // 0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => {
-                //     self.sta(&instruction.addressing_mode);
-                // }
-                0xAA => self.tax(),
-                0xe8 => self.inx(),
-                0x00 => return,
+use crate::bus::{Bus, Scheduling};
+use crate::cdl::{PRG_CODE, PRG_DATA, PRG_INDIRECT_CODE, PRG_INDIRECT_DATA};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+pub use mos6502::{
+    disasm, op_codes, AddressingMode, CpuError, Usage, Variant, BREAK2_FLAG, BREAK_FLAG,
+    CARRY_FLAG, DECIMAL_MODE_FLAG, INTERRUPT_DISABLE_FLAG, NEGATIVE_FLAG, OVERFLOW_FLAG, ZERO_FLAG,
+};
 
-                _ => todo!(),
-            }
-
-            if program_counter_state == self.program_counter {
-                self.program_counter += (instruction.bytes - 1) as u16;
-            }
-        }
-    }
-
-    fn mem_read(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
-    }
-
-    fn mem_write(&mut self, addr: u16, data: u8) {
-        self.memory[addr as usize] = data;
-    }
-
-    fn mem_read_u16(&self, pos: u16) -> u16 {
-        let lo = self.mem_read(pos) as u16;
-        let hi = self.mem_read(pos + 1) as u16;
-        (hi << 8) | (lo as u16)
-    }
+#[allow(clippy::module_inception)]
+mod tests;
 
-    fn mem_write_u16(&mut self, pos: u16, data: u16) {
-        //shifting 8 bits to the right
-        let hi = (data >> 8) as u8;
-        let lo = (data & 0xff) as u8;
-        self.mem_write(pos, lo);
-        self.mem_write(pos + 1, hi);
-    }
+pub type CPU = mos6502::CPU<Bus>;
 
-    pub fn load_and_run(&mut self, program: Vec<u8>) {
-        self.load(program);
-        self.reset();
-        self.run();
+impl mos6502::Bus for Bus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.mem_read(addr)
     }
 
-    pub fn reset(&mut self) {
-        self.register_a = 0;
-        self.register_x = 0;
-        self.status = 0;
-
-        self.program_counter = self.mem_read_u16(0xFFFC);
+    fn write(&mut self, addr: u16, data: u8) {
+        self.mem_write(addr, data);
     }
 
-    pub fn load(&mut self, program: Vec<u8>) {
-        self.memory[0x8000..(0x8000 + program.len())].copy_from_slice(&program[..]);
-        self.mem_write_u16(0xFFFC, 0x8000);
+    fn tick(&mut self, cycles: u16) {
+        Bus::tick(self, cycles);
     }
 
-    fn lda(&mut self, addressing_mode: &AddressingMode) {
-        let addr = self.get_operand_address(addressing_mode);
-        let value = self.mem_read(addr);
-        self.register_a = value;
-        self.update_zero_flag(self.register_a);
-        self.update_negative_flag(self.register_a);
+    fn interleaved(&self) -> bool {
+        self.scheduling() == Scheduling::Cycle
     }
 
-    fn tax(&mut self) {
-        self.register_x = self.register_a;
-        self.update_zero_flag(self.register_x);
-        self.update_negative_flag(self.register_x);
+    fn poll_nmi(&mut self) -> bool {
+        self.poll_nmi_status()
     }
 
-    fn inx(&mut self) {
-        self.register_x = self.register_x.wrapping_add(1);
-        self.update_zero_flag(self.register_x);
-        self.update_negative_flag(self.register_x);
+    fn irq(&self) -> bool {
+        self.irq_pending()
     }
 
-    fn update_zero_flag(&mut self, value: u8) {
-        //check if register a is 0 and if it is we set zero flag to 1 else we set it to 0
-        if value == 0 {
-            self.status |= 0b0000_0010;
-        } else {
-            self.status &= 0b1111_1101;
-        }
+    fn take_stall(&mut self) -> u16 {
+        self.take_dma_stall()
     }
 
-    fn update_negative_flag(&mut self, value: u8) {
-        //check if the negative bit of register a is set if it is we set the negative bit of the status
-        if value & 0b1000_0000 != 0 {
-            self.status |= 0b1000_0000;
-        } else {
-            self.status &= 0b0111_1111;
-        }
+    fn mark(&mut self, addr: u16, usage: Usage) {
+        let flags = match usage {
+            Usage::Code => PRG_CODE,
+            Usage::Data => PRG_DATA,
+            Usage::IndirectData => PRG_DATA | PRG_INDIRECT_DATA,
+            Usage::IndirectCode => PRG_INDIRECT_CODE,
+        };
+        self.log_code_data(addr, flags);
     }
+}
 
-    fn get_operand_address(&self, mode: &AddressingMode) -> u16 {
-        match mode {
-            AddressingMode::Immediate => self.program_counter,
-            AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
-            AddressingMode::ZeroPage_X => {
-                let pos = self.mem_read(self.program_counter);
-                pos.wrapping_add(self.register_x) as u16
-            }
-            AddressingMode::ZeroPage_Y => {
-                let pos = self.mem_read(self.program_counter);
-                pos.wrapping_add(self.register_y) as u16
-            }
-            AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
-            AddressingMode::Absolute_X => {
-                let base = self.mem_read_u16(self.program_counter);
-                base.wrapping_add(self.register_x as u16)
-            }
-            AddressingMode::Absolute_Y => {
-                let base = self.mem_read_u16(self.program_counter);
-                base.wrapping_add(self.register_y as u16)
-            }
-            AddressingMode::Indirect_X => {
-                let base = self.mem_read(self.program_counter);
-                //maybe make pointer u16? try after tests have been written to validate functionality
-                //probably not possible for the sake of the wrapping add on u8
-                let pointer: u8 = base.wrapping_add(self.register_x);
-                let lo = self.mem_read(pointer as u16);
-                let hi = self.mem_read(pointer.wrapping_add(1) as u16);
-                (hi as u16) << 8 | (lo as u16)
-            }
-            AddressingMode::Indirect_Y => {
-                let base = self.mem_read(self.program_counter);
-                //maybe make pointer u16? try after tests have been written to validate functionality
-                //probably not possible for the sake of the wrapping add on u8
-                let pointer: u8 = base.wrapping_add(self.register_y);
-                let lo = self.mem_read(pointer as u16);
-                let hi = self.mem_read(pointer.wrapping_add(1) as u16);
-                (hi as u16) << 8 | (lo as u16)
-            }
-            AddressingMode::NoneAddressing => {
-                panic!("mode {:?} is not supported", mode);
-            }
-        }
+impl Snapshot for CPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.register_a);
+        w.write_u8(self.register_x);
+        w.write_u8(self.register_y);
+        w.write_u8(self.status);
+        w.write_u16(self.program_counter);
+        w.write_u8(self.stack_pointer);
+        w.write_u64(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.register_a = r.read_u8()?;
+        self.register_x = r.read_u8()?;
+        self.register_y = r.read_u8()?;
+        self.status = r.read_u8()?;
+        self.program_counter = r.read_u16()?;
+        self.stack_pointer = r.read_u8()?;
+        self.cycles = r.read_u64()?;
+        Ok(())
     }
 }
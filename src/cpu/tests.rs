@@ -1,137 +1,54 @@
 #[cfg(test)]
 mod tests {
-    use crate::cpu::CPU;
-
-    fn lda_status_flags(cpu: CPU) {
-        assert_eq!(cpu.status & 0b0000_0010, 0b00);
-        assert_eq!(cpu.status & 0b1000_0000, 0);
-    }
-
-    #[test]
-    fn test_0xa9_lda_immediate_load_data() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x05, 0x00]);
-        assert_eq!(cpu.register_a, 0x05);
-        lda_status_flags(cpu);
-    }
-
-    #[test]
-    fn test_0xa5_lda_zero_page() {
-        let mut cpu = CPU::new();
-        cpu.mem_write(0xc1, 0x01);
-
-        cpu.load_and_run(vec![0xa5, 0xc1, 0x00]);
-        assert_eq!(cpu.register_a, 0x01);
-        lda_status_flags(cpu);
-    }
-
-    #[test]
-    fn test_0xb5_lda_zero_page_x() {
-        let mut cpu = CPU::new();
-        cpu.mem_write(0xc2, 0x01);
-
-        cpu.load_and_run(vec![0xe8, 0xb5, 0xc1, 0x00]);
-
-        assert_eq!(cpu.register_a, 0x01);
-        lda_status_flags(cpu);
-    }
-
-    #[test]
-    fn test_0xad_lda_absolute() {
-        let mut cpu = CPU::new();
-        cpu.mem_write(0xc1c2, 0x01);
-
-        cpu.load_and_run(vec![0xad, 0xc2, 0xc1, 0x00]);
-        assert_eq!(cpu.register_a, 0x01);
-        lda_status_flags(cpu);
-    }
-
-    #[test]
-    fn test_0xbd_lda_absolute_x() {
-        let mut cpu = CPU::new();
-        cpu.mem_write(0xc1c4, 0x01);
-
-        cpu.load_and_run(vec![0xe8, 0xe8, 0xbd, 0xc2, 0xc1, 0x00]);
-        assert_eq!(cpu.register_a, 0x01);
-        lda_status_flags(cpu);
-    }
-
-    //TODO: TEST AFTER OP CODE TO MODIFY Y HAS BEEN IMPLEMENTED
-
-    // #[test]
-    // fn test_0xb9_lda_absolute_y() {
-    //     let mut cpu = CPU::new();
-    //     cpu.mem_write(0xc1c5, 0x01);
-    //     cpu.register_y = 0x03;
-    //
-    //     cpu.load_and_run(vec![0xb9, 0xc2, 0xc1, 0x00]);
-    //     assert_eq!(cpu.register_a, 0x04);
-    //     lda_status_flags(cpu);
-    // }
-
-    #[test]
-    fn test_0xa1_lda_indirect_x() {
-        let mut cpu = CPU::new();
-        cpu.mem_write(0x06, 0x01);
-        cpu.mem_write(0x01, 0x03);
-
-        cpu.load_and_run(vec![0xe8, 0xe8, 0xa1, 0x04, 0x00]);
-        assert_eq!(cpu.register_a, 0x03);
-        lda_status_flags(cpu);
-    }
-
-    //TODO: TEST AFTER OP CODIE TO MODIFY Y HAS BEEN IMPLEMENTED
-
-    // #[test]
-    // fn test_0xb1_lda_indirect_y() {
-    //     let mut cpu = CPU::new();
-    //     cpu.mem_write(0x07, 0xcc);
-    //     cpu.mem_write(0xcc, 0xff);
-    //     //todo: increment y to 0x03
-    //     cpu.load_and_run(vec![0xb1, 0x04, 0x00]);
-    //     assert_eq!(cpu.register_a, 0xff);
-    //     lda_status_flags(cpu);
-    // }
-
-    #[test]
-    fn test_0xa9_lda_zero_flag() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
-        assert_eq!(cpu.status & 0b0000_0010, 0b10);
-    }
-
-    #[test]
-    fn test_0xaa_tax_move_a_to_x() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x0a, 0xaa, 0x00]);
-        assert_eq!(cpu.register_x, 10)
-    }
-
-    #[test]
-    fn test_0xaa_txa_zero_flag() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
-        assert_eq!(cpu.status & 0b0000_0010, 0b10);
-    }
-
-    #[test]
-    fn test_lda_txa_inx() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
-        assert_eq!(cpu.register_x, 0xc1)
-    }
-
-    #[test]
-    fn test_inx_overflow() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0xff, 0xaa, 0xe8, 0xe8, 0x00]);
-        assert_eq!(cpu.register_x, 1)
-    }
-
-    #[test]
-    fn test_inx() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xe8]);
-        assert_eq!(cpu.register_x, 0x01);
+    use crate::cpu::*;
+
+    #[test]
+    fn test_register_read_lands_mid_instruction() {
+        //LDA $2002 with vblank starting 7 dots in, before the read on the 4th cycle
+        let status_after_read = |scheduling| {
+            let mut cpu = CPU::new();
+            cpu.load(vec![0xad, 0x02, 0x20]);
+            cpu.reset();
+            cpu.bus.set_scheduling(scheduling);
+            cpu.bus.ppu.scanline = 240;
+            cpu.bus.ppu.dot = 335;
+            assert_eq!(cpu.step(), Ok(4));
+            assert_eq!((cpu.bus.ppu.scanline, cpu.bus.ppu.dot), (241, 6));
+            cpu.register_a & 0x80
+        };
+        assert_eq!(status_after_read(Scheduling::Cycle), 0x80);
+        assert_eq!(status_after_read(Scheduling::CatchUp), 0);
+    }
+
+    #[test]
+    fn test_scheduling_modes_run_the_same_cycles() {
+        let mut program = vec![
+            0xa2, 0x01, //LDX #$01
+            0xa0, 0xff, //LDY #$ff
+            0xfe, 0x00, 0x02, //INC $0200,X
+            0x20, 0x20, 0x80, //JSR $8020
+            0xb1, 0x10, //LDA ($10),Y
+            0x48, //PHA
+            0x68, //PLA
+            0xf0, 0x00, //BEQ +0
+            0xa9, 0x02, //LDA #$02
+            0x8d, 0x14, 0x40, //STA $4014
+            0x00, //BRK
+        ];
+        program.resize(0x20, 0x00);
+        program.push(0x60); //RTS
+        let run = |scheduling| {
+            let mut cpu = CPU::new();
+            cpu.load(program.clone());
+            cpu.reset();
+            cpu.bus.set_scheduling(scheduling);
+            cpu.run();
+            (cpu.cycles, cpu.bus.ppu.scanline, cpu.bus.ppu.dot)
+        };
+        let cycles = run(Scheduling::Cycle);
+        assert_eq!(cycles, run(Scheduling::CatchUp));
+        //the bus never runs ahead of the instructions, the OAM DMA is most of the time
+        assert_eq!(cycles.0, 2 + 2 + 7 + 6 + 6 + 5 + 3 + 4 + 3 + 2 + 4 + 513);
+        assert_eq!(cycles.0 * 3, cycles.1 as u64 * 341 + cycles.2 as u64);
     }
 }
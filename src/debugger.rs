@@ -0,0 +1,779 @@
+use crate::cheats::{Cheat, RamSearch, SearchFilter};
+use crate::cpu::disasm::{disassemble, disassemble_with_labels, Instruction};
+use crate::cpu::op_codes::OPCODES_MAP;
+use crate::cpu::{
+    CpuError, BREAK_FLAG, CARRY_FLAG, CPU, DECIMAL_MODE_FLAG, INTERRUPT_DISABLE_FLAG,
+    NEGATIVE_FLAG, OVERFLOW_FLAG, ZERO_FLAG,
+};
+use crate::headless::parse_hex;
+use crate::nes::Nes;
+use crate::symbols::Symbols;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+//how long `continue` runs without hitting anything before giving control back
+pub const DEFAULT_CONTINUE_FRAMES: u32 = 600;
+const DISASSEMBLY_LINES: usize = 10;
+const TRACE_LINES: usize = 20;
+//RAM search results are only listed once there are this few
+const SEARCH_LINES: usize = 20;
+const FLAG_NAMES: [(char, u8); 7] = [
+    ('n', NEGATIVE_FLAG),
+    ('v', OVERFLOW_FLAG),
+    ('b', BREAK_FLAG),
+    ('d', DECIMAL_MODE_FLAG),
+    ('i', INTERRUPT_DISABLE_FLAG),
+    ('z', ZERO_FLAG),
+    ('c', CARRY_FLAG),
+];
+
+pub const HELP: &str = "\
+step [N]            execute N instructions (s)
+next                step over subroutine calls (n)
+finish              run until the current subroutine returns
+continue [FRAMES]   run until a breakpoint or watchpoint (c)
+until ADDR          run to ADDR (u)
+break [ADDR]        set a breakpoint, list them without ADDR (b)
+delete ADDR|all     remove breakpoints
+watch ADDR[-ADDR] [r|w|rw]
+                    stop on memory accesses (w)
+unwatch N|all       remove a watchpoint
+regs                show registers (r)
+set REG VALUE       change a, x, y, sp, pc or p
+flag NAME 0|1       change one of the n v b d i z c flags
+x ADDR [LEN]        hexdump memory
+poke ADDR BYTE...   write memory
+disasm [ADDR] [N]   disassemble, around PC by default (d)
+bt                  call stack
+trace [N]           execute N instructions, printing each one
+symbols FILE        load labels from a .dbg, .mlb or .nl file
+cheat [CODE [NAME]] add a Game Genie or ADDR:VALUE cheat, list them without CODE
+cheat on|off N      enable or disable a cheat
+cheat save [FILE]   save the cheats for this ROM
+uncheat N|all       remove cheats
+search [new|= VALUE|<|>|changed|same]
+                    find RAM addresses by filtering snapshots, list them without
+                    a filter
+help                this text (h)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Subroutine,
+    Interrupt,
+}
+
+/// One entry of the call stack, `stack_pointer` is the value right after the return address was pushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind: CallKind,
+    pub call_site: u16,
+    pub target: u16,
+    pub stack_pointer: u8,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Step,
+    Breakpoint(u16),
+    Watchpoint {
+        index: usize,
+        addr: u16,
+        value: u8,
+        write: bool,
+    },
+    FrameLimit,
+    Error(CpuError),
+}
+
+/// Debugger state, commands are run against a console with `execute`.
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: BTreeSet<u16>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub symbols: Symbols,
+    //where `cheat save` writes by default
+    pub cheat_file: Option<PathBuf>,
+    call_stack: Vec<CallFrame>,
+    search: Option<RamSearch>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+
+    /// Executes one instruction and keeps the call stack up to date.
+    pub fn step(&mut self, nes: &mut Nes) -> Result<bool, CpuError> {
+        let pc = nes.cpu.program_counter;
+        let sp = nes.cpu.stack_pointer;
+        let opcode = nes.cpu.bus.peek(pc);
+        let frame_complete = nes.step_instruction()?;
+        track_call(&mut self.call_stack, &nes.cpu, pc, sp, opcode);
+        Ok(frame_complete)
+    }
+
+    /// Runs until `done` holds after an instruction, a breakpoint or watchpoint is hit, or
+    /// `max_frames` frames have passed. The first instruction never stops on a breakpoint.
+    pub fn run<F: Fn(&Debugger, &Nes) -> bool>(
+        &mut self,
+        nes: &mut Nes,
+        max_frames: u32,
+        done: F,
+    ) -> StopReason {
+        nes.cpu.bus.set_access_logging(!self.watchpoints.is_empty());
+        let reason = self.run_logged(nes, max_frames, done);
+        nes.cpu.bus.set_access_logging(false);
+        reason
+    }
+
+    fn run_logged<F: Fn(&Debugger, &Nes) -> bool>(
+        &mut self,
+        nes: &mut Nes,
+        max_frames: u32,
+        done: F,
+    ) -> StopReason {
+        let mut frames = 0;
+        loop {
+            match self.step(nes) {
+                Ok(true) => frames += 1,
+                Ok(false) => {}
+                Err(e) => return StopReason::Error(e),
+            }
+            if let Some(reason) = self.watchpoint_hit(nes) {
+                return reason;
+            }
+            if done(self, nes) {
+                return StopReason::Step;
+            }
+            let pc = nes.cpu.program_counter;
+            if self.breakpoints.contains(&pc) {
+                return StopReason::Breakpoint(pc);
+            }
+            if frames >= max_frames {
+                return StopReason::FrameLimit;
+            }
+        }
+    }
+
+    fn watchpoint_hit(&self, nes: &mut Nes) -> Option<StopReason> {
+        for access in nes.cpu.bus.take_accesses() {
+            let hit = self.watchpoints.iter().position(|w| {
+                w.range.contains(&access.addr) && if access.write { w.write } else { w.read }
+            });
+            if let Some(index) = hit {
+                return Some(StopReason::Watchpoint {
+                    index,
+                    addr: access.addr,
+                    value: access.value,
+                    write: access.write,
+                });
+            }
+        }
+        None
+    }
+
+    /// Runs one command line and returns what to print.
+    pub fn execute(&mut self, nes: &mut Nes, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(String::new()),
+        };
+        let args: Vec<&str> = words.collect();
+        let arg = |i: usize| args.get(i).copied();
+
+        let reason = match command {
+            "s" | "step" => {
+                let count = parse_count(arg(0), 1)?;
+                let mut reason = StopReason::Step;
+                for _ in 0..count {
+                    reason = self.run(nes, u32::MAX, |_, _| true);
+                    if reason != StopReason::Step {
+                        break;
+                    }
+                }
+                reason
+            }
+            "n" | "next" => {
+                let depth = self.call_stack.len();
+                if nes.cpu.bus.peek(nes.cpu.program_counter) == 0x20 {
+                    self.run(nes, DEFAULT_CONTINUE_FRAMES, move |d, _| {
+                        d.call_stack.len() <= depth
+                    })
+                } else {
+                    self.run(nes, u32::MAX, |_, _| true)
+                }
+            }
+            "finish" => {
+                let depth = self.call_stack.len();
+                if depth == 0 {
+                    return Err("Not inside a subroutine".to_string());
+                }
+                self.run(nes, DEFAULT_CONTINUE_FRAMES, move |d, _| {
+                    d.call_stack.len() < depth
+                })
+            }
+            "c" | "continue" => {
+                let frames = parse_count(arg(0), DEFAULT_CONTINUE_FRAMES as usize)? as u32;
+                self.run(nes, frames, |_, _| false)
+            }
+            "u" | "until" => {
+                let target = self.parse_addr(nes, arg(0))?;
+                self.run(nes, DEFAULT_CONTINUE_FRAMES, move |_, nes| {
+                    nes.cpu.program_counter == target
+                })
+            }
+            "b" | "break" => {
+                return match arg(0) {
+                    Some(addr) => {
+                        let addr = self.parse_addr(nes, Some(addr))?;
+                        self.breakpoints.insert(addr);
+                        Ok(format!("Breakpoint at ${:04x}\n", addr))
+                    }
+                    None => Ok(self
+                        .breakpoints
+                        .iter()
+                        .map(|addr| format!("${:04x}\n", addr))
+                        .collect()),
+                };
+            }
+            "delete" => {
+                match arg(0) {
+                    Some("all") => self.breakpoints.clear(),
+                    addr => {
+                        let addr = self.parse_addr(nes, addr)?;
+                        if !self.breakpoints.remove(&addr) {
+                            return Err(format!("No breakpoint at ${:04x}", addr));
+                        }
+                    }
+                }
+                return Ok(String::new());
+            }
+            "w" | "watch" => return self.add_watchpoint(nes, arg(0), arg(1)),
+            "unwatch" => {
+                match arg(0) {
+                    Some("all") => self.watchpoints.clear(),
+                    None => return Err("unwatch needs a watchpoint number or all".to_string()),
+                    index => {
+                        let index = parse_count(index, usize::MAX)?;
+                        if index >= self.watchpoints.len() {
+                            return Err(format!("No watchpoint {}", index));
+                        }
+                        self.watchpoints.remove(index);
+                    }
+                }
+                return Ok(String::new());
+            }
+            "r" | "regs" => return Ok(registers(nes)),
+            "set" => {
+                let value = parse_addr(arg(1))?;
+                let cpu = &mut nes.cpu;
+                match arg(0) {
+                    Some("a") => cpu.register_a = value as u8,
+                    Some("x") => cpu.register_x = value as u8,
+                    Some("y") => cpu.register_y = value as u8,
+                    Some("sp") => cpu.stack_pointer = value as u8,
+                    Some("p") => cpu.status = value as u8,
+                    Some("pc") => cpu.program_counter = value,
+                    _ => return Err("Unknown register, use a, x, y, sp, pc or p".to_string()),
+                }
+                return Ok(registers(nes));
+            }
+            "flag" => {
+                let flag = arg(0)
+                    .and_then(|name| {
+                        FLAG_NAMES
+                            .iter()
+                            .find(|(c, _)| name.eq_ignore_ascii_case(&c.to_string()))
+                    })
+                    .ok_or_else(|| "Unknown flag, use n, v, b, d, i, z or c".to_string())?;
+                match arg(1) {
+                    Some("1") => nes.cpu.status |= flag.1,
+                    Some("0") => nes.cpu.status &= !flag.1,
+                    _ => return Err("Flags are set with 0 or 1".to_string()),
+                }
+                return Ok(registers(nes));
+            }
+            "x" => {
+                let addr = self.parse_addr(nes, arg(0))?;
+                let len = match arg(1) {
+                    Some(len) => parse_addr(Some(len))? as usize,
+                    None => 0x40,
+                };
+                return Ok(hexdump(nes, addr, len));
+            }
+            "poke" => {
+                let addr = self.parse_addr(nes, arg(0))?;
+                if args.len() < 2 {
+                    return Err("poke needs at least one byte".to_string());
+                }
+                for (i, byte) in args[1..].iter().enumerate() {
+                    let byte = parse_hex(byte)
+                        .filter(|&b| b <= 0xff)
+                        .ok_or_else(|| format!("Invalid byte {}", byte))?;
+                    nes.cpu
+                        .bus
+                        .mem_write(addr.wrapping_add(i as u16), byte as u8);
+                }
+                return Ok(String::new());
+            }
+            "d" | "disasm" => {
+                let pc = nes.cpu.program_counter;
+                let start = match arg(0) {
+                    Some(addr) => self.parse_addr(nes, Some(addr))?,
+                    None => disassembly_start(nes, pc),
+                };
+                let count = parse_count(arg(1), DISASSEMBLY_LINES)?;
+                return Ok(self.disassembly(nes, start, count));
+            }
+            "bt" => return Ok(self.backtrace(nes)),
+            "trace" => {
+                let count = parse_count(arg(0), TRACE_LINES)?;
+                let mut out = String::new();
+                let mut reason = StopReason::Step;
+                for _ in 0..count {
+                    out.push_str(&self.trace_line(nes));
+                    reason = self.run(nes, u32::MAX, |_, _| true);
+                    if reason != StopReason::Step {
+                        break;
+                    }
+                }
+                if reason != StopReason::Step {
+                    out.push_str(&self.describe_stop(nes, reason));
+                }
+                return Ok(out);
+            }
+            "symbols" => {
+                let path = arg(0).ok_or_else(|| "symbols needs a file".to_string())?;
+                let before = self.symbols.len();
+                self.symbols.load(Path::new(path))?;
+                return Ok(format!("Loaded {} labels\n", self.symbols.len() - before));
+            }
+            "cheat" => return self.cheat(nes, &args),
+            "uncheat" => {
+                let cheats = &mut nes.cpu.bus.cheats.cheats;
+                match arg(0) {
+                    Some("all") => cheats.clear(),
+                    index => {
+                        let index = parse_count(index, usize::MAX)?;
+                        if index >= cheats.len() {
+                            return Err(format!("No cheat {}", index));
+                        }
+                        cheats.remove(index);
+                    }
+                }
+                return Ok(String::new());
+            }
+            "search" => return self.search(nes, &args),
+            "h" | "help" => return Ok(HELP.to_string()),
+            _ => return Err(format!("Unknown command {}, try help", command)),
+        };
+        Ok(self.describe_stop(nes, reason))
+    }
+
+    fn cheat(&mut self, nes: &mut Nes, args: &[&str]) -> Result<String, String> {
+        let cheats = &mut nes.cpu.bus.cheats;
+        match args {
+            [] => {
+                let mut out = String::new();
+                for (i, cheat) in cheats.cheats.iter().enumerate() {
+                    let compare = match cheat.compare {
+                        Some(compare) => format!(" if {:02x}", compare),
+                        None => String::new(),
+                    };
+                    writeln!(
+                        out,
+                        "{}: {} {} ${:04x} = {:02x}{} {}",
+                        i,
+                        if cheat.enabled { "on " } else { "off" },
+                        cheat.code,
+                        cheat.addr,
+                        cheat.value,
+                        compare,
+                        cheat.description
+                    )
+                    .unwrap();
+                }
+                Ok(out)
+            }
+            [state @ ("on" | "off"), index] => {
+                let index = parse_count(Some(index), usize::MAX)?;
+                let cheat = cheats
+                    .cheats
+                    .get_mut(index)
+                    .ok_or_else(|| format!("No cheat {}", index))?;
+                cheat.enabled = *state == "on";
+                Ok(String::new())
+            }
+            ["save", file @ ..] => {
+                let path = match file.first() {
+                    Some(file) => PathBuf::from(file),
+                    None => self
+                        .cheat_file
+                        .clone()
+                        .ok_or_else(|| "cheat save needs a file".to_string())?,
+                };
+                cheats
+                    .save(&path)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                Ok(format!(
+                    "Saved {} cheats to {}\n",
+                    cheats.cheats.len(),
+                    path.display()
+                ))
+            }
+            [code, name @ ..] => {
+                let mut cheat = Cheat::parse(code)?;
+                cheat.description = name.join(" ");
+                let out = format!(
+                    "{}: ${:04x} = {:02x}\n",
+                    cheats.cheats.len(),
+                    cheat.addr,
+                    cheat.value
+                );
+                cheats.add(cheat);
+                Ok(out)
+            }
+        }
+    }
+
+    fn search(&mut self, nes: &mut Nes, args: &[&str]) -> Result<String, String> {
+        let filter = match args {
+            ["new"] => {
+                self.search = Some(RamSearch::new(nes));
+                None
+            }
+            [] => None,
+            ["=", value] | ["==", value] => Some(SearchFilter::Equal(
+                parse_hex(value)
+                    .filter(|&v| v <= 0xff)
+                    .ok_or_else(|| format!("Invalid byte {}", value))? as u8,
+            )),
+            ["<"] => Some(SearchFilter::Less),
+            [">"] => Some(SearchFilter::Greater),
+            ["changed"] => Some(SearchFilter::Changed),
+            ["same"] => Some(SearchFilter::Unchanged),
+            _ => return Err("Unknown search, try help".to_string()),
+        };
+        let search = self
+            .search
+            .as_mut()
+            .ok_or_else(|| "Start a search with search new".to_string())?;
+        if let Some(filter) = filter {
+            search.filter(nes, filter);
+        }
+        let candidates = search.candidates().to_vec();
+        let mut out = format!("{} addresses\n", candidates.len());
+        if candidates.len() <= SEARCH_LINES {
+            for (addr, value) in candidates {
+                writeln!(out, "{} = {:02x}", self.describe_addr(nes, addr), value).unwrap();
+            }
+        }
+        Ok(out)
+    }
+
+    fn add_watchpoint(
+        &mut self,
+        nes: &Nes,
+        range: Option<&str>,
+        kind: Option<&str>,
+    ) -> Result<String, String> {
+        let range = range.ok_or_else(|| "watch needs an address".to_string())?;
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (
+                self.parse_addr(nes, Some(start))?,
+                self.parse_addr(nes, Some(end))?,
+            ),
+            None => {
+                let addr = self.parse_addr(nes, Some(range))?;
+                (addr, addr)
+            }
+        };
+        if start > end {
+            return Err(format!(
+                "Watch range ${:04x}-${:04x} ends before it starts",
+                start, end
+            ));
+        }
+        let (read, write) = match kind.unwrap_or("rw") {
+            "r" => (true, false),
+            "w" => (false, true),
+            "rw" => (true, true),
+            kind => return Err(format!("Unknown access {}, use r, w or rw", kind)),
+        };
+        self.watchpoints.push(Watchpoint {
+            range: start..=end,
+            read,
+            write,
+        });
+        Ok(format!(
+            "Watchpoint {} on ${:04x}-${:04x}\n",
+            self.watchpoints.len() - 1,
+            start,
+            end
+        ))
+    }
+
+    fn describe_stop(&self, nes: &Nes, reason: StopReason) -> String {
+        let mut out = match reason {
+            StopReason::Step => String::new(),
+            StopReason::Breakpoint(addr) => {
+                format!("Breakpoint at {}\n", self.describe_addr(nes, addr))
+            }
+            StopReason::Watchpoint {
+                index,
+                addr,
+                value,
+                write,
+            } => format!(
+                "Watchpoint {}: {} ${:04x} = ${:02x}\n",
+                index,
+                if write { "write" } else { "read" },
+                addr,
+                value
+            ),
+            StopReason::FrameLimit => "Stopped after the frame limit\n".to_string(),
+            StopReason::Error(e) => format!("{}\n", e),
+        };
+        let pc = nes.cpu.program_counter;
+        out.push_str(&self.disassembly(nes, pc, 1));
+        out
+    }
+
+    fn disassembly(&self, nes: &Nes, start: u16, count: usize) -> String {
+        let pc = nes.cpu.program_counter;
+        let mut out = String::new();
+        let mut addr = start;
+        for _ in 0..count {
+            let instruction = self.disassemble(nes, addr);
+            if let Some(label) = self.symbols.label_at(&nes.cpu.bus, addr) {
+                writeln!(out, "{}:", label).unwrap();
+            }
+            let marker = match (addr == pc, self.breakpoints.contains(&addr)) {
+                (true, _) => '>',
+                (false, true) => '*',
+                (false, false) => ' ',
+            };
+            writeln!(
+                out,
+                "{} {}{}",
+                marker,
+                format_instruction(&instruction),
+                self.source_comment(nes, addr)
+            )
+            .unwrap();
+            addr = instruction.next_addr();
+        }
+        out
+    }
+
+    fn backtrace(&self, nes: &Nes) -> String {
+        let mut out = format!("#0 {}\n", self.describe_addr(nes, nes.cpu.program_counter));
+        for (i, frame) in self.call_stack.iter().rev().enumerate() {
+            let kind = match frame.kind {
+                CallKind::Subroutine => "JSR",
+                CallKind::Interrupt => "interrupt",
+            };
+            writeln!(
+                out,
+                "#{} {} from {} ({})",
+                i + 1,
+                self.describe_addr(nes, frame.target),
+                self.describe_addr(nes, frame.call_site),
+                kind
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    fn disassemble(&self, nes: &Nes, addr: u16) -> Instruction {
+        let bus = &nes.cpu.bus;
+        disassemble_with_labels(|a| bus.peek(a), addr, |a| self.symbols.label(bus, a))
+    }
+
+    /// The instruction at PC with the registers before it runs, as one line of a trace log.
+    pub fn trace_line(&self, nes: &Nes) -> String {
+        let cpu = &nes.cpu;
+        let pc = cpu.program_counter;
+        let mut out = String::new();
+        if let Some(label) = self.symbols.label_at(&cpu.bus, pc) {
+            writeln!(out, "{}:", label).unwrap();
+        }
+        writeln!(
+            out,
+            "{:<32} A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x}{}",
+            format_instruction(&self.disassemble(nes, pc)),
+            cpu.register_a,
+            cpu.register_x,
+            cpu.register_y,
+            cpu.status,
+            cpu.stack_pointer,
+            self.source_comment(nes, pc)
+        )
+        .unwrap();
+        out
+    }
+
+    //"$c0a3 <name>", or just the address without a label
+    fn describe_addr(&self, nes: &Nes, addr: u16) -> String {
+        match self.symbols.label(&nes.cpu.bus, addr) {
+            Some(label) => format!("${:04x} <{}>", addr, label),
+            None => format!("${:04x}", addr),
+        }
+    }
+
+    fn source_comment(&self, nes: &Nes, addr: u16) -> String {
+        match self.symbols.source_line(&nes.cpu.bus, addr) {
+            Some(source) => format!("  ; {}:{}", source.file, source.line),
+            None => String::new(),
+        }
+    }
+
+    //a label name or a hex address
+    fn parse_addr(&self, nes: &Nes, arg: Option<&str>) -> Result<u16, String> {
+        match arg.and_then(|name| self.symbols.address(&nes.cpu.bus, name)) {
+            Some(addr) => Ok(addr),
+            None => parse_addr(arg),
+        }
+    }
+}
+
+/// Updates `stack` after the instruction `opcode` at `pc` ran with the stack pointer at `sp`.
+pub fn track_call(stack: &mut Vec<CallFrame>, cpu: &CPU, pc: u16, sp: u8, opcode: u8) {
+    let new_sp = cpu.stack_pointer;
+    //anything that unwound the stack past a frame returned from it, this covers
+    //RTS and RTI as well as games that drop return addresses or reset the stack
+    while stack
+        .last()
+        .is_some_and(|frame| frame.stack_pointer < new_sp)
+    {
+        stack.pop();
+    }
+    let kind = if cpu.interrupted() {
+        //NMI or IRQ, `opcode` is the instruction they came before
+        Some(CallKind::Interrupt)
+    } else {
+        match (opcode, sp.wrapping_sub(new_sp)) {
+            (0x20, 2) => Some(CallKind::Subroutine),
+            (0x00, 3) => Some(CallKind::Interrupt),
+            _ => None,
+        }
+    };
+    if let Some(kind) = kind {
+        stack.push(CallFrame {
+            kind,
+            call_site: pc,
+            target: cpu.program_counter,
+            stack_pointer: new_sp,
+        });
+    }
+}
+
+fn format_instruction(instruction: &Instruction) -> String {
+    let bytes: Vec<String> = instruction
+        .bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!(
+        "{:04x}  {:<9} {}",
+        instruction.addr,
+        bytes.join(" "),
+        instruction.text
+    )
+}
+
+//a few instructions before `pc`, found by decoding forward from earlier addresses
+//until one of them lines up with `pc` through valid opcodes only
+fn disassembly_start(nes: &Nes, pc: u16) -> u16 {
+    for back in (1..=9u16).rev() {
+        let start = pc.wrapping_sub(back);
+        let mut addr = start;
+        while addr != pc && pc.wrapping_sub(addr) <= back {
+            if !OPCODES_MAP.contains_key(&nes.cpu.bus.peek(addr)) {
+                break;
+            }
+            addr = disassemble(|a| nes.cpu.bus.peek(a), addr).next_addr();
+        }
+        if addr == pc {
+            return start;
+        }
+    }
+    pc
+}
+
+pub fn registers(nes: &Nes) -> String {
+    let cpu = &nes.cpu;
+    let flags: String = FLAG_NAMES
+        .iter()
+        .map(|&(c, flag)| {
+            if cpu.status & flag != 0 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!(
+        "PC:{:04x} A:{:02x} X:{:02x} Y:{:02x} SP:{:02x} P:{:02x} {} CYC:{}\n",
+        cpu.program_counter,
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.stack_pointer,
+        cpu.status,
+        flags,
+        cpu.cycles
+    )
+}
+
+fn hexdump(nes: &Nes, addr: u16, len: usize) -> String {
+    let mut out = String::new();
+    for row in (0..len).step_by(16) {
+        let row_addr = addr.wrapping_add(row as u16);
+        write!(out, "{:04x} ", row_addr).unwrap();
+        let count = (len - row).min(16);
+        let bytes: Vec<u8> = (0..count as u16)
+            .map(|i| nes.cpu.bus.peek(row_addr.wrapping_add(i)))
+            .collect();
+        for byte in bytes.iter() {
+            write!(out, " {:02x}", byte).unwrap();
+        }
+        out.push_str(&"   ".repeat(16 - count));
+        let ascii: String = bytes
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+            .collect();
+        writeln!(out, "  {}", ascii).unwrap();
+    }
+    out
+}
+
+fn parse_addr(arg: Option<&str>) -> Result<u16, String> {
+    let arg = arg.ok_or_else(|| "Missing address".to_string())?;
+    parse_hex(arg).ok_or_else(|| format!("Invalid address {}", arg))
+}
+
+fn parse_count(arg: Option<&str>, default: usize) -> Result<usize, String> {
+    match arg {
+        Some(arg) => arg.parse().map_err(|_| format!("Invalid count {}", arg)),
+        None => Ok(default),
+    }
+}
@@ -0,0 +1,188 @@
+use crate::cpu::disasm::disassemble;
+use crate::cpu::CARRY_FLAG;
+use crate::debugger::*;
+use crate::test_util::test_nes;
+
+//8000 LDX #$00
+//8002 JSR $800c
+//8005 INX
+//8006 STX $20
+//8008 JMP $8002
+//800c LDA #$05
+//800e STA $10
+//8010 RTS
+const PROGRAM: [u8; 17] = [
+    0xa2, 0x00, 0x20, 0x0c, 0x80, 0xe8, 0x86, 0x20, 0x4c, 0x02, 0x80, 0xea, 0xa9, 0x05, 0x85, 0x10,
+    0x60,
+];
+
+#[test]
+fn test_disassemble() {
+    let memory = [
+        0x20, 0x0c, 0x80, 0xd0, 0xfc, 0x0a, 0x6c, 0x34, 0x12, 0xb1, 0x10, 0x02,
+    ];
+    let read = |addr: u16| memory[addr as usize];
+    assert_eq!(disassemble(read, 0).text, "JSR $800c");
+    assert_eq!(disassemble(read, 3).text, "BNE $0001");
+    assert_eq!(disassemble(read, 5).text, "ASL A");
+    assert_eq!(disassemble(read, 6).text, "JMP ($1234)");
+    assert_eq!(disassemble(read, 9).text, "LDA ($10),Y");
+    assert_eq!(disassemble(read, 11).text, ".db $02");
+}
+
+#[test]
+fn test_step_into_and_finish() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    debugger.execute(&mut nes, "step 2").unwrap();
+    assert_eq!(nes.cpu.program_counter, 0x800c);
+    assert_eq!(debugger.call_stack().len(), 1);
+    assert!(debugger
+        .execute(&mut nes, "bt")
+        .unwrap()
+        .contains("$800c from $8002"));
+
+    debugger.execute(&mut nes, "finish").unwrap();
+    assert_eq!(nes.cpu.program_counter, 0x8005);
+    assert!(debugger.call_stack().is_empty());
+    assert!(debugger.execute(&mut nes, "finish").is_err());
+}
+
+#[test]
+fn test_interrupts_on_the_call_stack() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut stack = Vec::new();
+    //TXS moving the stack down by 3 is not an interrupt
+    nes.cpu.stack_pointer = 0xfa;
+    track_call(&mut stack, &nes.cpu, 0x8000, 0xfd, 0x9a);
+    assert!(stack.is_empty());
+    track_call(&mut stack, &nes.cpu, 0x8000, 0xfd, 0x00);
+    assert_eq!(stack[0].kind, CallKind::Interrupt);
+
+    //NMI at the next vblank, the vector points into the NOPs
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    nes.cpu.bus.mem_write(0x2000, 0x80);
+    let reason = debugger.run(&mut nes, 2, |debugger, _| {
+        debugger
+            .call_stack()
+            .iter()
+            .any(|frame| frame.kind == CallKind::Interrupt)
+    });
+    assert_eq!(reason, StopReason::Step);
+    let frame = debugger.call_stack().last().unwrap();
+    assert_eq!(frame.kind, CallKind::Interrupt);
+    assert_eq!(frame.target, 0xeaea);
+}
+
+#[test]
+fn test_step_over() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    debugger.execute(&mut nes, "s").unwrap();
+    let out = debugger.execute(&mut nes, "next").unwrap();
+    assert_eq!(nes.cpu.program_counter, 0x8005);
+    assert!(out.contains("> 8005  e8        INX"));
+    assert_eq!(nes.cpu.register_a, 0x05);
+}
+
+#[test]
+fn test_breakpoint_and_run_to_cursor() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    debugger.execute(&mut nes, "b 8006").unwrap();
+    let out = debugger.execute(&mut nes, "c").unwrap();
+    assert!(out.starts_with("Breakpoint at $8006"));
+    assert_eq!(nes.cpu.register_x, 1);
+
+    //continuing leaves the breakpoint and comes back to it on the next iteration
+    debugger.execute(&mut nes, "continue").unwrap();
+    assert_eq!(nes.cpu.register_x, 2);
+
+    debugger.execute(&mut nes, "delete all").unwrap();
+    debugger.execute(&mut nes, "until 8010").unwrap();
+    assert_eq!(nes.cpu.program_counter, 0x8010);
+}
+
+#[test]
+fn test_watchpoint() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    debugger.execute(&mut nes, "watch 10-1f w").unwrap();
+    let out = debugger.execute(&mut nes, "c").unwrap();
+    assert!(out.starts_with("Watchpoint 0: write $0010 = $05"));
+    assert_eq!(nes.cpu.program_counter, 0x8010);
+
+    debugger.execute(&mut nes, "unwatch 0").unwrap();
+    debugger.execute(&mut nes, "watch 20 r").unwrap();
+    assert_eq!(
+        debugger.execute(&mut nes, "c 2").unwrap().lines().next(),
+        Some("Stopped after the frame limit")
+    );
+
+    assert_eq!(
+        debugger.execute(&mut nes, "watch 1f-10"),
+        Err("Watch range $001f-$0010 ends before it starts".to_string())
+    );
+    assert!(debugger.execute(&mut nes, "unwatch").is_err());
+    assert!(debugger.execute(&mut nes, "unwatch 0").is_ok());
+}
+
+#[test]
+fn test_edit_registers_and_memory() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    debugger.execute(&mut nes, "set a 7f").unwrap();
+    debugger.execute(&mut nes, "flag c 1").unwrap();
+    assert_eq!(nes.cpu.register_a, 0x7f);
+    assert_ne!(nes.cpu.status & CARRY_FLAG, 0);
+    assert!(debugger.execute(&mut nes, "r").unwrap().contains("A:7f"));
+
+    debugger.execute(&mut nes, "poke 300 de ad").unwrap();
+    assert!(debugger
+        .execute(&mut nes, "x 300 4")
+        .unwrap()
+        .starts_with("0300  de ad 00 00"));
+    assert!(debugger.execute(&mut nes, "set q 1").is_err());
+    assert!(debugger.execute(&mut nes, "bogus").is_err());
+}
+
+#[test]
+fn test_disassembly_around_pc() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    debugger.execute(&mut nes, "step 3").unwrap();
+    let out = debugger.execute(&mut nes, "d").unwrap();
+    assert!(out.starts_with("  8005  e8        INX"));
+    assert!(out.contains("  8008  4c 02 80  JMP $8002"));
+    assert!(out.contains("> 800e  85 10     STA $10"));
+}
+
+#[test]
+fn test_cheats_and_ram_search() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    assert_eq!(
+        debugger.execute(&mut nes, "cheat 800d:07 Seven").unwrap(),
+        "0: $800d = 07\n"
+    );
+    assert!(debugger.execute(&mut nes, "search >").is_err());
+    debugger.execute(&mut nes, "search new").unwrap();
+    debugger.execute(&mut nes, "until 8008").unwrap();
+    assert_eq!(nes.cpu.bus.peek(0x10), 0x07);
+
+    debugger.execute(&mut nes, "search >").unwrap();
+    assert_eq!(
+        debugger.execute(&mut nes, "search = 1").unwrap(),
+        "1 addresses\n$0020 = 01\n"
+    );
+
+    debugger.execute(&mut nes, "cheat off 0").unwrap();
+    assert_eq!(
+        debugger.execute(&mut nes, "cheat").unwrap(),
+        "0: off 800D:07 $800d = 07 Seven\n"
+    );
+    assert!(debugger.execute(&mut nes, "cheat save").is_err());
+    debugger.execute(&mut nes, "uncheat all").unwrap();
+    assert!(nes.cpu.bus.cheats.is_empty());
+}
@@ -0,0 +1,30 @@
+/// Picture output of the PPU. Each pixel is a 6 bit palette index with the
+/// three emphasis bits from PPUMASK in bits 6-8, conversion to RGB happens later.
+pub struct Frame {
+    pub data: Vec<u16>,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame::new()
+    }
+}
+
+impl Frame {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 240;
+
+    pub fn new() -> Self {
+        Frame {
+            data: vec![0; Frame::WIDTH * Frame::HEIGHT],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: u16) {
+        self.data[y * Frame::WIDTH + x] = pixel;
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> u16 {
+        self.data[y * Frame::WIDTH + x]
+    }
+}
@@ -0,0 +1,239 @@
+use crate::MovieMode;
+use nes_emulator::frame::Frame;
+use nes_emulator::joypad::JoypadButton;
+use nes_emulator::movie::{
+    self, MovieCommand, MoviePlayer, MovieRecorder, DEFAULT_CHECKPOINT_INTERVAL,
+};
+use nes_emulator::nes::Nes;
+use nes_emulator::ntsc::NtscFilter;
+use nes_emulator::palette::Palette;
+use nes_emulator::rewind::Rewind;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const AUDIO_SAMPLE_RATE: i32 = 44_100;
+//the audio queue is kept around this many samples, ~46 ms
+const AUDIO_TARGET_QUEUE: f64 = 2048.0;
+//largest change of the resampling ratio, too small to be heard as a pitch change
+const MAX_RATE_DELTA: f64 = 0.005;
+//when emulation falls further behind than this it stops trying to catch up
+const MAX_FRAMES_BEHIND: u32 = 5;
+//frames stepped back per displayed frame while the rewind key is held
+const REWIND_SPEED: u64 = 2;
+
+pub fn run(
+    mut nes: Nes,
+    scale: u32,
+    movie: MovieMode,
+    cdl: Option<PathBuf>,
+    palette: Palette,
+    mut ntsc: Option<NtscFilter>,
+) -> Result<(), String> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let window = video_subsystem
+        .window(
+            "NES Emulator",
+            Frame::WIDTH as u32 * scale,
+            Frame::HEIGHT as u32 * scale,
+        )
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    canvas
+        .set_logical_size(Frame::WIDTH as u32, Frame::HEIGHT as u32)
+        .map_err(|e| e.to_string())?;
+    canvas.set_integer_scale(true)?;
+    //the filter's wider picture is scaled down to the logical size
+    let (width, height) = match ntsc {
+        Some(_) => (NtscFilter::WIDTH, NtscFilter::HEIGHT),
+        None => (Frame::WIDTH, Frame::HEIGHT),
+    };
+    let creator = canvas.texture_creator();
+    let mut texture = creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+        .map_err(|e| e.to_string())?;
+
+    let audio_subsystem = sdl_context.audio()?;
+    let desired_spec = AudioSpecDesired {
+        freq: Some(AUDIO_SAMPLE_RATE),
+        channels: Some(1),
+        samples: Some(512),
+    };
+    let audio_queue: AudioQueue<f32> = audio_subsystem.open_queue(None, &desired_spec)?;
+    let output_rate = audio_queue.spec().freq as f64;
+    nes.set_audio_sample_rate(output_rate);
+    audio_queue.resume();
+
+    let key_map = key_map();
+    let mut rewind = Rewind::default();
+    let mut rewinding = false;
+    let (mut recorder, mut player, record_path) = match movie {
+        MovieMode::Off => (None, None, None),
+        MovieMode::Record { path, rom_filename } => (
+            Some(MovieRecorder::new(
+                &rom_filename,
+                DEFAULT_CHECKPOINT_INTERVAL,
+            )),
+            None,
+            Some(path),
+        ),
+        MovieMode::Play(movie) => (None, Some(MoviePlayer::new(movie)), None),
+    };
+    let mut event_pump = sdl_context.event_pump()?;
+    let mut rgb = vec![0; width * height * 3];
+
+    let mut next_frame = Instant::now();
+
+    'running: loop {
+        let mut commands = MovieCommand::empty();
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => commands |= MovieCommand::SOFT_RESET,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => commands |= MovieCommand::POWER,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => set_button(&mut nes, &key_map, keycode, true),
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => set_button(&mut nes, &key_map, keycode, false),
+                _ => {}
+            }
+        }
+
+        //rewinding is off while a movie records or plays, it would break the movie,
+        //resets only come from the movie while one plays
+        let result = if let Some(player) = player.as_mut().filter(|p| !p.finished()) {
+            player
+                .run_frame(&mut nes)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        } else if let Some(recorder) = recorder.as_mut() {
+            recorder
+                .run_frame(&mut nes, commands)
+                .map_err(|e| e.to_string())
+        } else if rewinding {
+            rewind.rewind(&mut nes, REWIND_SPEED).map(|_| ())
+        } else {
+            movie::apply_commands(&mut nes, commands);
+            let result = nes.run_frame().map_err(|e| e.to_string());
+            rewind.record(&nes);
+            result
+        };
+        if let Err(e) = result {
+            save_movie(recorder, record_path)?;
+            save_code_data_log(&nes, cdl)?;
+            return Err(e);
+        }
+
+        match ntsc.as_mut() {
+            Some(ntsc) => ntsc.filter(nes.frame(), &mut rgb),
+            None => palette.frame_to_rgb(nes.frame(), &mut rgb),
+        }
+        texture
+            .update(None, &rgb, width * 3)
+            .map_err(|e| e.to_string())?;
+        canvas.clear();
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+
+        audio_queue.queue_audio(&nes.take_audio_samples())?;
+        nes.set_audio_sample_rate(output_rate * rate_control(&audio_queue));
+
+        //after the frame, a PAL movie switches the region on its first one
+        let frame_duration = Duration::from_secs_f64(1.0 / nes.frame_rate());
+        next_frame += frame_duration;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else if now - next_frame > frame_duration * MAX_FRAMES_BEHIND {
+            next_frame = now;
+        }
+    }
+
+    save_movie(recorder, record_path)?;
+    save_code_data_log(&nes, cdl)
+}
+
+fn save_code_data_log(nes: &Nes, path: Option<PathBuf>) -> Result<(), String> {
+    match path {
+        Some(path) => nes
+            .save_code_data_log(&path)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e)),
+        None => Ok(()),
+    }
+}
+
+fn save_movie(recorder: Option<MovieRecorder>, path: Option<PathBuf>) -> Result<(), String> {
+    match (recorder, path) {
+        (Some(recorder), Some(path)) => recorder
+            .finish()
+            .save(&path)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e)),
+        _ => Ok(()),
+    }
+}
+
+// Dynamic rate control: the APU produces slightly more samples when the queue
+// runs low and slightly fewer when it fills up, so the queue neither underruns
+// (crackling) nor grows without bound, even though 60.0988 Hz emulation and the
+// sound card clock drift apart.
+fn rate_control(audio_queue: &AudioQueue<f32>) -> f64 {
+    let queued = (audio_queue.size() as usize / std::mem::size_of::<f32>()) as f64;
+    let fill = (queued / (2.0 * AUDIO_TARGET_QUEUE)).min(1.0);
+    1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill)
+}
+
+fn set_button(
+    nes: &mut Nes,
+    key_map: &HashMap<Keycode, JoypadButton>,
+    keycode: Keycode,
+    pressed: bool,
+) {
+    if let (Some(button), Some(joypad)) = (key_map.get(&keycode), nes.joypad_mut(0)) {
+        joypad.set_button_pressed_status(*button, pressed);
+    }
+}
+
+fn key_map() -> HashMap<Keycode, JoypadButton> {
+    let mut key_map = HashMap::new();
+    key_map.insert(Keycode::Down, JoypadButton::DOWN);
+    key_map.insert(Keycode::Up, JoypadButton::UP);
+    key_map.insert(Keycode::Right, JoypadButton::RIGHT);
+    key_map.insert(Keycode::Left, JoypadButton::LEFT);
+    key_map.insert(Keycode::Space, JoypadButton::SELECT);
+    key_map.insert(Keycode::Return, JoypadButton::START);
+    key_map.insert(Keycode::A, JoypadButton::BUTTON_A);
+    key_map.insert(Keycode::S, JoypadButton::BUTTON_B);
+    key_map
+}
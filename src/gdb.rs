@@ -0,0 +1,408 @@
+//! GDB remote serial protocol stub for the CPU.
+//!
+//! Registers are numbered A=0, X=1, Y=2, P=3, SP=4, PC=5. The `g` packet sends them in
+//! that order, one byte each except PC which is two bytes little endian. Memory is the
+//! 64 KiB CPU address space, reads go through `Bus::peek` so they never touch I/O state.
+//!
+//! Supported packets: `?`, `g`, `G`, `p`, `P`, `m`, `M`, `s`, `c`, `Z0`-`Z4`, `z0`-`z4`,
+//! `k`, `D`, `H`, `qSupported`, `qAttached` and `QStartNoAckMode`. Ctrl-C stops a `c`.
+
+use crate::debugger::{Debugger, StopReason, Watchpoint};
+use crate::nes::Nes;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+#[cfg(test)]
+mod tests;
+
+pub const DEFAULT_PORT: u16 = 6502;
+//largest packet advertised in qSupported, and the bytes an `m` reply fits in it
+//next to the `$`, `#` and checksum
+const PACKET_SIZE: usize = 0x4000;
+const MAX_READ_LEN: usize = (PACKET_SIZE - 4) / 2;
+const REGISTER_COUNT: usize = 6;
+const PC_REGISTER: usize = 5;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+enum Incoming {
+    Packet(String),
+    Interrupt,
+    Closed,
+}
+
+/// Waits for one debugger to connect and serves it until it detaches or disconnects.
+pub fn serve(nes: &mut Nes, listener: &TcpListener) -> io::Result<()> {
+    let (stream, _) = listener.accept()?;
+    GdbSession::new(nes, stream).run()
+}
+
+/// One client connection, the console only runs while a `s` or `c` is in progress.
+pub struct GdbSession<'a> {
+    nes: &'a mut Nes,
+    debugger: Debugger,
+    stream: TcpStream,
+    no_ack: bool,
+    //bytes received but not yet parsed
+    buffer: Vec<u8>,
+}
+
+impl<'a> GdbSession<'a> {
+    pub fn new(nes: &'a mut Nes, stream: TcpStream) -> Self {
+        GdbSession {
+            nes,
+            debugger: Debugger::new(),
+            stream,
+            no_ack: false,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        self.stream.set_nodelay(true)?;
+        loop {
+            let packet = match self.read_packet()? {
+                Incoming::Packet(packet) => packet,
+                //already stopped, just report it
+                Incoming::Interrupt => {
+                    self.send(&format!("S{:02x}", SIGINT))?;
+                    continue;
+                }
+                Incoming::Closed => return Ok(()),
+            };
+            match packet.as_str() {
+                "k" => return Ok(()),
+                "D" => return self.send("OK"),
+                "QStartNoAckMode" => {
+                    self.send("OK")?;
+                    self.no_ack = true;
+                }
+                _ => {
+                    let response = self.handle(&packet)?;
+                    self.send(&response)?;
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        let args = packet.get(1..).unwrap_or("");
+        let response = match packet.as_bytes().first() {
+            Some(b'?') => format!("S{:02x}", SIGTRAP),
+            Some(b'g') => self.read_registers(),
+            Some(b'G') => self.write_registers(args),
+            Some(b'p') => self.read_register(args),
+            Some(b'P') => self.write_register(args),
+            Some(b'm') => self.read_memory(args),
+            Some(b'M') => self.write_memory(args),
+            Some(b's') if args.is_empty() => {
+                let reason = self.debugger.run(self.nes, u32::MAX, |_, _| true);
+                self.stop_reply(&reason)
+            }
+            Some(b'c') if args.is_empty() => self.resume()?,
+            Some(b'Z') => self.set_breakpoint(args, true),
+            Some(b'z') => self.set_breakpoint(args, false),
+            Some(b'H') => "OK".to_string(),
+            Some(b'q') if args.starts_with("Supported") => {
+                format!("PacketSize={:x};QStartNoAckMode+", PACKET_SIZE)
+            }
+            Some(b'q') if args == "Attached" => "1".to_string(),
+            //an empty response tells the client the packet is not supported
+            _ => String::new(),
+        };
+        Ok(response)
+    }
+
+    //runs a frame at a time so a Ctrl-C from the client is noticed
+    fn resume(&mut self) -> io::Result<String> {
+        loop {
+            let reason = self.debugger.run(self.nes, 1, |_, _| false);
+            if reason != StopReason::FrameLimit {
+                return Ok(self.stop_reply(&reason));
+            }
+            if self.poll_interrupt()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    fn stop_reply(&self, reason: &StopReason) -> String {
+        match reason {
+            StopReason::Watchpoint { index, addr, .. } => {
+                let watchpoint = &self.debugger.watchpoints[*index];
+                let kind = match (watchpoint.read, watchpoint.write) {
+                    (false, _) => "watch",
+                    (true, false) => "rwatch",
+                    (true, true) => "awatch",
+                };
+                format!("T{:02x}{}:{:04x};", SIGTRAP, kind, addr)
+            }
+            StopReason::Error(_) => format!("S{:02x}", SIGILL),
+            _ => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    fn registers(&self) -> [u16; REGISTER_COUNT] {
+        let cpu = &self.nes.cpu;
+        [
+            cpu.register_a as u16,
+            cpu.register_x as u16,
+            cpu.register_y as u16,
+            cpu.status as u16,
+            cpu.stack_pointer as u16,
+            cpu.program_counter,
+        ]
+    }
+
+    fn set_register(&mut self, index: usize, value: u16) {
+        let cpu = &mut self.nes.cpu;
+        match index {
+            0 => cpu.register_a = value as u8,
+            1 => cpu.register_x = value as u8,
+            2 => cpu.register_y = value as u8,
+            3 => cpu.status = value as u8,
+            4 => cpu.stack_pointer = value as u8,
+            _ => cpu.program_counter = value,
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        let registers = self.registers();
+        (0..REGISTER_COUNT)
+            .map(|i| encode_register(i, registers[i]))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let bytes = match decode_hex(args) {
+            Some(bytes) if bytes.len() == REGISTER_COUNT + 1 => bytes,
+            _ => return "E01".to_string(),
+        };
+        for (i, &byte) in bytes[..PC_REGISTER].iter().enumerate() {
+            self.set_register(i, byte as u16);
+        }
+        self.set_register(PC_REGISTER, u16::from_le_bytes([bytes[5], bytes[6]]));
+        "OK".to_string()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match usize::from_str_radix(args, 16) {
+            Ok(i) if i < REGISTER_COUNT => encode_register(i, self.registers()[i]),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let (index, value) = match args.split_once('=') {
+            Some(pair) => pair,
+            None => return "E01".to_string(),
+        };
+        let index = match usize::from_str_radix(index, 16) {
+            Ok(i) if i < REGISTER_COUNT => i,
+            _ => return "E01".to_string(),
+        };
+        let width = if index == PC_REGISTER { 2 } else { 1 };
+        match decode_hex(value) {
+            Some(bytes) if bytes.len() == width => {
+                let value = u16::from_le_bytes([bytes[0], bytes.get(1).copied().unwrap_or(0)]);
+                self.set_register(index, value);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let (addr, len) = match parse_range(args) {
+            Some(range) => range,
+            None => return "E01".to_string(),
+        };
+        //a shorter reply than asked for is allowed, the client reads the rest next
+        (0..len.min(MAX_READ_LEN))
+            .map(|i| format!("{:02x}", self.nes.cpu.bus.peek(addr.wrapping_add(i as u16))))
+            .collect()
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let (range, data) = match args.split_once(':') {
+            Some(pair) => pair,
+            None => return "E01".to_string(),
+        };
+        let (addr, len, bytes) = match (parse_range(range), decode_hex(data)) {
+            (Some((addr, len)), Some(bytes)) if bytes.len() == len => (addr, len, bytes),
+            _ => return "E01".to_string(),
+        };
+        for (i, &byte) in bytes.iter().enumerate().take(len) {
+            self.nes
+                .cpu
+                .bus
+                .mem_write(addr.wrapping_add(i as u16), byte);
+        }
+        "OK".to_string()
+    }
+
+    // Z0/Z1 are breakpoints, Z2 write, Z3 read and Z4 access watchpoints, the kind
+    // argument after the address is the length for watchpoints
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let mut fields = args.split(',');
+        let (kind, addr, len) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(kind), Some(addr), Some(len)) => (kind, addr, len),
+            _ => return "E01".to_string(),
+        };
+        let (addr, len) = match (
+            u16::from_str_radix(addr, 16),
+            usize::from_str_radix(len, 16),
+        ) {
+            (Ok(addr), Ok(len)) if len <= 0x10000 => (addr, len.max(1)),
+            _ => return "E01".to_string(),
+        };
+        let (read, write) = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.breakpoints.insert(addr);
+                } else {
+                    self.debugger.breakpoints.remove(&addr);
+                }
+                return "OK".to_string();
+            }
+            "2" => (false, true),
+            "3" => (true, false),
+            "4" => (true, true),
+            _ => return String::new(),
+        };
+        let watchpoint = Watchpoint {
+            //ranges running past $FFFF end there
+            range: addr..=(addr as u32 + len as u32 - 1).min(0xffff) as u16,
+            read,
+            write,
+        };
+        if insert {
+            self.debugger.watchpoints.push(watchpoint);
+        } else if let Some(index) = self
+            .debugger
+            .watchpoints
+            .iter()
+            .position(|w| *w == watchpoint)
+        {
+            self.debugger.watchpoints.remove(index);
+        }
+        "OK".to_string()
+    }
+
+    fn read_packet(&mut self) -> io::Result<Incoming> {
+        loop {
+            if let Some(incoming) = self.parse_buffer()? {
+                return Ok(incoming);
+            }
+            let mut chunk = [0; 1024];
+            let count = match self.stream.read(&mut chunk) {
+                Ok(count) => count,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if count == 0 {
+                return Ok(Incoming::Closed);
+            }
+            self.buffer.extend_from_slice(&chunk[..count]);
+        }
+    }
+
+    //takes the next complete packet or interrupt out of the buffer, skipping acks
+    fn parse_buffer(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            match self.buffer.first() {
+                None => return Ok(None),
+                Some(0x03) => {
+                    self.buffer.remove(0);
+                    return Ok(Some(Incoming::Interrupt));
+                }
+                Some(b'$') => break,
+                //acks, naks and line noise
+                Some(_) => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        let end = match self.buffer.iter().position(|&b| b == b'#') {
+            Some(end) if end + 2 < self.buffer.len() => end,
+            _ => return Ok(None),
+        };
+        let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+        let data = &packet[1..end];
+        let checksum = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|text| u8::from_str_radix(text, 16).ok());
+        if !self.no_ack {
+            let valid = checksum == Some(data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)));
+            self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            if !valid {
+                return Ok(None);
+            }
+        }
+        Ok(Some(Incoming::Packet(
+            String::from_utf8_lossy(data).into_owned(),
+        )))
+    }
+
+    //checks for a Ctrl-C without blocking, other bytes are kept for later
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut chunk = [0; 1024];
+        let result = self.stream.read(&mut chunk);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(count) => self.buffer.extend_from_slice(&chunk[..count]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        match self.buffer.iter().position(|&b| b == 0x03) {
+            Some(index) => {
+                self.buffer.remove(index);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, checksum);
+        self.stream.write_all(packet.as_bytes())?;
+        self.stream.flush()
+    }
+}
+
+fn encode_register(index: usize, value: u16) -> String {
+    if index == PC_REGISTER {
+        let [low, high] = value.to_le_bytes();
+        format!("{:02x}{:02x}", low, high)
+    } else {
+        format!("{:02x}", value as u8)
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect()
+}
+
+//"ADDR,LEN" of the m and M packets
+fn parse_range(text: &str) -> Option<(u16, usize)> {
+    let (addr, len) = text.split_once(',')?;
+    let addr = u32::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    if addr > 0xffff || len > 0x10000 {
+        return None;
+    }
+    Some((addr as u16, len))
+}
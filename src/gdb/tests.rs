@@ -0,0 +1,164 @@
+use crate::gdb::*;
+use crate::nes::Nes;
+use crate::test_util::test_nes;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+//8000 LDX #$00
+//8002 INX
+//8003 STX $20
+//8005 JMP $8002
+const PROGRAM: [u8; 8] = [0xa2, 0x00, 0xe8, 0x86, 0x20, 0x4c, 0x02, 0x80];
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send_raw(&mut self, data: &[u8]) {
+        self.stream.write_all(data).unwrap();
+    }
+
+    fn send(&mut self, packet: &str) {
+        let checksum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        self.send_raw(format!("${}#{:02x}", packet, checksum).as_bytes());
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn receive(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(
+            checksum,
+            data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+        );
+        self.send_raw(b"+");
+        String::from_utf8(data).unwrap()
+    }
+
+    fn command(&mut self, packet: &str) -> String {
+        self.send(packet);
+        assert_eq!(self.read_byte(), b'+');
+        self.receive()
+    }
+}
+
+fn start() -> (Client, thread::JoinHandle<Nes>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut nes = test_nes(&PROGRAM);
+        serve(&mut nes, &listener).unwrap();
+        nes
+    });
+    let stream = TcpStream::connect(addr).unwrap();
+    (Client { stream }, server)
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let (mut client, server) = start();
+    assert!(client.command("qSupported:swbreak+").contains("PacketSize"));
+    assert_eq!(client.command("?"), "S05");
+    //A X Y P SP PC
+    assert_eq!(client.command("g"), "00000024fd0080");
+    assert_eq!(client.command("P0=7f"), "OK");
+    assert_eq!(client.command("p0"), "7f");
+    assert_eq!(client.command("P5=0280"), "OK");
+    assert_eq!(client.command("p5"), "0280");
+    assert_eq!(client.command("G0102032400fd0080"), "E01");
+    assert_eq!(client.command("G01020324fd0080"), "OK");
+    assert_eq!(client.command("g"), "01020324fd0080");
+
+    assert_eq!(client.command("m8000,3"), "a200e8");
+    assert_eq!(client.command("M0010,2:beef"), "OK");
+    assert_eq!(client.command("m0010,2"), "beef");
+    assert_eq!(client.command("mzz,1"), "E01");
+    //capped to the advertised packet size of 0x4000
+    assert_eq!(client.command("m0,10000").len(), 0x4000 - 4);
+    assert_eq!(client.command("vMustReplyEmpty"), "");
+    client.send("D");
+    assert_eq!(client.read_byte(), b'+');
+    assert_eq!(client.receive(), "OK");
+
+    let nes = server.join().unwrap();
+    assert_eq!(nes.cpu.register_a, 0x01);
+    assert_eq!(nes.cpu.bus.peek(0x10), 0xbe);
+}
+
+#[test]
+fn test_step_breakpoint_and_watchpoint() {
+    let (mut client, server) = start();
+    assert_eq!(client.command("s"), "S05");
+    assert_eq!(client.command("p5"), "0280");
+
+    assert_eq!(client.command("Z0,8005,1"), "OK");
+    assert_eq!(client.command("c"), "S05");
+    assert_eq!(client.command("p5"), "0580");
+    assert_eq!(client.command("z0,8005,1"), "OK");
+
+    assert_eq!(client.command("Z2,20,1"), "OK");
+    assert_eq!(client.command("c"), "T05watch:0020;");
+    assert_eq!(client.command("p5"), "0580");
+    assert_eq!(client.command("m20,1"), "02");
+    assert_eq!(client.command("z2,20,1"), "OK");
+
+    assert_eq!(client.command("QStartNoAckMode"), "OK");
+    client.send("k");
+    let nes = server.join().unwrap();
+    assert_eq!(nes.cpu.bus.peek(0x20), 0x02);
+}
+
+#[test]
+fn test_watchpoint_lengths() {
+    let (mut client, server) = start();
+    //the whole address space
+    assert_eq!(client.command("Z2,0,10000"), "OK");
+    assert_eq!(client.command("c"), "T05watch:0020;");
+    assert_eq!(client.command("z2,0,10000"), "OK");
+    assert_eq!(client.command("Z2,0,10001"), "E01");
+    assert_eq!(client.command("Z2,0,100000000"), "E01");
+    //past the end of the address space
+    assert_eq!(client.command("Z3,ff00,200"), "OK");
+    assert_eq!(client.command("z3,ff00,200"), "OK");
+
+    client.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn test_interrupt_while_running() {
+    let (mut client, server) = start();
+    client.send("c");
+    assert_eq!(client.read_byte(), b'+');
+    client.send_raw(&[0x03]);
+    assert_eq!(client.receive(), "S02");
+    assert_eq!(client.command("?"), "S05");
+    drop(client);
+    let nes = server.join().unwrap();
+    assert!(nes.cpu.bus.peek(0x20) > 0);
+}
+
+#[test]
+fn test_bad_checksum_is_nacked() {
+    let (mut client, server) = start();
+    client.send_raw(b"$g#00");
+    assert_eq!(client.read_byte(), b'-');
+    assert_eq!(client.command("p1"), "00");
+    drop(client);
+    server.join().unwrap();
+}
@@ -0,0 +1,322 @@
+use crate::apu::DEFAULT_SAMPLE_RATE;
+use crate::cheats::Cheat;
+use crate::cpu::CpuError;
+use crate::joypad::JoypadButton;
+use crate::movie::{Movie, MovieError, MoviePlayer};
+use crate::nes::Nes;
+use crate::ntsc::{NtscFilter, NtscSettings};
+use crate::palette::Palette;
+use crate::profiler::Profiler;
+use crate::screenshot;
+use crate::symbols::Symbols;
+use crate::wav;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+pub const DEFAULT_FRAMES: u32 = 600;
+
+/// What to run and which results to write when no display is attached.
+pub struct HeadlessOptions {
+    //with a condition this is the most frames to wait for it
+    pub frames: u32,
+    pub until: Option<MemoryCondition>,
+    pub input_script: Option<InputScript>,
+    //played before the input script takes over
+    pub movie: Option<Movie>,
+    pub png: Option<PathBuf>,
+    //what the PNG is drawn with, unless it goes through the composite filter
+    pub palette: Palette,
+    pub ntsc: Option<NtscSettings>,
+    pub wav: Option<PathBuf>,
+    pub ram: Option<PathBuf>,
+    //code/data log, added to when the file exists
+    pub cdl: Option<PathBuf>,
+    //profiler reports, the format is picked by the extension
+    pub profile: Vec<PathBuf>,
+    //names for the profiler reports
+    pub symbols: Symbols,
+    pub cheats: Vec<Cheat>,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        HeadlessOptions::new()
+    }
+}
+
+impl HeadlessOptions {
+    pub fn new() -> Self {
+        HeadlessOptions {
+            frames: DEFAULT_FRAMES,
+            until: None,
+            input_script: None,
+            movie: None,
+            png: None,
+            palette: Palette::new(),
+            ntsc: None,
+            wav: None,
+            ram: None,
+            cdl: None,
+            profile: Vec::new(),
+            symbols: Symbols::new(),
+            cheats: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    Cpu { error: CpuError, frame: u32 },
+    Io(io::Error),
+    ConditionNotMet { frames: u32 },
+    Desync(MovieError),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeadlessError::Cpu { error, frame } => write!(f, "{} (frame {})", error, frame),
+            HeadlessError::Io(e) => write!(f, "{}", e),
+            HeadlessError::ConditionNotMet { frames } => {
+                write!(f, "Condition not met after {} frames", frames)
+            }
+            HeadlessError::Desync(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for HeadlessError {
+    fn from(e: io::Error) -> Self {
+        HeadlessError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+}
+
+/// A stop condition on a CPU address, written as `ADDR==VALUE` or `ADDR!=VALUE` in hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryCondition {
+    pub addr: u16,
+    pub comparison: Comparison,
+    pub value: u8,
+}
+
+impl MemoryCondition {
+    pub fn parse(s: &str) -> Result<MemoryCondition, String> {
+        let (addr, value, comparison) = if let Some((addr, value)) = s.split_once("==") {
+            (addr, value, Comparison::Equal)
+        } else if let Some((addr, value)) = s.split_once("!=") {
+            (addr, value, Comparison::NotEqual)
+        } else {
+            return Err(format!("Invalid condition {}, expected ADDR==VALUE", s));
+        };
+        Ok(MemoryCondition {
+            addr: parse_hex(addr).ok_or_else(|| format!("Invalid address {}", addr))?,
+            comparison,
+            value: parse_hex(value)
+                .and_then(|v| u8::try_from(v).ok())
+                .ok_or_else(|| format!("Invalid value {}", value))?,
+        })
+    }
+
+    pub fn matches(&self, nes: &Nes) -> bool {
+        let data = nes.cpu.bus.peek(self.addr);
+        match self.comparison {
+            Comparison::Equal => data == self.value,
+            Comparison::NotEqual => data != self.value,
+        }
+    }
+}
+
+//accepts 1f, $1f and 0x1f
+pub(crate) fn parse_hex(s: &str) -> Option<u16> {
+    let s = s.trim();
+    let digits = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Controller input by frame. Each line is `<frame> <port 1> [<port 2>]` with buttons
+/// joined by `+`, e.g. `120 START` or `300 A+RIGHT -`, where `-` releases everything.
+/// Buttons stay held until the next line, `#` starts a comment.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct InputScript {
+    //sorted by frame
+    entries: Vec<(u32, [JoypadButton; 2])>,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<InputScript, String> {
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |msg: String| format!("line {}: {}", number + 1, msg);
+            let mut fields = line.split_whitespace();
+            let frame = fields
+                .next()
+                .and_then(|f| f.parse().ok())
+                .ok_or_else(|| error(format!("invalid frame in {}", line)))?;
+            let port1 = parse_buttons(fields.next().unwrap_or("-")).map_err(error)?;
+            let port2 = parse_buttons(fields.next().unwrap_or("-")).map_err(error)?;
+            if fields.next().is_some() {
+                return Err(error(format!("too many fields in {}", line)));
+            }
+            entries.push((frame, [port1, port2]));
+        }
+        entries.sort_by_key(|&(frame, _)| frame);
+        Ok(InputScript { entries })
+    }
+
+    pub fn from_file(path: &Path) -> Result<InputScript, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        InputScript::parse(&text)
+    }
+
+    /// Buttons held on both ports during `frame`.
+    pub fn buttons_at(&self, frame: u32) -> [JoypadButton; 2] {
+        self.entries
+            .iter()
+            .take_while(|&&(start, _)| start <= frame)
+            .last()
+            .map(|&(_, buttons)| buttons)
+            .unwrap_or([JoypadButton::empty(); 2])
+    }
+}
+
+fn parse_buttons(s: &str) -> Result<JoypadButton, String> {
+    let mut buttons = JoypadButton::empty();
+    if s == "-" {
+        return Ok(buttons);
+    }
+    for name in s.split('+') {
+        buttons |= match name.to_ascii_uppercase().as_str() {
+            "A" => JoypadButton::BUTTON_A,
+            "B" => JoypadButton::BUTTON_B,
+            "SELECT" => JoypadButton::SELECT,
+            "START" => JoypadButton::START,
+            "UP" => JoypadButton::UP,
+            "DOWN" => JoypadButton::DOWN,
+            "LEFT" => JoypadButton::LEFT,
+            "RIGHT" => JoypadButton::RIGHT,
+            _ => return Err(format!("unknown button {}", name)),
+        };
+    }
+    Ok(buttons)
+}
+
+/// Runs `nes` as configured and returns the number of frames emulated.
+///
+/// The outputs are written even when the run fails, they are usually what explains the failure.
+pub fn run(nes: &mut Nes, options: &HeadlessOptions) -> Result<u32, HeadlessError> {
+    nes.set_audio_sample_rate(DEFAULT_SAMPLE_RATE);
+    let mut samples = Vec::new();
+    let mut frame = 0;
+    let mut result = Ok(());
+    let mut movie = options.movie.clone().map(MoviePlayer::new);
+    if let Some(path) = options.cdl.as_ref() {
+        nes.start_code_data_log(path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    for cheat in options.cheats.iter() {
+        nes.cpu.bus.cheats.add(cheat.clone());
+    }
+    if !options.profile.is_empty() {
+        nes.set_profiler(Some(Profiler::new()));
+    }
+
+    while frame < options.frames {
+        if let Some(player) = movie.as_mut().filter(|player| !player.finished()) {
+            match player.run_frame(nes) {
+                Ok(_) => {}
+                Err(MovieError::Cpu { error, frame }) => {
+                    result = Err(HeadlessError::Cpu { error, frame });
+                    break;
+                }
+                Err(e) => {
+                    result = Err(HeadlessError::Desync(e));
+                    break;
+                }
+            }
+        } else if let Err(error) = run_scripted_frame(nes, options, frame) {
+            result = Err(HeadlessError::Cpu { error, frame });
+            break;
+        }
+        samples.extend(nes.take_audio_samples());
+        frame += 1;
+        if options.until.is_some_and(|until| until.matches(nes)) {
+            break;
+        }
+    }
+    if result.is_ok() && options.until.is_some_and(|until| !until.matches(nes)) {
+        result = Err(HeadlessError::ConditionNotMet { frames: frame });
+    }
+
+    if let Some(path) = options.png.as_ref() {
+        match options.ntsc {
+            Some(settings) => {
+                let mut rgb = vec![0; NtscFilter::WIDTH * NtscFilter::HEIGHT * 3];
+                NtscFilter::new(settings).filter(nes.frame(), &mut rgb);
+                screenshot::save_rgb_png(&rgb, NtscFilter::WIDTH, NtscFilter::HEIGHT, path)?;
+            }
+            None => screenshot::save_png(nes.frame(), &options.palette, path)?,
+        }
+    }
+    if let Some(path) = options.wav.as_ref() {
+        wav::save_wav(path, DEFAULT_SAMPLE_RATE as u32, &samples)?;
+    }
+    if let Some(path) = options.ram.as_ref() {
+        fs::write(path, &nes.cpu.bus.ram()[..])?;
+    }
+    if let Some(path) = options.cdl.as_ref() {
+        nes.save_code_data_log(path)?;
+    }
+    if let Some(profiler) = nes.take_profiler() {
+        let name = |addr| {
+            options
+                .symbols
+                .label(&nes.cpu.bus, addr)
+                .unwrap_or_else(|| format!("${:04x}", addr))
+        };
+        for path in options.profile.iter() {
+            let report = match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => profiler.json_report(name),
+                Some("folded") | Some("collapsed") => profiler.collapsed_stacks(name),
+                _ => profiler.text_report(name),
+            };
+            fs::write(path, report)?;
+        }
+    }
+    result.map(|_| frame)
+}
+
+fn run_scripted_frame(
+    nes: &mut Nes,
+    options: &HeadlessOptions,
+    frame: u32,
+) -> Result<(), CpuError> {
+    if let Some(script) = options.input_script.as_ref() {
+        let buttons = script.buttons_at(frame);
+        for (port, &buttons) in buttons.iter().enumerate() {
+            if let Some(joypad) = nes.joypad_mut(port) {
+                joypad.set_buttons(buttons);
+            }
+        }
+    }
+    nes.run_frame()
+}
@@ -0,0 +1,116 @@
+use crate::cpu::CpuError;
+use crate::headless::*;
+use crate::ntsc::{NtscFilter, NtscSettings};
+use crate::test_util::{temp_path, test_nes};
+
+//NROM image that runs `program` from $8000
+
+#[test]
+fn test_parse_condition() {
+    assert_eq!(
+        MemoryCondition::parse("$6000==80").unwrap(),
+        MemoryCondition {
+            addr: 0x6000,
+            comparison: Comparison::Equal,
+            value: 0x80
+        }
+    );
+    assert_eq!(
+        MemoryCondition::parse("0x10!=0").unwrap().comparison,
+        Comparison::NotEqual
+    );
+    assert!(MemoryCondition::parse("10=1").is_err());
+    assert!(MemoryCondition::parse("10==100").is_err());
+}
+
+#[test]
+fn test_input_script() {
+    let script = InputScript::parse("# intro\n10 START\n\n20 a+Right B\n30 -\n").unwrap();
+    assert_eq!(script.buttons_at(0), [JoypadButton::empty(); 2]);
+    assert_eq!(script.buttons_at(15)[0], JoypadButton::START);
+    assert_eq!(
+        script.buttons_at(25),
+        [
+            JoypadButton::BUTTON_A | JoypadButton::RIGHT,
+            JoypadButton::BUTTON_B
+        ]
+    );
+    assert_eq!(script.buttons_at(100), [JoypadButton::empty(); 2]);
+    assert!(InputScript::parse("10 TURBO").is_err());
+    assert!(InputScript::parse("START").is_err());
+}
+
+#[test]
+fn test_run_until_condition() {
+    //LDA #$42; STA $10; JMP $8004
+    let mut nes = test_nes(&[0xa9, 0x42, 0x85, 0x10, 0x4c, 0x04, 0x80]);
+    let ram = temp_path("headless_ram.bin");
+    let wav = temp_path("headless.wav");
+    let png = temp_path("headless.png");
+    let mut options = HeadlessOptions::new();
+    options.until = Some(MemoryCondition::parse("10==42").unwrap());
+    options.ram = Some(ram.clone());
+    options.wav = Some(wav.clone());
+    options.png = Some(png.clone());
+
+    assert_eq!(run(&mut nes, &options).unwrap(), 1);
+    let dump = std::fs::read(&ram).unwrap();
+    assert_eq!(dump.len(), 2048);
+    assert_eq!(dump[0x10], 0x42);
+    let wav_data = std::fs::read(&wav).unwrap();
+    assert_eq!(&wav_data[..4], b"RIFF");
+    assert_eq!(&wav_data[8..12], b"WAVE");
+    let png_data = std::fs::read(&png).unwrap();
+    assert_eq!(&png_data[1..4], b"PNG");
+
+    for path in [ram, wav, png].iter() {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_ntsc_png() {
+    let mut nes = test_nes(&[0x4c, 0x00, 0x80]);
+    let png = temp_path("headless_ntsc.png");
+    let mut options = HeadlessOptions::new();
+    options.frames = 1;
+    options.png = Some(png.clone());
+    options.ntsc = Some(NtscSettings::new());
+    run(&mut nes, &options).unwrap();
+
+    //the width is in the IHDR chunk after the signature and chunk header
+    let png_data = std::fs::read(&png).unwrap();
+    let width = u32::from_be_bytes([png_data[16], png_data[17], png_data[18], png_data[19]]);
+    assert_eq!(width as usize, NtscFilter::WIDTH);
+    std::fs::remove_file(&png).unwrap();
+}
+
+#[test]
+fn test_condition_not_met() {
+    let mut nes = test_nes(&[0x4c, 0x00, 0x80]);
+    let mut options = HeadlessOptions::new();
+    options.frames = 3;
+    options.until = Some(MemoryCondition::parse("10==42").unwrap());
+    assert!(matches!(
+        run(&mut nes, &options),
+        Err(HeadlessError::ConditionNotMet { frames: 3 })
+    ));
+}
+
+#[test]
+fn test_cpu_error() {
+    let mut nes = test_nes(&[0xea, 0x02]);
+    match run(&mut nes, &HeadlessOptions::new()) {
+        Err(HeadlessError::Cpu { error, frame }) => {
+            assert_eq!(
+                error,
+                CpuError::UnknownOpcode {
+                    opcode: 0x02,
+                    address: 0x8001
+                }
+            );
+            assert_eq!(frame, 0);
+        }
+        _ => panic!("expected a CPU error"),
+    }
+}
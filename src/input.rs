@@ -0,0 +1,107 @@
+use crate::frame::Frame;
+use crate::joypad::Joypad;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub mod arkanoid;
+pub mod four_score;
+pub mod power_pad;
+#[cfg(test)]
+mod tests;
+pub mod zapper;
+
+use arkanoid::ArkanoidPaddle;
+use four_score::FourScore;
+use power_pad::PowerPad;
+use zapper::Zapper;
+
+/// Whatever is plugged into one of the two controller ports.
+pub enum InputDevice {
+    Unplugged,
+    Joypad(Joypad),
+    Zapper(Zapper),
+    FourScore(FourScore),
+    PowerPad(PowerPad),
+    ArkanoidPaddle(ArkanoidPaddle),
+}
+
+impl InputDevice {
+    /// Writes to $4016, bit 0 is the strobe line shared by both ports.
+    pub fn write(&mut self, data: u8) {
+        match self {
+            InputDevice::Unplugged | InputDevice::Zapper(_) => {}
+            InputDevice::Joypad(joypad) => joypad.write(data),
+            InputDevice::FourScore(four_score) => four_score.write(data),
+            InputDevice::PowerPad(power_pad) => power_pad.write(data),
+            InputDevice::ArkanoidPaddle(paddle) => paddle.write(data),
+        }
+    }
+
+    /// Reads $4016/$4017, only bits 0-4 are driven by the device.
+    pub fn read(&mut self, frame: &Frame) -> u8 {
+        match self {
+            InputDevice::Unplugged => 0,
+            InputDevice::Joypad(joypad) => joypad.read(),
+            InputDevice::Zapper(zapper) => zapper.read(frame),
+            InputDevice::FourScore(four_score) => four_score.read(),
+            InputDevice::PowerPad(power_pad) => power_pad.read(),
+            InputDevice::ArkanoidPaddle(paddle) => paddle.read(),
+        }
+    }
+
+    pub fn joypad(&self) -> Option<&Joypad> {
+        match self {
+            InputDevice::Joypad(joypad) => Some(joypad),
+            _ => None,
+        }
+    }
+
+    pub fn joypad_mut(&mut self) -> Option<&mut Joypad> {
+        match self {
+            InputDevice::Joypad(joypad) => Some(joypad),
+            _ => None,
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            InputDevice::Unplugged => 0,
+            InputDevice::Joypad(_) => 1,
+            InputDevice::Zapper(_) => 2,
+            InputDevice::FourScore(_) => 3,
+            InputDevice::PowerPad(_) => 4,
+            InputDevice::ArkanoidPaddle(_) => 5,
+        }
+    }
+}
+
+//the connected devices are a frontend setting, a saved device only
+//restores its state when the same kind of device is plugged in
+impl Snapshot for InputDevice {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.kind());
+        let mut device = StateWriter::new();
+        match self {
+            InputDevice::Unplugged | InputDevice::Zapper(_) => {}
+            InputDevice::Joypad(joypad) => joypad.save_state(&mut device),
+            InputDevice::FourScore(four_score) => four_score.save_state(&mut device),
+            InputDevice::PowerPad(power_pad) => power_pad.save_state(&mut device),
+            InputDevice::ArkanoidPaddle(paddle) => paddle.save_state(&mut device),
+        }
+        w.write_bytes(&device.into_inner());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let kind = r.read_u8()?;
+        let mut device = StateReader::new(r.read_bytes()?, r.minor_version());
+        if kind != self.kind() {
+            return Ok(());
+        }
+        match self {
+            InputDevice::Unplugged | InputDevice::Zapper(_) => Ok(()),
+            InputDevice::Joypad(joypad) => joypad.load_state(&mut device),
+            InputDevice::FourScore(four_score) => four_score.load_state(&mut device),
+            InputDevice::PowerPad(power_pad) => power_pad.load_state(&mut device),
+            InputDevice::ArkanoidPaddle(paddle) => paddle.load_state(&mut device),
+        }
+    }
+}
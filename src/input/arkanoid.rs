@@ -0,0 +1,56 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+/// Arkanoid "Vaus" paddle: fire button on D3 and the potentiometer position
+/// shifted out MSB first and inverted on D4.
+pub struct ArkanoidPaddle {
+    //the stock controller reports roughly 0x62 to 0xF2
+    pub position: u8,
+    pub fire: bool,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Default for ArkanoidPaddle {
+    fn default() -> Self {
+        ArkanoidPaddle::new()
+    }
+}
+
+impl ArkanoidPaddle {
+    pub fn new() -> Self {
+        ArkanoidPaddle {
+            position: 0x62,
+            fire: false,
+            strobe: false,
+            shift: 0,
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.shift = !self.position;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.shift = !self.position;
+        }
+        let data = ((self.fire as u8) << 3) | ((self.shift >> 7) << 4);
+        self.shift <<= 1;
+        data
+    }
+}
+
+impl Snapshot for ArkanoidPaddle {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.strobe);
+        w.write_u8(self.shift);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.strobe = r.read_bool()?;
+        self.shift = r.read_u8()?;
+        Ok(())
+    }
+}
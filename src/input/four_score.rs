@@ -0,0 +1,71 @@
+use crate::joypad::Joypad;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+/// One side of the Four Score multitap: controllers 1 and 3 on the first
+/// port, 2 and 4 on the second. Each read shifts out 8 bits per controller
+/// followed by a signature byte identifying the port.
+pub struct FourScore {
+    pub joypads: [Joypad; 2],
+    signature: u8,
+    strobe: bool,
+    read_count: u8,
+}
+
+impl FourScore {
+    pub fn first_port() -> Self {
+        FourScore::new(0b0000_1000)
+    }
+
+    pub fn second_port() -> Self {
+        FourScore::new(0b0000_0100)
+    }
+
+    fn new(signature: u8) -> Self {
+        FourScore {
+            joypads: [Joypad::new(), Joypad::new()],
+            signature,
+            strobe: false,
+            read_count: 0,
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        for joypad in self.joypads.iter_mut() {
+            joypad.write(data);
+        }
+        if self.strobe {
+            self.read_count = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let data = match self.read_count {
+            0..=7 => self.joypads[0].read(),
+            8..=15 => self.joypads[1].read(),
+            16..=23 => (self.signature >> (self.read_count - 16)) & 1,
+            _ => 1,
+        };
+        if !self.strobe && self.read_count < 24 {
+            self.read_count += 1;
+        }
+        data
+    }
+}
+
+impl Snapshot for FourScore {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.joypads[0].save_state(w);
+        self.joypads[1].save_state(w);
+        w.write_bool(self.strobe);
+        w.write_u8(self.read_count);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.joypads[0].load_state(r)?;
+        self.joypads[1].load_state(r)?;
+        self.strobe = r.read_bool()?;
+        self.read_count = r.read_u8()?;
+        Ok(())
+    }
+}
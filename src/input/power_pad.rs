@@ -0,0 +1,86 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+/// Power Pad mat with 12 buttons, shifted out on D3 and D4.
+pub struct PowerPad {
+    //bit n is button n + 1, numbered as on side B of the mat
+    pub buttons: u16,
+    strobe: bool,
+    shift_d3: u8,
+    shift_d4: u8,
+}
+
+impl Default for PowerPad {
+    fn default() -> Self {
+        PowerPad::new()
+    }
+}
+
+impl PowerPad {
+    pub fn new() -> Self {
+        PowerPad {
+            buttons: 0,
+            strobe: false,
+            shift_d3: 0,
+            shift_d4: 0,
+        }
+    }
+
+    pub fn set_button_pressed_status(&mut self, button: u8, pressed: bool) {
+        assert!(
+            (1..=12).contains(&button),
+            "Power Pad has no button {}",
+            button
+        );
+        let mask = 1 << (button - 1);
+        if pressed {
+            self.buttons |= mask;
+        } else {
+            self.buttons &= !mask;
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.reload();
+        }
+        let data = ((self.shift_d3 & 1) << 3) | ((self.shift_d4 & 1) << 4);
+        //1s are shifted in once the buttons are exhausted
+        self.shift_d3 = (self.shift_d3 >> 1) | 0x80;
+        self.shift_d4 = (self.shift_d4 >> 1) | 0x80;
+        data
+    }
+
+    fn reload(&mut self) {
+        const D3_ORDER: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+        const D4_ORDER: [u8; 4] = [4, 3, 12, 8];
+        self.shift_d3 = self.pack(&D3_ORDER);
+        self.shift_d4 = self.pack(&D4_ORDER) | 0xF0;
+    }
+
+    fn pack(&self, order: &[u8]) -> u8 {
+        order.iter().enumerate().fold(0, |acc, (bit, button)| {
+            acc | ((((self.buttons >> (button - 1)) & 1) as u8) << bit)
+        })
+    }
+}
+
+impl Snapshot for PowerPad {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.strobe);
+        w.write_u8(self.shift_d3);
+        w.write_u8(self.shift_d4);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.strobe = r.read_bool()?;
+        self.shift_d3 = r.read_u8()?;
+        self.shift_d4 = r.read_u8()?;
+        Ok(())
+    }
+}
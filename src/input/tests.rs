@@ -0,0 +1,93 @@
+use crate::bus::Bus;
+use crate::frame::Frame;
+use crate::input::arkanoid::ArkanoidPaddle;
+use crate::input::four_score::FourScore;
+use crate::input::power_pad::PowerPad;
+use crate::input::zapper::Zapper;
+use crate::input::InputDevice;
+use crate::joypad::JoypadButton;
+
+fn strobe(bus: &mut Bus) {
+    bus.mem_write(0x4016, 1);
+    bus.mem_write(0x4016, 0);
+}
+
+fn read_bits(bus: &mut Bus, addr: u16, count: usize, mask: u8) -> Vec<u8> {
+    (0..count)
+        .map(|_| (bus.mem_read(addr) & mask != 0) as u8)
+        .collect()
+}
+
+#[test]
+fn test_zapper_light_and_trigger() {
+    let mut bus = Bus::new();
+    let mut zapper = Zapper::new();
+    zapper.aim(100, 50);
+    zapper.trigger = true;
+    bus.set_input_device(1, InputDevice::Zapper(zapper));
+
+    //black screen: no light, trigger pulled
+    assert_eq!(bus.mem_read(0x4017) & 0b0001_1000, 0b0001_1000);
+
+    bus.ppu.frame.set_pixel(100, 50, 0x30);
+    assert_eq!(bus.mem_read(0x4017) & 0b0001_1000, 0b0001_0000);
+}
+
+#[test]
+fn test_zapper_off_screen() {
+    let mut frame = Frame::new();
+    frame.data.iter_mut().for_each(|pixel| *pixel = 0x30);
+    let mut zapper = Zapper::new();
+    zapper.aim(300, 10);
+    assert_eq!(zapper.read(&frame), 0b0000_1000);
+}
+
+#[test]
+fn test_four_score() {
+    let mut bus = Bus::new();
+    let mut first = FourScore::first_port();
+    first.joypads[0].set_buttons(JoypadButton::BUTTON_A);
+    first.joypads[1].set_buttons(JoypadButton::RIGHT);
+    bus.set_input_device(0, InputDevice::FourScore(first));
+    bus.set_input_device(1, InputDevice::FourScore(FourScore::second_port()));
+    strobe(&mut bus);
+
+    let port1 = read_bits(&mut bus, 0x4016, 24, 1);
+    assert_eq!(port1[0..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(port1[8..16], [0, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(port1[16..24], [0, 0, 0, 1, 0, 0, 0, 0]);
+
+    let port2 = read_bits(&mut bus, 0x4017, 24, 1);
+    assert_eq!(port2[16..24], [0, 0, 1, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_power_pad() {
+    let mut bus = Bus::new();
+    let mut power_pad = PowerPad::new();
+    power_pad.set_button_pressed_status(1, true);
+    power_pad.set_button_pressed_status(12, true);
+    bus.set_input_device(1, InputDevice::PowerPad(power_pad));
+    strobe(&mut bus);
+
+    let reads: Vec<u8> = (0..8).map(|_| bus.mem_read(0x4017)).collect();
+    let d3: Vec<u8> = reads.iter().map(|r| (r >> 3) & 1).collect();
+    let d4: Vec<u8> = reads.iter().map(|r| (r >> 4) & 1).collect();
+    assert_eq!(d3, vec![0, 1, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(d4, vec![0, 0, 1, 0, 1, 1, 1, 1]);
+}
+
+#[test]
+fn test_arkanoid_paddle() {
+    let mut bus = Bus::new();
+    let mut paddle = ArkanoidPaddle::new();
+    paddle.position = 0b1010_0000;
+    paddle.fire = true;
+    bus.set_input_device(1, InputDevice::ArkanoidPaddle(paddle));
+    strobe(&mut bus);
+
+    assert_eq!(bus.mem_read(0x4017) & 0b0000_1000, 0b0000_1000);
+    strobe(&mut bus);
+    let position = read_bits(&mut bus, 0x4017, 8, 0b0001_0000);
+    assert_eq!(position, vec![0, 1, 0, 1, 1, 1, 1, 1]);
+}
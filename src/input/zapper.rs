@@ -0,0 +1,63 @@
+use crate::frame::Frame;
+
+const LIGHT_NOT_DETECTED: u8 = 0b0000_1000;
+const TRIGGER_PULLED: u8 = 0b0001_0000;
+
+/// Light gun. The photodiode is modelled by looking at the aimed pixel of the last frame.
+pub struct Zapper {
+    pub x: usize,
+    pub y: usize,
+    pub trigger: bool,
+    //false while the gun points away from the screen
+    pub on_screen: bool,
+}
+
+impl Default for Zapper {
+    fn default() -> Self {
+        Zapper::new()
+    }
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Zapper {
+            x: 0,
+            y: 0,
+            trigger: false,
+            on_screen: false,
+        }
+    }
+
+    pub fn aim(&mut self, x: usize, y: usize) {
+        self.on_screen = x < Frame::WIDTH && y < Frame::HEIGHT;
+        self.x = x;
+        self.y = y;
+    }
+
+    pub fn read(&self, frame: &Frame) -> u8 {
+        let mut data = if self.detects_light(frame) {
+            0
+        } else {
+            LIGHT_NOT_DETECTED
+        };
+        if self.trigger {
+            data |= TRIGGER_PULLED;
+        }
+        data
+    }
+
+    fn detects_light(&self, frame: &Frame) -> bool {
+        self.on_screen && is_bright(frame.get_pixel(self.x, self.y))
+    }
+}
+
+//the sensor only reacts to the light rows of the palette, games flash white targets
+fn is_bright(pixel: u16) -> bool {
+    let column = pixel & 0x0F;
+    let row = (pixel >> 4) & 0x03;
+    match column {
+        0x0E | 0x0F => false,
+        0x0D => row == 3,
+        _ => row >= 2,
+    }
+}
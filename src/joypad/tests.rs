@@ -0,0 +1,43 @@
+use crate::cpu::CPU;
+use crate::joypad::{Joypad, JoypadButton};
+
+#[test]
+fn test_strobe_mode() {
+    let mut joypad = Joypad::new();
+    joypad.write(1);
+    joypad.set_button_pressed_status(JoypadButton::BUTTON_A, true);
+    for _ in 0..10 {
+        assert_eq!(joypad.read(), 1);
+    }
+}
+
+#[test]
+fn test_strobe_mode_on_off() {
+    let mut joypad = Joypad::new();
+    joypad.set_buttons(JoypadButton::RIGHT | JoypadButton::LEFT | JoypadButton::SELECT);
+
+    for _ in 0..2 {
+        joypad.write(1);
+        joypad.write(0);
+        let bits: Vec<u8> = (0..8).map(|_| joypad.read()).collect();
+        assert_eq!(bits, vec![0, 0, 1, 0, 0, 0, 1, 1]);
+        //reads past the 8th button report 1
+        assert_eq!(joypad.read(), 1);
+    }
+}
+
+#[test]
+fn test_read_through_bus() {
+    let mut cpu = CPU::new();
+    cpu.bus.ports[1]
+        .joypad_mut()
+        .unwrap()
+        .set_buttons(JoypadButton::BUTTON_A);
+    cpu.bus.mem_write(0x4016, 1);
+    cpu.bus.mem_write(0x4016, 0);
+
+    //LDA $4017: the upper bits are open bus, left over from the address high byte
+    cpu.load_and_run(vec![0xad, 0x17, 0x40, 0x00]);
+    assert_eq!(cpu.register_a, 0x41);
+    assert_eq!(cpu.bus.mem_read(0x4016) & 1, 0);
+}
@@ -0,0 +1,28 @@
+pub mod apu;
+pub mod blargg;
+pub mod bus;
+pub mod cartridge;
+pub mod cdl;
+pub mod cheats;
+pub mod cpu;
+pub mod debugger;
+pub mod frame;
+pub mod gdb;
+pub mod headless;
+pub mod input;
+pub mod joypad;
+pub mod movie;
+pub mod nes;
+pub mod ntsc;
+pub mod palette;
+pub mod ppu;
+pub mod profiler;
+pub mod region;
+pub mod regression;
+pub mod rewind;
+pub mod savestate;
+pub mod screenshot;
+pub mod symbols;
+#[cfg(test)]
+mod test_util;
+pub mod wav;
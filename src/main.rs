@@ -1,5 +1,565 @@
-pub mod cpu;
+#[cfg(feature = "sdl")]
+mod frontend;
+
+use nes_emulator::blargg;
+use nes_emulator::bus::Scheduling;
+use nes_emulator::cartridge::Rom;
+use nes_emulator::cheats::{self, Cheat, CheatList};
+use nes_emulator::debugger::{self, Debugger};
+use nes_emulator::gdb;
+use nes_emulator::headless::{self, HeadlessError, HeadlessOptions, InputScript, MemoryCondition};
+use nes_emulator::movie::Movie;
+use nes_emulator::nes::Nes;
+use nes_emulator::ntsc::{NtscFilter, NtscSettings};
+use nes_emulator::palette::{NtscParams, Palette};
+use nes_emulator::region::Region;
+use nes_emulator::regression::{Manifest, Outcome};
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "usage: nes_emulator [--scale N] [--record MOVIE | --play MOVIE] [--cdl FILE]
+                    [--cheat CODE]... [--region REGION] [--catch-up] [--palette FILE]
+                    [--ntsc] [--sharpness X] [--fringing X] <rom.nes>
+       nes_emulator headless [--frames N] [--until ADDR==VALUE] [--input SCRIPT]
+                             [--movie MOVIE] [--png FILE] [--wav FILE] [--ram FILE]
+                             [--cdl FILE] [--profile FILE]... [--symbols FILE]...
+                             [--cheat CODE]... [--region REGION] [--catch-up]
+                             [--palette FILE] [--ntsc] [--sharpness X] [--fringing X]
+                             <rom.nes>
+       nes_emulator debug [--symbols FILE]... [--region REGION] <rom.nes>
+       nes_emulator gdb [--port N] <rom.nes>
+       nes_emulator blargg [--frames N] <rom.nes|dir>...
+       nes_emulator regress [--update] <manifest>
+       nes_emulator palette [--hue DEGREES] [--saturation X] [--contrast X]
+                            [--brightness X] [--gamma X] [--emphasis] <out.pal>
+
+REGION is auto, ntsc, pal or dendy, auto goes by the ROM header
+--catch-up runs the PPU and APU after each instruction instead of each CPU cycle,
+which is faster but less accurate
+--palette takes a .pal file of 64 colors, or 512 with the emphasized ones, the
+palette subcommand writes one generated from the NTSC signal
+--ntsc draws the picture through a composite video filter instead of a palette,
+--sharpness and --fringing go from 0 to 1 and turn it on as well
+NES_ROM_DATABASE names an nes20db.xml export used to fix wrong iNES headers
+In the window Backspace rewinds, F1 resets and F2 power cycles the console, a movie
+being recorded keeps the resets";
+
+/// Input movie the frontend records to or plays back.
+pub enum MovieMode {
+    Off,
+    Record { path: PathBuf, rom_filename: String },
+    Play(Movie),
+}
 
 fn main() {
-    println!("Hello, world!");
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("headless") {
+        args.next();
+        run_headless(args);
+    }
+    if args.peek().map(String::as_str) == Some("debug") {
+        args.next();
+        run_debugger(args);
+    }
+    if args.peek().map(String::as_str) == Some("gdb") {
+        args.next();
+        run_gdb_server(args);
+    }
+    if args.peek().map(String::as_str) == Some("blargg") {
+        args.next();
+        run_blargg(args);
+    }
+    if args.peek().map(String::as_str) == Some("regress") {
+        args.next();
+        run_regression_tests(args);
+    }
+    if args.peek().map(String::as_str) == Some("palette") {
+        args.next();
+        run_palette_generator(args);
+    }
+
+    let mut scale = 3;
+    let mut rom_path = None;
+    let mut movie = MovieMode::Off;
+    let mut cdl = None;
+    let mut cheats = Vec::new();
+    let mut region = None;
+    let mut scheduling = Scheduling::Cycle;
+    let mut palette = Palette::new();
+    let mut ntsc = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                scale = match args.next().and_then(|s| s.parse().ok()) {
+                    Some(scale) if scale > 0 => scale,
+                    _ => exit_with_usage(),
+                }
+            }
+            "--record" => {
+                movie = MovieMode::Record {
+                    path: path_arg(&mut args),
+                    rom_filename: String::new(),
+                }
+            }
+            "--play" => movie = MovieMode::Play(load_movie(&path_arg(&mut args))),
+            "--cdl" => cdl = Some(path_arg(&mut args)),
+            "--cheat" => cheats.push(cheat_arg(&mut args)),
+            "--region" => region = region_arg(&mut args),
+            "--catch-up" => scheduling = Scheduling::CatchUp,
+            "--palette" => palette = palette_arg(&mut args),
+            "--ntsc" | "--sharpness" | "--fringing" => ntsc_arg(&arg, &mut args, &mut ntsc),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => exit_with_usage(),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| exit_with_usage());
+    if let MovieMode::Record { rom_filename, .. } = &mut movie {
+        *rom_filename = Path::new(&rom_path)
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    }
+
+    //movies start from power on with blank cartridge RAM, so the .sav is left alone
+    let nes = match movie {
+        MovieMode::Off => Nes::from_file(Path::new(&rom_path)),
+        _ => Rom::from_file(Path::new(&rom_path)).map(Nes::new),
+    };
+    let mut nes = nes.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    //saved cheats would desync movies, only the ones given on the command line apply
+    if let MovieMode::Off = movie {
+        nes.cpu.bus.cheats = load_cheats(Path::new(&rom_path), &nes);
+    }
+    for cheat in cheats {
+        nes.cpu.bus.cheats.add(cheat);
+    }
+    if let Some(region) = region {
+        nes.set_region(region);
+    }
+    nes.set_scheduling(scheduling);
+    if let Some(path) = cdl.as_ref() {
+        nes.start_code_data_log(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+    }
+
+    run_frontend(nes, scale, movie, cdl, palette, ntsc.map(NtscFilter::new));
+}
+
+#[cfg(feature = "sdl")]
+fn run_frontend(
+    nes: Nes,
+    scale: u32,
+    movie: MovieMode,
+    cdl: Option<PathBuf>,
+    palette: Palette,
+    ntsc: Option<NtscFilter>,
+) {
+    if let Err(e) = frontend::run(nes, scale, movie, cdl, palette, ntsc) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_frontend(
+    _nes: Nes,
+    _scale: u32,
+    _movie: MovieMode,
+    _cdl: Option<PathBuf>,
+    _palette: Palette,
+    _ntsc: Option<NtscFilter>,
+) {
+    eprintln!("nes_emulator was built without a frontend, rebuild with --features sdl");
+    process::exit(1);
+}
+
+//exit codes: 1 for CPU and file errors, 3 when the --until condition was never met
+//and 4 when a movie desynced
+fn run_headless(mut args: impl Iterator<Item = String>) -> ! {
+    let mut options = HeadlessOptions::new();
+    let mut frames_set = false;
+    let mut region = None;
+    let mut scheduling = Scheduling::Cycle;
+    let mut rom_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                options.frames = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_else(|| exit_with_usage());
+                frames_set = true;
+            }
+            "--until" => {
+                let condition = args.next().unwrap_or_else(|| exit_with_usage());
+                options.until = Some(MemoryCondition::parse(&condition).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    exit_with_usage()
+                }));
+            }
+            "--input" => {
+                let path = args.next().unwrap_or_else(|| exit_with_usage());
+                options.input_script = Some(
+                    InputScript::from_file(Path::new(&path)).unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }),
+                );
+            }
+            "--movie" => {
+                let movie = load_movie(&path_arg(&mut args));
+                if !frames_set {
+                    options.frames = movie.frames.len() as u32;
+                }
+                options.movie = Some(movie);
+            }
+            "--png" => options.png = Some(path_arg(&mut args)),
+            "--wav" => options.wav = Some(path_arg(&mut args)),
+            "--ram" => options.ram = Some(path_arg(&mut args)),
+            "--cdl" => options.cdl = Some(path_arg(&mut args)),
+            "--profile" => options.profile.push(path_arg(&mut args)),
+            "--cheat" => options.cheats.push(cheat_arg(&mut args)),
+            "--region" => region = region_arg(&mut args),
+            "--catch-up" => scheduling = Scheduling::CatchUp,
+            "--palette" => options.palette = palette_arg(&mut args),
+            "--ntsc" | "--sharpness" | "--fringing" => ntsc_arg(&arg, &mut args, &mut options.ntsc),
+            "--symbols" => {
+                let path = path_arg(&mut args);
+                options.symbols.load(&path).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                });
+            }
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => exit_with_usage(),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| exit_with_usage());
+
+    //no save file, batch runs must not touch the player's saves
+    let rom = Rom::from_file(Path::new(&rom_path)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let mut nes = Nes::new(rom);
+    if let Some(region) = region {
+        nes.set_region(region);
+    }
+    nes.set_scheduling(scheduling);
+    match headless::run(&mut nes, &options) {
+        Ok(frames) => {
+            println!("ran {} frames", frames);
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            match e {
+                HeadlessError::ConditionNotMet { .. } => process::exit(3),
+                HeadlessError::Desync(_) => process::exit(4),
+                _ => process::exit(1),
+            }
+        }
+    }
+}
+
+fn run_debugger(mut args: impl Iterator<Item = String>) -> ! {
+    let mut debugger = Debugger::new();
+    let mut region = None;
+    let mut rom_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--symbols" => {
+                let path = path_arg(&mut args);
+                debugger.symbols.load(&path).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                });
+            }
+            "--region" => region = region_arg(&mut args),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => exit_with_usage(),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| exit_with_usage());
+    let rom = Rom::from_file(Path::new(&rom_path)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let mut nes = Nes::new(rom);
+    if let Some(region) = region {
+        nes.set_region(region);
+    }
+    let rom_path = Path::new(&rom_path);
+    nes.cpu.bus.cheats = load_cheats(rom_path, &nes);
+    debugger.cheat_file = nes
+        .cpu
+        .bus
+        .rom()
+        .map(|rom| cheats::cheat_file(rom_path, rom));
+    print!("{}", debugger::registers(&nes));
+
+    //an empty line repeats the last command, like in gdb
+    let mut last_command = String::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(nes) ");
+        io::stdout().flush().ok();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => process::exit(0),
+        };
+        let line = line.trim();
+        if line == "q" || line == "quit" {
+            process::exit(0);
+        }
+        if !line.is_empty() {
+            last_command = line.to_string();
+        }
+        match debugger.execute(&mut nes, &last_command) {
+            Ok(output) => print!("{}", output),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+//runs blargg's test ROMs, directories are searched for .nes files, exits with 1 when
+//any ROM did not pass
+fn run_blargg(mut args: impl Iterator<Item = String>) -> ! {
+    let mut max_frames = blargg::DEFAULT_MAX_FRAMES;
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                max_frames = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_else(|| exit_with_usage())
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        exit_with_usage();
+    }
+    let mut roms = Vec::new();
+    for path in paths {
+        roms.extend(blargg::find_roms(&path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            process::exit(1);
+        }));
+    }
+
+    let mut passed = 0;
+    for rom in roms.iter() {
+        let result = blargg::run_file(rom, max_frames);
+        if result.passed() {
+            passed += 1;
+        }
+        println!(
+            "{}: {}",
+            rom.display(),
+            result.to_string().replace('\n', "\n    ")
+        );
+    }
+    println!("{} of {} passed", passed, roms.len());
+    process::exit(if passed == roms.len() { 0 } else { 1 });
+}
+
+//screenshot regression tests, exits with 1 when any failed
+fn run_regression_tests(args: impl Iterator<Item = String>) -> ! {
+    let mut update = false;
+    let mut manifest_path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--update" => update = true,
+            _ if manifest_path.is_none() => manifest_path = Some(PathBuf::from(arg)),
+            _ => exit_with_usage(),
+        }
+    }
+    let manifest_path = manifest_path.unwrap_or_else(|| exit_with_usage());
+    let mut manifest = Manifest::open(&manifest_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let dir = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+
+    let results = manifest.run(dir, update);
+    let mut failed = 0;
+    for result in results.iter() {
+        match &result.outcome {
+            Outcome::Passed => println!("{}: passed", result.name),
+            Outcome::Updated => println!("{}: updated", result.name),
+            Outcome::Failed { expected, actual } => {
+                failed += 1;
+                println!(
+                    "{}: failed, expected {} got {}",
+                    result.name,
+                    expected.as_deref().unwrap_or("no hash"),
+                    actual
+                );
+            }
+            Outcome::Error(e) => {
+                failed += 1;
+                println!("{}: error: {}", result.name, e);
+            }
+        }
+    }
+    if update {
+        if let Err(e) = manifest.save(&manifest_path) {
+            eprintln!("Failed to write {}: {}", manifest_path.display(), e);
+            process::exit(1);
+        }
+    }
+    println!("{} of {} passed", results.len() - failed, results.len());
+    process::exit(if failed == 0 { 0 } else { 1 });
+}
+
+fn run_palette_generator(mut args: impl Iterator<Item = String>) -> ! {
+    let mut params = NtscParams::new();
+    let mut emphasis = false;
+    let mut out_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hue" => params.hue = number_arg(&mut args),
+            "--saturation" => params.saturation = number_arg(&mut args),
+            "--contrast" => params.contrast = number_arg(&mut args),
+            "--brightness" => params.brightness = number_arg(&mut args),
+            "--gamma" => {
+                params.gamma = number_arg(&mut args);
+                if params.gamma <= 0.0 {
+                    exit_with_usage();
+                }
+            }
+            "--emphasis" => emphasis = true,
+            _ if out_path.is_none() => out_path = Some(PathBuf::from(arg)),
+            _ => exit_with_usage(),
+        }
+    }
+    let out_path = out_path.unwrap_or_else(|| exit_with_usage());
+    if let Err(e) = Palette::ntsc(&params).save(&out_path, emphasis) {
+        eprintln!("Failed to write {}: {}", out_path.display(), e);
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+fn run_gdb_server(mut args: impl Iterator<Item = String>) -> ! {
+    let mut port = gdb::DEFAULT_PORT;
+    let mut rom_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                port = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_else(|| exit_with_usage())
+            }
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => exit_with_usage(),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| exit_with_usage());
+    let rom = Rom::from_file(Path::new(&rom_path)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let mut nes = Nes::new(rom);
+
+    //only local clients, the protocol has no authentication
+    let result = TcpListener::bind(("127.0.0.1", port)).and_then(|listener| {
+        eprintln!("Waiting for a GDB connection on 127.0.0.1:{}", port);
+        gdb::serve(&mut nes, &listener)
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+fn load_movie(path: &Path) -> Movie {
+    Movie::from_file(path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+//the cheats saved for the ROM loaded from `rom_path`
+fn load_cheats(rom_path: &Path, nes: &Nes) -> CheatList {
+    let rom = match nes.cpu.bus.rom() {
+        Some(rom) => rom,
+        None => return CheatList::new(),
+    };
+    CheatList::open(&cheats::cheat_file(rom_path, rom)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+fn cheat_arg(args: &mut impl Iterator<Item = String>) -> Cheat {
+    let code = args.next().unwrap_or_else(|| exit_with_usage());
+    Cheat::parse(&code).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit_with_usage()
+    })
+}
+
+//None for auto, which keeps the region of the ROM header
+fn region_arg(args: &mut impl Iterator<Item = String>) -> Option<Region> {
+    let name = args.next().unwrap_or_else(|| exit_with_usage());
+    if name == "auto" {
+        return None;
+    }
+    Some(Region::parse(&name).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit_with_usage()
+    }))
+}
+
+fn palette_arg(args: &mut impl Iterator<Item = String>) -> Palette {
+    Palette::open(&path_arg(args)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+fn ntsc_arg(
+    arg: &str,
+    args: &mut impl Iterator<Item = String>,
+    settings: &mut Option<NtscSettings>,
+) {
+    let settings = settings.get_or_insert_with(NtscSettings::new);
+    let value = match arg {
+        "--sharpness" => &mut settings.sharpness,
+        "--fringing" => &mut settings.fringing,
+        _ => return,
+    };
+    *value = number_arg(args);
+    if !(0.0..=1.0).contains(value) {
+        exit_with_usage();
+    }
+}
+
+fn number_arg(args: &mut impl Iterator<Item = String>) -> f64 {
+    args.next()
+        .and_then(|s| s.parse().ok())
+        .filter(|n: &f64| n.is_finite())
+        .unwrap_or_else(|| exit_with_usage())
+}
+
+fn path_arg(args: &mut impl Iterator<Item = String>) -> PathBuf {
+    PathBuf::from(args.next().unwrap_or_else(|| exit_with_usage()))
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
 }
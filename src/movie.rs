@@ -0,0 +1,382 @@
+use crate::cartridge::Rom;
+use crate::cpu::CpuError;
+use crate::joypad::JoypadButton;
+use crate::nes::Nes;
+use crate::region::Region;
+use bitflags::bitflags;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+pub const DEFAULT_CHECKPOINT_INTERVAL: u32 = 60;
+
+//button order of an FM2 gamepad field, the first character is the highest bit
+const FM2_BUTTONS: [(char, JoypadButton); 8] = [
+    ('R', JoypadButton::RIGHT),
+    ('L', JoypadButton::LEFT),
+    ('D', JoypadButton::DOWN),
+    ('U', JoypadButton::UP),
+    ('T', JoypadButton::START),
+    ('S', JoypadButton::SELECT),
+    ('B', JoypadButton::BUTTON_B),
+    ('A', JoypadButton::BUTTON_A),
+];
+
+bitflags! {
+    // https://fceux.com/web/help/fm2.html
+    pub struct MovieCommand: u8 {
+        const SOFT_RESET = 0b0000_0001;
+        const POWER      = 0b0000_0010;
+    }
+}
+
+/// Input of one frame, commands are applied before the frame runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub commands: MovieCommand,
+    pub buttons: [JoypadButton; 2],
+}
+
+/// Hash of the 2 KiB work RAM after `frame` frames, used to detect desyncs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub frame: u32,
+    pub ram_hash: u64,
+}
+
+/// Controller input by frame, stored as an FCEUX .fm2 text movie.
+///
+/// Checkpoints are written as extra `ramHash <frame> <hash>` header lines, other
+/// emulators skip header keys they do not know.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_filename: String,
+    //kept as found in the file, FCEUX writes the ROM's MD5 here
+    pub rom_checksum: Option<String>,
+    pub guid: Option<String>,
+    //recorded on a PAL console, FM2 has no flag for Dendy
+    pub pal: bool,
+    pub comments: Vec<String>,
+    pub frames: Vec<MovieFrame>,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    Cpu {
+        error: CpuError,
+        frame: u32,
+    },
+    Desync {
+        frame: u32,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Cpu { error, frame } => write!(f, "{} (frame {})", error, frame),
+            MovieError::Desync {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Movie desynced at frame {}: RAM hash {:016x}, expected {:016x}",
+                frame, actual, expected
+            ),
+        }
+    }
+}
+
+impl Movie {
+    pub fn new() -> Self {
+        Movie::default()
+    }
+
+    pub fn from_file(path: &Path) -> Result<Movie, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Movie::parse_fm2(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_fm2())
+    }
+
+    pub fn parse_fm2(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie::new();
+        let mut ports = [true, true];
+        for (number, line) in text.lines().enumerate() {
+            let error = |msg: String| format!("line {}: {}", number + 1, msg);
+            if line.starts_with('|') {
+                movie
+                    .frames
+                    .push(parse_fm2_frame(line, ports).map_err(error)?);
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "" => {}
+                "binary" if value == "1" => {
+                    return Err(error("binary movies are not supported".to_string()))
+                }
+                "fourscore" if value == "1" => {
+                    return Err(error("Four Score movies are not supported".to_string()))
+                }
+                "port0" | "port1" => {
+                    let port = (key == "port1") as usize;
+                    ports[port] = match value {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(error(format!("unsupported device {}", value))),
+                    };
+                }
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => movie.rom_checksum = Some(value.to_string()),
+                "guid" => movie.guid = Some(value.to_string()),
+                "palFlag" => movie.pal = value == "1",
+                "comment" => movie.comments.push(value.to_string()),
+                "ramHash" => {
+                    let (frame, hash) = value
+                        .split_once(' ')
+                        .ok_or_else(|| error(format!("invalid ramHash {}", value)))?;
+                    movie.checkpoints.push(Checkpoint {
+                        frame: frame
+                            .parse()
+                            .map_err(|_| error(format!("invalid frame {}", frame)))?,
+                        ram_hash: u64::from_str_radix(hash, 16)
+                            .map_err(|_| error(format!("invalid hash {}", hash)))?,
+                    });
+                }
+                //version, emuVersion, rerecordCount and friends
+                _ => {}
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut out = String::new();
+        out.push_str("version 3\n");
+        out.push_str("emuVersion 22020\n");
+        out.push_str("rerecordCount 0\n");
+        out.push_str(&format!("palFlag {}\n", self.pal as u8));
+        out.push_str(&format!("romFilename {}\n", self.rom_filename));
+        if let Some(checksum) = &self.rom_checksum {
+            out.push_str(&format!("romChecksum {}\n", checksum));
+        }
+        if let Some(guid) = &self.guid {
+            out.push_str(&format!("guid {}\n", guid));
+        }
+        out.push_str("fourscore 0\n");
+        out.push_str("microphone 0\n");
+        out.push_str("port0 1\n");
+        out.push_str("port1 1\n");
+        out.push_str("port2 0\n");
+        out.push_str("FDS 0\n");
+        out.push_str("NewPPU 0\n");
+        for comment in self.comments.iter() {
+            out.push_str(&format!("comment {}\n", comment));
+        }
+        for checkpoint in self.checkpoints.iter() {
+            out.push_str(&format!(
+                "ramHash {} {:016x}\n",
+                checkpoint.frame, checkpoint.ram_hash
+            ));
+        }
+        for frame in self.frames.iter() {
+            out.push_str(&format!(
+                "|{}|{}|{}||\n",
+                frame.commands.bits(),
+                fm2_buttons(frame.buttons[0]),
+                fm2_buttons(frame.buttons[1])
+            ));
+        }
+        out
+    }
+}
+
+fn parse_fm2_frame(line: &str, ports: [bool; 2]) -> Result<MovieFrame, String> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 5 {
+        return Err(format!("invalid input line {}", line));
+    }
+    let commands = fields[1]
+        .parse()
+        .map(MovieCommand::from_bits_truncate)
+        .map_err(|_| format!("invalid commands {}", fields[1]))?;
+    let mut buttons = [JoypadButton::empty(); 2];
+    for (port, field) in fields[2..4].iter().enumerate() {
+        if !ports[port] {
+            continue;
+        }
+        if field.chars().count() != FM2_BUTTONS.len() {
+            return Err(format!("invalid gamepad input {}", field));
+        }
+        for (c, &(_, button)) in field.chars().zip(FM2_BUTTONS.iter()) {
+            if c != '.' && c != ' ' {
+                buttons[port] |= button;
+            }
+        }
+    }
+    Ok(MovieFrame { commands, buttons })
+}
+
+fn fm2_buttons(buttons: JoypadButton) -> String {
+    FM2_BUTTONS
+        .iter()
+        .map(|&(c, button)| if buttons.contains(button) { c } else { '.' })
+        .collect()
+}
+
+/// FNV-1a over the work RAM.
+pub fn ram_hash(nes: &Nes) -> u64 {
+    nes.cpu
+        .bus
+        .ram()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// The `romChecksum` FCEUX writes, the base64 MD5 of the ROM without its header.
+pub fn rom_checksum(rom: &Rom) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::from("base64:");
+    for chunk in rom.md5().chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Soft resets or power cycles the console as a movie frame's commands say.
+pub fn apply_commands(nes: &mut Nes, commands: MovieCommand) {
+    if commands.contains(MovieCommand::POWER) {
+        nes.power_cycle();
+    } else if commands.contains(MovieCommand::SOFT_RESET) {
+        nes.reset();
+    }
+}
+
+/// Records the input of every frame it runs, with a RAM hash checkpoint every `checkpoint_interval` frames.
+pub struct MovieRecorder {
+    movie: Movie,
+    checkpoint_interval: u32,
+}
+
+impl MovieRecorder {
+    pub fn new(rom_filename: &str, checkpoint_interval: u32) -> Self {
+        let mut movie = Movie::new();
+        movie.rom_filename = rom_filename.to_string();
+        MovieRecorder {
+            movie,
+            checkpoint_interval: checkpoint_interval.max(1),
+        }
+    }
+
+    /// Applies `commands`, records the joypad buttons currently held and runs the frame.
+    pub fn run_frame(&mut self, nes: &mut Nes, commands: MovieCommand) -> Result<(), MovieError> {
+        let frame = self.movie.frames.len() as u32;
+        apply_commands(nes, commands);
+        let buttons = |port| {
+            nes.joypad(port)
+                .map_or(JoypadButton::empty(), |j| j.buttons())
+        };
+        let buttons = [buttons(0), buttons(1)];
+        self.movie.pal = nes.region() == Region::Pal;
+        if frame == 0 {
+            self.movie.rom_checksum = nes.cpu.bus.rom().map(rom_checksum);
+        }
+        self.movie.frames.push(MovieFrame { commands, buttons });
+        nes.run_frame()
+            .map_err(|error| MovieError::Cpu { error, frame })?;
+
+        let frame = frame + 1;
+        if frame.is_multiple_of(self.checkpoint_interval) {
+            self.movie.checkpoints.push(Checkpoint {
+                frame,
+                ram_hash: ram_hash(nes),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds a movie's input into the console and checks its checkpoints.
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer { movie, frame: 0 }
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+
+    /// Runs the next frame of the movie, returns false once the movie is over.
+    pub fn run_frame(&mut self, nes: &mut Nes) -> Result<bool, MovieError> {
+        let input = match self.movie.frames.get(self.frame) {
+            Some(input) => *input,
+            None => return Ok(false),
+        };
+        let frame = self.frame as u32;
+        if frame == 0 && self.movie.pal {
+            nes.set_region(Region::Pal);
+        }
+        apply_commands(nes, input.commands);
+        for (port, &buttons) in input.buttons.iter().enumerate() {
+            if let Some(joypad) = nes.joypad_mut(port) {
+                joypad.set_buttons(buttons);
+            }
+        }
+        nes.run_frame()
+            .map_err(|error| MovieError::Cpu { error, frame })?;
+        self.frame += 1;
+
+        let frame = self.frame as u32;
+        if let Some(checkpoint) = self.movie.checkpoints.iter().find(|c| c.frame == frame) {
+            let actual = ram_hash(nes);
+            if actual != checkpoint.ram_hash {
+                return Err(MovieError::Desync {
+                    frame,
+                    expected: checkpoint.ram_hash,
+                    actual,
+                });
+            }
+        }
+        Ok(true)
+    }
+}
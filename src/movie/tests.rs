@@ -0,0 +1,117 @@
+use crate::cartridge::Rom;
+use crate::joypad::JoypadButton;
+use crate::movie::*;
+use crate::nes::Nes;
+use crate::region::Region;
+use crate::test_util::{test_nes, test_rom_with};
+
+//loop: LDA #$01; STA $4016; LDA #$00; STA $4016; LDA $4016; ADC $10; STA $10; JMP loop
+const PROGRAM: [u8; 20] = [
+    0xa9, 0x01, 0x8d, 0x16, 0x40, 0xa9, 0x00, 0x8d, 0x16, 0x40, 0xad, 0x16, 0x40, 0x65, 0x10, 0x85,
+    0x10, 0x4c, 0x00, 0x80,
+];
+
+fn record(frames: u32) -> (Movie, Nes) {
+    let mut nes = test_nes(&PROGRAM);
+    let mut recorder = MovieRecorder::new("test.nes", 4);
+    for frame in 0..frames {
+        let buttons = if frame % 3 == 1 {
+            JoypadButton::BUTTON_A | JoypadButton::UP
+        } else {
+            JoypadButton::empty()
+        };
+        nes.joypad_mut(0).unwrap().set_buttons(buttons);
+        let commands = if frame == 5 {
+            MovieCommand::SOFT_RESET
+        } else {
+            MovieCommand::empty()
+        };
+        recorder.run_frame(&mut nes, commands).unwrap();
+    }
+    (recorder.finish(), nes)
+}
+
+#[test]
+fn test_parse_fm2() {
+    let text = "version 3\nromFilename game\nport0 1\nport1 0\nport2 0\n\
+                |0|R......A|||\n|1|...U.S..|||\n|2|........|||\n";
+    let movie = Movie::parse_fm2(text).unwrap();
+    assert_eq!(movie.rom_filename, "game");
+    assert_eq!(movie.frames.len(), 3);
+    assert_eq!(
+        movie.frames[0].buttons[0],
+        JoypadButton::RIGHT | JoypadButton::BUTTON_A
+    );
+    assert_eq!(
+        movie.frames[1].buttons[0],
+        JoypadButton::UP | JoypadButton::SELECT
+    );
+    assert_eq!(movie.frames[1].commands, MovieCommand::SOFT_RESET);
+    assert_eq!(movie.frames[2].commands, MovieCommand::POWER);
+
+    assert!(Movie::parse_fm2("binary 1\n").is_err());
+    assert!(Movie::parse_fm2("|0|RL|||\n").is_err());
+}
+
+#[test]
+fn test_fm2_round_trip() {
+    let (movie, _) = record(12);
+    assert_eq!(movie.checkpoints.len(), 3);
+    assert!(movie.to_fm2().contains("\nromChecksum base64:"));
+    assert_eq!(Movie::parse_fm2(&movie.to_fm2()).unwrap(), movie);
+}
+
+#[test]
+fn test_rom_checksum() {
+    let nes = test_nes(&PROGRAM);
+    let mut rom = Rom::new(&test_rom_with(&[])).unwrap();
+    assert_eq!(
+        rom_checksum(nes.cpu.bus.rom().unwrap()),
+        record(1).0.rom_checksum.unwrap()
+    );
+    //MD5 d41d8cd98f00b204e9800998ecf8427e, padded on the last group
+    rom.prg_rom.clear();
+    rom.chr_rom.clear();
+    assert_eq!(rom_checksum(&rom), "base64:1B2M2Y8AsgTpgAmY7PhCfg==");
+}
+
+#[test]
+fn test_playback_matches_recording() {
+    let (movie, recorded) = record(20);
+    let mut nes = test_nes(&PROGRAM);
+    let mut player = MoviePlayer::new(movie);
+    while player.run_frame(&mut nes).unwrap() {}
+    assert!(player.finished());
+    assert_eq!(ram_hash(&nes), ram_hash(&recorded));
+    assert_eq!(nes.save_state(), recorded.save_state());
+}
+
+#[test]
+fn test_desync_is_detected() {
+    let (mut movie, _) = record(8);
+    movie.frames[2].buttons[0] = JoypadButton::BUTTON_A;
+    let mut nes = test_nes(&PROGRAM);
+    let mut player = MoviePlayer::new(movie);
+    let result = (0..8).try_for_each(|_| player.run_frame(&mut nes).map(|_| ()));
+    assert!(matches!(result, Err(MovieError::Desync { frame: 4, .. })));
+}
+
+#[test]
+fn test_pal_movie_plays_on_pal() {
+    let mut recorded = test_nes(&PROGRAM);
+    recorded.set_region(Region::Pal);
+    let mut recorder = MovieRecorder::new("test.nes", 4);
+    for _ in 0..8 {
+        recorder
+            .run_frame(&mut recorded, MovieCommand::empty())
+            .unwrap();
+    }
+    let movie = Movie::parse_fm2(&recorder.finish().to_fm2()).unwrap();
+    assert!(movie.pal);
+
+    let mut nes = test_nes(&PROGRAM);
+    let mut player = MoviePlayer::new(movie);
+    while player.run_frame(&mut nes).unwrap() {}
+    assert_eq!(nes.region(), Region::Pal);
+    assert_eq!(nes.save_state(), recorded.save_state());
+}
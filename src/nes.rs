@@ -0,0 +1,159 @@
+use crate::bus::{Bus, Scheduling};
+use crate::cartridge::save::SaveFile;
+use crate::cartridge::Rom;
+use crate::cdl::CodeDataLog;
+use crate::cpu::{CpuError, CPU};
+use crate::frame::Frame;
+use crate::joypad::Joypad;
+use crate::profiler::Profiler;
+use crate::region::Region;
+use crate::savestate;
+use std::io;
+use std::path::Path;
+
+/// The whole console: the CPU and, through its bus, everything else.
+pub struct Nes {
+    pub cpu: CPU,
+    profiler: Option<Profiler>,
+}
+
+impl Nes {
+    pub fn new(rom: Rom) -> Self {
+        let mut cpu = CPU::with_bus(Bus::with_rom(rom));
+        cpu.reset();
+        Nes {
+            cpu,
+            profiler: None,
+        }
+    }
+
+    /// Loads a ROM file, and its `.sav` file next to it for battery-backed cartridges.
+    pub fn from_file(path: &Path) -> Result<Nes, String> {
+        let rom = Rom::from_file(path)?;
+        let battery = rom.battery;
+        let mut nes = Nes::new(rom);
+        if battery {
+            nes.cpu
+                .bus
+                .attach_save_file(SaveFile::for_rom(path))
+                .map_err(|e| format!("Failed to load save file: {}", e))?;
+        }
+        Ok(nes)
+    }
+
+    /// Runs until the PPU enters vblank.
+    pub fn run_frame(&mut self) -> Result<(), CpuError> {
+        while !self.step_instruction()? {}
+        Ok(())
+    }
+
+    /// Executes one instruction, returns true when it completed a frame.
+    pub fn step_instruction(&mut self) -> Result<bool, CpuError> {
+        match self.profiler.as_mut() {
+            Some(profiler) => profiler.step(&mut self.cpu)?,
+            None => self.cpu.step()?,
+        };
+        if !self.cpu.bus.ppu.take_frame_complete() {
+            return Ok(false);
+        }
+        if let Err(e) = self.cpu.bus.end_frame() {
+            eprintln!("Failed to write save file: {}", e);
+        }
+        Ok(true)
+    }
+
+    /// Snapshot of the whole console in the format described in `savestate`.
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::save(self)
+    }
+
+    /// Restores a snapshot from `save_state`, leaving the console untouched on error.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        savestate::load(self, data)
+    }
+
+    /// Starts the code/data logger, adding to the .cdl file at `path` if there is one.
+    pub fn start_code_data_log(&mut self, path: &Path) -> Result<(), String> {
+        let rom = self
+            .cpu
+            .bus
+            .rom()
+            .ok_or_else(|| "No cartridge to log".to_string())?;
+        let log = CodeDataLog::open(rom, path)?;
+        self.cpu.bus.set_code_data_log(Some(log));
+        Ok(())
+    }
+
+    pub fn save_code_data_log(&self, path: &Path) -> io::Result<()> {
+        match self.cpu.bus.code_data_log() {
+            Some(log) => log.save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Profiles every instruction from now on, or stops with None.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.cpu.bus.set_access_counting(profiler.is_some());
+        self.profiler = profiler;
+    }
+
+    /// Detaches the profiler with the memory accesses counted while it ran.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        let mut profiler = self.profiler.take()?;
+        if let Some(counts) = self.cpu.bus.take_access_counts() {
+            profiler.add_access_counts(&counts);
+        }
+        self.cpu.bus.set_access_counting(false);
+        Some(profiler)
+    }
+
+    /// The region from the cartridge header unless it was changed with `set_region`.
+    pub fn region(&self) -> Region {
+        self.cpu.bus.region()
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.cpu.bus.set_region(region);
+    }
+
+    /// Switches between running the PPU and APU per CPU cycle and catching them up after
+    /// each instruction.
+    pub fn set_scheduling(&mut self, scheduling: Scheduling) {
+        self.cpu.bus.set_scheduling(scheduling);
+    }
+
+    /// Frames per second the console runs at in its region.
+    pub fn frame_rate(&self) -> f64 {
+        self.region().timing().frame_rate
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    /// Turns the console off and on again.
+    pub fn power_cycle(&mut self) {
+        self.cpu.bus.power_cycle();
+        self.cpu.reset();
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.cpu.bus.ppu.frame
+    }
+
+    pub fn joypad(&self, port: usize) -> Option<&Joypad> {
+        self.cpu.bus.ports[port].joypad()
+    }
+
+    pub fn joypad_mut(&mut self, port: usize) -> Option<&mut Joypad> {
+        self.cpu.bus.ports[port].joypad_mut()
+    }
+
+    pub fn set_audio_sample_rate(&mut self, sample_rate: f64) {
+        self.cpu.bus.apu.set_sample_rate(sample_rate);
+    }
+
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.bus.apu.take_samples()
+    }
+}
@@ -0,0 +1,165 @@
+//! Composite video filter: turns the palette indices of a frame into the signal the PPU
+//! sends to an NTSC TV and decodes it the way the TV would, with the color fringes on
+//! sharp edges, artifact colors and dot crawl that come with it.
+
+use crate::frame::Frame;
+use crate::palette::{self, NtscParams};
+
+#[cfg(test)]
+mod tests;
+
+//samples of the signal per dot, the subcarrier takes 12
+const SAMPLES_PER_DOT: usize = 8;
+//signal samples per output pixel
+const SAMPLES_PER_PIXEL: usize = 4;
+//a scanline is 341 dots, 4 samples more than a whole number of subcarrier cycles
+const LINE_PHASE_STEP: usize = 341 * SAMPLES_PER_DOT % 12;
+//each frame starts this far into the cycle from the last, ignoring the dot odd frames skip
+const FRAME_PHASE_STEP: usize = 4;
+//black around the picture so the filters can look past its edges
+const PADDING: usize = 24;
+//the luma filter spans a subcarrier cycle, which removes the subcarrier from it
+const LUMA_WINDOW: usize = 12;
+//the short luma filter sharpness mixes in, it lets some of the subcarrier through
+const SHARP_LUMA_WINDOW: usize = 4;
+//TVs decode color with less bandwidth than luma
+const CHROMA_WINDOW: usize = 24;
+
+/// How the picture is decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscSettings {
+    pub params: NtscParams,
+    //0 to 1, more detail in luma at the price of subcarrier showing as dot crawl
+    pub sharpness: f64,
+    //0 to 1, how much of the luma edges the color decoder picks up as color
+    pub fringing: f64,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        NtscSettings::new()
+    }
+}
+
+impl NtscSettings {
+    pub fn new() -> Self {
+        NtscSettings {
+            params: NtscParams::new(),
+            sharpness: 0.0,
+            fringing: 1.0,
+        }
+    }
+}
+
+pub struct NtscFilter {
+    settings: NtscSettings,
+    //one subcarrier cycle of the signal for each pixel value
+    levels: Vec<[f64; 12]>,
+    //the average of each cycle
+    luma: Vec<f64>,
+    cos: [f64; 12],
+    sin: [f64; 12],
+    frames: u64,
+    signal: Vec<f64>,
+    chroma: Vec<f64>,
+}
+
+impl NtscFilter {
+    pub const WIDTH: usize = Frame::WIDTH * SAMPLES_PER_DOT / SAMPLES_PER_PIXEL;
+    pub const HEIGHT: usize = Frame::HEIGHT;
+
+    pub fn new(settings: NtscSettings) -> Self {
+        let mut levels = Vec::with_capacity(512);
+        for pixel in 0..512 {
+            let mut cycle = [0.0; 12];
+            for (phase, level) in cycle.iter_mut().enumerate() {
+                *level = palette::signal_level(pixel, phase);
+            }
+            levels.push(cycle);
+        }
+        let luma = levels
+            .iter()
+            .map(|cycle| cycle.iter().sum::<f64>() / 12.0)
+            .collect();
+        let mut cos = [0.0; 12];
+        let mut sin = [0.0; 12];
+        for phase in 0..12 {
+            let angle = palette::subcarrier_angle(phase, &settings.params);
+            cos[phase] = angle.cos();
+            sin[phase] = angle.sin();
+        }
+        let samples = Frame::WIDTH * SAMPLES_PER_DOT + 2 * PADDING;
+        NtscFilter {
+            settings,
+            levels,
+            luma,
+            cos,
+            sin,
+            frames: 0,
+            signal: vec![0.0; samples],
+            chroma: vec![0.0; samples],
+        }
+    }
+
+    pub fn settings(&self) -> &NtscSettings {
+        &self.settings
+    }
+
+    /// The frame as `WIDTH` x `HEIGHT` RGB triples. Each call is decoded as the frame after
+    /// the last one, the subcarrier moves on and the artifacts crawl.
+    pub fn filter(&mut self, frame: &Frame, rgb: &mut [u8]) {
+        let frame_phase = self.frames as usize % 3 * FRAME_PHASE_STEP;
+        self.frames += 1;
+        let out_line = NtscFilter::WIDTH * 3;
+        for (y, out) in rgb
+            .chunks_exact_mut(out_line)
+            .take(Frame::HEIGHT)
+            .enumerate()
+        {
+            let line = &frame.data[y * Frame::WIDTH..(y + 1) * Frame::WIDTH];
+            self.filter_line(line, (frame_phase + y * LINE_PHASE_STEP) % 12, out);
+        }
+    }
+
+    //sample `n` of the padded line is at phase `(start + n) % 12`
+    fn filter_line(&mut self, line: &[u16], start: usize, out: &mut [u8]) {
+        let unfringed = 1.0 - self.settings.fringing;
+        for (x, &pixel) in line.iter().enumerate() {
+            let pixel = (pixel & 0x1ff) as usize;
+            let first = PADDING + x * SAMPLES_PER_DOT;
+            for n in first..first + SAMPLES_PER_DOT {
+                let level = self.levels[pixel][(start + n) % 12];
+                self.signal[n] = level;
+                //without fringing the color decoder does not see the steps between dots
+                self.chroma[n] = level - self.luma[pixel] * unfringed;
+            }
+        }
+
+        let average = |samples: &[f64], center: usize, window: usize| {
+            samples[center - window / 2..center + window / 2]
+                .iter()
+                .sum::<f64>()
+                / window as f64
+        };
+        for (x, out) in out.chunks_exact_mut(3).enumerate() {
+            let center = PADDING + x * SAMPLES_PER_PIXEL + SAMPLES_PER_PIXEL / 2;
+            let luma = average(&self.signal, center, LUMA_WINDOW);
+            let sharp = average(&self.signal, center, SHARP_LUMA_WINDOW);
+            let y = luma + (sharp - luma) * self.settings.sharpness;
+
+            let (mut i, mut q) = (0.0, 0.0);
+            for n in center - CHROMA_WINDOW / 2..center + CHROMA_WINDOW / 2 {
+                let phase = (start + n) % 12;
+                i += self.chroma[n] * self.cos[phase];
+                q += self.chroma[n] * self.sin[phase];
+            }
+            let window = CHROMA_WINDOW as f64;
+            out.copy_from_slice(&palette::yiq_to_rgb(
+                y,
+                i / window,
+                q / window,
+                &self.settings.params,
+            ));
+        }
+    }
+}
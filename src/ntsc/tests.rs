@@ -0,0 +1,94 @@
+use super::*;
+use crate::palette::Palette;
+
+fn filled(pixel: u16) -> Frame {
+    let mut frame = Frame::new();
+    frame.data.iter_mut().for_each(|p| *p = pixel);
+    frame
+}
+
+//white and black dots side by side, luma edges everywhere
+fn stripes() -> Frame {
+    let mut frame = Frame::new();
+    for (i, pixel) in frame.data.iter_mut().enumerate() {
+        *pixel = if i % 2 == 0 { 0x30 } else { 0x0f };
+    }
+    frame
+}
+
+fn filter(filter: &mut NtscFilter, frame: &Frame) -> Vec<u8> {
+    let mut rgb = vec![0; NtscFilter::WIDTH * NtscFilter::HEIGHT * 3];
+    filter.filter(frame, &mut rgb);
+    rgb
+}
+
+fn pixel(rgb: &[u8], x: usize, y: usize) -> [u8; 3] {
+    let i = (y * NtscFilter::WIDTH + x) * 3;
+    [rgb[i], rgb[i + 1], rgb[i + 2]]
+}
+
+fn is_grey(rgb: &[u8]) -> bool {
+    rgb.chunks_exact(3).all(|c| c[0] == c[1] && c[1] == c[2])
+}
+
+#[test]
+fn test_flat_colors_match_the_palette() {
+    let palette = Palette::ntsc(&NtscParams::new());
+    let mut ntsc = NtscFilter::new(NtscSettings::new());
+    for color in [0x16, 0x2a, 0x12 | 0x40, 0x30] {
+        let rgb = filter(&mut ntsc, &filled(color));
+        for y in [0, 1, 2, 100] {
+            let decoded = pixel(&rgb, NtscFilter::WIDTH / 2, y);
+            let expected = palette.rgb(color);
+            for channel in 0..3 {
+                assert!(
+                    (decoded[channel] as i32 - expected[channel] as i32).abs() <= 1,
+                    "{:02x}: {:?} {:?}",
+                    color,
+                    decoded,
+                    expected
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_fringing() {
+    let rgb = filter(&mut NtscFilter::new(NtscSettings::new()), &stripes());
+    assert!(!is_grey(&rgb));
+
+    let mut settings = NtscSettings::new();
+    settings.fringing = 0.0;
+    let rgb = filter(&mut NtscFilter::new(settings), &stripes());
+    assert!(is_grey(&rgb));
+}
+
+#[test]
+fn test_dot_crawl() {
+    let mut ntsc = NtscFilter::new(NtscSettings::new());
+    let frames: Vec<Vec<u8>> = (0..4).map(|_| filter(&mut ntsc, &stripes())).collect();
+    assert_ne!(frames[0], frames[1]);
+    assert_ne!(frames[1], frames[2]);
+    assert_eq!(frames[0], frames[3]);
+    //and from one line to the next
+    assert_ne!(pixel(&frames[0], 200, 10), pixel(&frames[0], 200, 11));
+}
+
+#[test]
+fn test_sharpness() {
+    //a white dot on black, sharper luma rises higher and falls off quicker
+    let mut frame = filled(0x0f);
+    frame.set_pixel(100, 0, 0x30);
+    let brightest = |sharpness: f64| {
+        let mut settings = NtscSettings::new();
+        settings.sharpness = sharpness;
+        settings.fringing = 0.0;
+        let rgb = filter(&mut NtscFilter::new(settings), &frame);
+        (0..NtscFilter::WIDTH)
+            .map(|x| pixel(&rgb, x, 0)[0])
+            .max()
+            .unwrap()
+    };
+    assert!(brightest(1.0) > brightest(0.0));
+}
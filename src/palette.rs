@@ -0,0 +1,272 @@
+use crate::frame::Frame;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+pub static SYSTEM_PALETTE: [(u8, u8, u8); 64] = [
+    (0x80, 0x80, 0x80),
+    (0x00, 0x3D, 0xA6),
+    (0x00, 0x12, 0xB0),
+    (0x44, 0x00, 0x96),
+    (0xA1, 0x00, 0x5E),
+    (0xC7, 0x00, 0x28),
+    (0xBA, 0x06, 0x00),
+    (0x8C, 0x17, 0x00),
+    (0x5C, 0x2F, 0x00),
+    (0x10, 0x45, 0x00),
+    (0x05, 0x4A, 0x00),
+    (0x00, 0x47, 0x2E),
+    (0x00, 0x41, 0x66),
+    (0x00, 0x00, 0x00),
+    (0x05, 0x05, 0x05),
+    (0x05, 0x05, 0x05),
+    (0xC7, 0xC7, 0xC7),
+    (0x00, 0x77, 0xFF),
+    (0x21, 0x55, 0xFF),
+    (0x82, 0x37, 0xFA),
+    (0xEB, 0x2F, 0xB5),
+    (0xFF, 0x29, 0x50),
+    (0xFF, 0x22, 0x00),
+    (0xD6, 0x32, 0x00),
+    (0xC4, 0x62, 0x00),
+    (0x35, 0x80, 0x00),
+    (0x05, 0x8F, 0x00),
+    (0x00, 0x8A, 0x55),
+    (0x00, 0x99, 0xCC),
+    (0x21, 0x21, 0x21),
+    (0x09, 0x09, 0x09),
+    (0x09, 0x09, 0x09),
+    (0xFF, 0xFF, 0xFF),
+    (0x0F, 0xD7, 0xFF),
+    (0x69, 0xA2, 0xFF),
+    (0xD4, 0x80, 0xFF),
+    (0xFF, 0x45, 0xF3),
+    (0xFF, 0x61, 0x8B),
+    (0xFF, 0x88, 0x33),
+    (0xFF, 0x9C, 0x12),
+    (0xFA, 0xBC, 0x20),
+    (0x9F, 0xE3, 0x0E),
+    (0x2B, 0xF0, 0x35),
+    (0x0C, 0xF0, 0xA4),
+    (0x05, 0xFB, 0xFF),
+    (0x5E, 0x5E, 0x5E),
+    (0x0D, 0x0D, 0x0D),
+    (0x0D, 0x0D, 0x0D),
+    (0xFF, 0xFF, 0xFF),
+    (0xA6, 0xFC, 0xFF),
+    (0xB3, 0xEC, 0xFF),
+    (0xDA, 0xAB, 0xEB),
+    (0xFF, 0xA8, 0xF9),
+    (0xFF, 0xAB, 0xB3),
+    (0xFF, 0xD2, 0xB0),
+    (0xFF, 0xEF, 0xA6),
+    (0xFF, 0xF7, 0x9C),
+    (0xD7, 0xE8, 0x95),
+    (0xA6, 0xED, 0xAF),
+    (0xA2, 0xF2, 0xDA),
+    (0x99, 0xFF, 0xFC),
+    (0xDD, 0xDD, 0xDD),
+    (0x11, 0x11, 0x11),
+    (0x11, 0x11, 0x11),
+];
+
+//how much the emphasis bits darken the other channels
+const EMPHASIS_ATTENUATION: f64 = 0.746;
+//composite levels of the four luma rows, low and high half of the wave, in volts
+const LEVELS_LOW: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+const LEVELS_HIGH: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f64 = 0.518;
+const WHITE: f64 = 1.962;
+//samples from the start of the wave to the colorburst the decoder locks to
+const BURST_PHASE: f64 = 4.0;
+
+/// Knobs of the composite decoder the NTSC palette is generated with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscParams {
+    //in degrees, positive turns each hue towards the next one in the palette
+    pub hue: f64,
+    pub saturation: f64,
+    pub contrast: f64,
+    //added to every channel, 0 leaves it alone
+    pub brightness: f64,
+    //of the display, 1 outputs the signal as it is
+    pub gamma: f64,
+}
+
+impl Default for NtscParams {
+    fn default() -> Self {
+        NtscParams::new()
+    }
+}
+
+impl NtscParams {
+    pub fn new() -> Self {
+        NtscParams {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+/// RGB for each of the 512 pixel values a frame holds, the 64 colors under each
+/// combination of the emphasis bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::new()
+    }
+}
+
+impl Palette {
+    /// `SYSTEM_PALETTE`, with emphasis applied by darkening the other channels.
+    pub fn new() -> Self {
+        let mut colors = [[0; 3]; 64];
+        for (color, &(r, g, b)) in colors.iter_mut().zip(SYSTEM_PALETTE.iter()) {
+            *color = [r, g, b];
+        }
+        Palette::from_colors(&colors)
+    }
+
+    /// Extends 64 colors to 512 the way `new` does.
+    pub fn from_colors(colors: &[[u8; 3]; 64]) -> Self {
+        let mut palette = Vec::with_capacity(512);
+        for emphasis in 0..8 {
+            for (index, color) in colors.iter().enumerate() {
+                let mut color = *color;
+                //the two black columns are not affected
+                if index & 0x0f < 0x0e {
+                    for (channel, value) in color.iter_mut().enumerate() {
+                        if emphasis & !(1 << channel) != 0 {
+                            *value = (*value as f64 * EMPHASIS_ATTENUATION).round() as u8;
+                        }
+                    }
+                }
+                palette.push(color);
+            }
+        }
+        Palette { colors: palette }
+    }
+
+    /// Decodes the composite signal the PPU puts out for each color.
+    pub fn ntsc(params: &NtscParams) -> Self {
+        let colors = (0..512).map(|pixel| ntsc_color(pixel, params)).collect();
+        Palette { colors }
+    }
+
+    /// Reads a .pal file, 64 colors or 512 with the emphasized ones after them.
+    pub fn parse_pal(bytes: &[u8]) -> Result<Palette, String> {
+        let colors: Vec<[u8; 3]> = bytes.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+        match bytes.len() {
+            192 => {
+                let mut base = [[0; 3]; 64];
+                base.copy_from_slice(&colors);
+                Ok(Palette::from_colors(&base))
+            }
+            1536 => Ok(Palette { colors }),
+            len => Err(format!(
+                "a palette is 192 or 1536 bytes, this one is {}",
+                len
+            )),
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Palette, String> {
+        let bytes =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Palette::parse_pal(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The .pal bytes, only the 64 colors without emphasis unless `emphasis` is set.
+    pub fn to_pal(&self, emphasis: bool) -> Vec<u8> {
+        let count = if emphasis { 512 } else { 64 };
+        self.colors[..count].iter().flatten().copied().collect()
+    }
+
+    pub fn save(&self, path: &Path, emphasis: bool) -> io::Result<()> {
+        fs::write(path, self.to_pal(emphasis))
+    }
+
+    pub fn rgb(&self, pixel: u16) -> [u8; 3] {
+        self.colors[(pixel & 0x1ff) as usize]
+    }
+
+    /// Converts a frame to packed RGB24.
+    pub fn frame_to_rgb(&self, frame: &Frame, rgb: &mut [u8]) {
+        for (pixel, out) in frame.data.iter().zip(rgb.chunks_exact_mut(3)) {
+            out.copy_from_slice(&self.rgb(*pixel));
+        }
+    }
+}
+
+//the PPU draws each dot as samples of a square wave between two levels, 12 to a cycle of
+//the color subcarrier, and the phase of the wave is the hue. Averaging a cycle gives
+//luma, multiplying by the subcarrier first gives I and Q.
+fn ntsc_color(pixel: u16, params: &NtscParams) -> [u8; 3] {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for phase in 0..12 {
+        let level = signal_level(pixel, phase);
+        let angle = subcarrier_angle(phase, params);
+        y += level;
+        i += level * angle.cos();
+        q += level * angle.sin();
+    }
+    yiq_to_rgb(y / 12.0, i / 12.0, q / 12.0, params)
+}
+
+/// The signal for `pixel` at a phase of the subcarrier, 0 at black and 1 at white.
+pub(crate) fn signal_level(pixel: u16, phase: usize) -> f64 {
+    let hue = (pixel & 0x0f) as usize;
+    let row = ((pixel >> 4) & 0x03) as usize;
+    let emphasis = pixel >> 6;
+    let (low, high) = match hue {
+        0x00 => (LEVELS_HIGH[row], LEVELS_HIGH[row]),
+        0x0d => (LEVELS_LOW[row], LEVELS_LOW[row]),
+        0x0e | 0x0f => (BLACK, BLACK),
+        _ => (LEVELS_LOW[row], LEVELS_HIGH[row]),
+    };
+    let in_phase = |phase: usize| (hue + phase) % 12 < 6;
+
+    let mut level = if in_phase(phase) { high } else { low };
+    //each emphasis bit pulls the signal down during a third of the wave
+    let emphasized = (0..3).any(|bit| emphasis & (1 << bit) != 0 && in_phase(phase + bit * 4));
+    if emphasized && hue < 0x0e {
+        level *= EMPHASIS_ATTENUATION;
+    }
+    (level - BLACK) / (WHITE - BLACK)
+}
+
+/// Where the decoder's subcarrier is at a phase, in radians.
+pub(crate) fn subcarrier_angle(phase: usize, params: &NtscParams) -> f64 {
+    std::f64::consts::PI * ((phase % 12) as f64 + BURST_PHASE) / 6.0 - params.hue.to_radians()
+}
+
+/// Applies the picture controls to decoded YIQ and converts it to RGB.
+pub(crate) fn yiq_to_rgb(y: f64, i: f64, q: f64, params: &NtscParams) -> [u8; 3] {
+    let y = y * params.contrast + params.brightness;
+    let chroma = params.saturation * params.contrast;
+    let (i, q) = (i * chroma, q * chroma);
+
+    let channel = |value: f64| {
+        let mut value = value.clamp(0.0, 1.0);
+        //the composite filter gets here for every pixel, powf is slow
+        if params.gamma != 1.0 {
+            value = value.powf(1.0 / params.gamma);
+        }
+        (value * 255.0).round() as u8
+    };
+    [
+        channel(y + 0.946882 * i + 0.623557 * q),
+        channel(y - 0.274788 * i - 0.635691 * q),
+        channel(y - 1.108545 * i + 1.709007 * q),
+    ]
+}
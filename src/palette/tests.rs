@@ -0,0 +1,90 @@
+use super::*;
+use crate::test_util::temp_path;
+
+#[test]
+fn test_ntsc_hues() {
+    let palette = Palette::ntsc(&NtscParams::new());
+    let dominant = |pixel: u16| {
+        let rgb = palette.rgb(pixel);
+        (0..3).max_by_key(|&channel| rgb[channel]).unwrap()
+    };
+    assert_eq!(dominant(0x16), 0);
+    assert_eq!(dominant(0x1a), 1);
+    assert_eq!(dominant(0x12), 2);
+
+    assert_eq!(palette.rgb(0x0f), [0, 0, 0]);
+    assert_eq!(palette.rgb(0x1d), [0, 0, 0]);
+    assert_eq!(palette.rgb(0x20), [255, 255, 255]);
+    let grey = palette.rgb(0x10);
+    assert!(grey[0] == grey[1] && grey[1] == grey[2] && grey[0] > 0x80 && grey[0] < 0xff);
+}
+
+#[test]
+fn test_ntsc_params() {
+    let normal = Palette::ntsc(&NtscParams::new());
+    let mut params = NtscParams::new();
+    params.saturation = 0.0;
+    let grey = Palette::ntsc(&params).rgb(0x16);
+    assert!(grey[0] == grey[1] && grey[1] == grey[2]);
+
+    let mut params = NtscParams::new();
+    params.brightness = 0.1;
+    assert!(Palette::ntsc(&params).rgb(0x10)[0] > normal.rgb(0x10)[0]);
+
+    //a third of the way around the color wheel turns red into green
+    let mut params = NtscParams::new();
+    params.hue = 120.0;
+    let rotated = Palette::ntsc(&params);
+    assert_eq!(rotated.rgb(0x16).iter().max(), Some(&rotated.rgb(0x16)[1]));
+}
+
+#[test]
+fn test_emphasis() {
+    for palette in [Palette::new(), Palette::ntsc(&NtscParams::new())] {
+        //red emphasis keeps red and darkens the rest
+        let white = palette.rgb(0x30);
+        let red = palette.rgb(0x30 | 0x40);
+        assert!(red[0] > red[1] && red[0] > red[2]);
+        assert!(red[1] < white[1] && red[2] < white[2]);
+        let all = palette.rgb(0x30 | 0x1c0);
+        assert!(all.iter().zip(white.iter()).all(|(all, white)| all < white));
+        //black stays black
+        assert_eq!(palette.rgb(0x0f | 0x1c0), palette.rgb(0x0f));
+    }
+}
+
+#[test]
+fn test_pal_files() {
+    let palette = Palette::ntsc(&NtscParams::new());
+    let path = temp_path("full.pal");
+    palette.save(&path, true).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 1536);
+    assert_eq!(Palette::open(&path).unwrap(), palette);
+
+    //64 colors get the emphasis added on load
+    palette.save(&path, false).unwrap();
+    let loaded = Palette::open(&path).unwrap();
+    assert_eq!(loaded.rgb(0x16), palette.rgb(0x16));
+    assert_ne!(loaded.rgb(0x16 | 0x80), loaded.rgb(0x16));
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        Palette::parse_pal(&palette.to_pal(false))
+            .unwrap()
+            .to_pal(false),
+        palette.to_pal(false)
+    );
+    assert!(Palette::parse_pal(&[0; 100]).is_err());
+    assert!(Palette::open(&temp_path("missing.pal")).is_err());
+}
+
+#[test]
+fn test_frame_to_rgb() {
+    let palette = Palette::new();
+    let mut frame = Frame::new();
+    frame.set_pixel(1, 0, 0x16 | 0x100);
+    let mut rgb = vec![0; Frame::WIDTH * Frame::HEIGHT * 3];
+    palette.frame_to_rgb(&frame, &mut rgb);
+    assert_eq!(rgb[0..3], palette.rgb(0x00));
+    assert_eq!(rgb[3..6], palette.rgb(0x116));
+}
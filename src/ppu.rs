@@ -0,0 +1,596 @@
+use crate::cartridge::Mirroring;
+use crate::cdl::{CHR_DRAWN, CHR_READ};
+use crate::frame::Frame;
+use crate::region::{Timing, NTSC};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+#[cfg(test)]
+mod tests;
+
+const DOTS_PER_SCANLINE: u16 = 341;
+
+// PPUCTRL $2000
+const CTRL_NAMETABLE: u8 = 0b0000_0011;
+const CTRL_VRAM_INCREMENT: u8 = 0b0000_0100;
+const CTRL_SPRITE_PATTERN: u8 = 0b0000_1000;
+const CTRL_BACKGROUND_PATTERN: u8 = 0b0001_0000;
+const CTRL_SPRITE_SIZE: u8 = 0b0010_0000;
+const CTRL_GENERATE_NMI: u8 = 0b1000_0000;
+
+// PPUMASK $2001
+const MASK_GRAYSCALE: u8 = 0b0000_0001;
+const MASK_LEFT_BACKGROUND: u8 = 0b0000_0010;
+const MASK_LEFT_SPRITES: u8 = 0b0000_0100;
+const MASK_SHOW_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SHOW_SPRITES: u8 = 0b0001_0000;
+
+// PPUSTATUS $2002
+const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+
+pub struct NesPPU {
+    pub chr_rom: Vec<u8>,
+    chr_ram: bool,
+    pub palette_table: [u8; 32],
+    pub vram: [u8; 4096],
+    pub oam_data: [u8; 256],
+    pub mirroring: Mirroring,
+
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+    // "loopy" registers: current and temporary VRAM address, fine x scroll and the write toggle
+    pub v: u16,
+    pub t: u16,
+    pub fine_x: u8,
+    pub write_toggle: bool,
+    read_buffer: u8,
+    //last value written to any register, seen in the unused bits of PPUSTATUS
+    io_latch: u8,
+
+    pub scanline: u16,
+    pub dot: u16,
+    pub odd_frame: bool,
+    nmi_interrupt: bool,
+    frame_complete: bool,
+    sprite_zero_hit_dot: Option<u16>,
+    //code/data log flags of CHR-ROM while logging is on
+    chr_log: Option<Vec<u8>>,
+    //frame length and vblank line of the console's region
+    timing: &'static Timing,
+
+    pub frame: Frame,
+}
+
+impl NesPPU {
+    pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        NesPPU {
+            chr_rom: if chr_ram { vec![0; 0x2000] } else { chr_rom },
+            chr_ram,
+            palette_table: [0; 32],
+            vram: [0; 4096],
+            oam_data: [0; 256],
+            mirroring,
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            write_toggle: false,
+            read_buffer: 0,
+            io_latch: 0,
+            scanline: 0,
+            dot: 0,
+            odd_frame: false,
+            nmi_interrupt: false,
+            frame_complete: false,
+            sprite_zero_hit_dot: None,
+            chr_log: None,
+            timing: &NTSC,
+            frame: Frame::new(),
+        }
+    }
+
+    /// PPU with 8 KiB of CHR-RAM, used when no cartridge is inserted.
+    pub fn new_empty_rom() -> Self {
+        NesPPU::new(Vec::new(), Mirroring::Horizontal)
+    }
+
+    /// Back to the power on state, keeping the cartridge's CHR-ROM.
+    pub fn power_cycle(&mut self) {
+        let chr_rom = if self.chr_ram {
+            Vec::new()
+        } else {
+            std::mem::take(&mut self.chr_rom)
+        };
+        let chr_log = self.chr_log.take();
+        let timing = self.timing;
+        *self = NesPPU::new(chr_rom, self.mirroring);
+        self.chr_log = chr_log;
+        self.timing = timing;
+    }
+
+    pub fn set_timing(&mut self, timing: &'static Timing) {
+        self.timing = timing;
+    }
+
+    /// Starts or stops marking the CHR bytes that are drawn or read, see `cdl`.
+    pub fn set_chr_log(&mut self, log: Option<Vec<u8>>) {
+        self.chr_log = log;
+    }
+
+    pub fn chr_log(&self) -> Option<&[u8]> {
+        self.chr_log.as_deref()
+    }
+
+    fn log_chr(&mut self, addr: usize, flags: u8) {
+        if let Some(byte) = self.chr_log.as_mut().and_then(|log| log.get_mut(addr)) {
+            *byte |= flags;
+        }
+    }
+
+    /// Advances the PPU by the given number of dots, 3 per CPU cycle on NTSC and 3.2 on PAL.
+    pub fn tick(&mut self, dots: u16) {
+        for _ in 0..dots {
+            self.tick_dot();
+        }
+    }
+
+    fn tick_dot(&mut self) {
+        let pre_render_scanline = self.timing.scanlines - 1;
+        let vblank_scanline = self.timing.vblank_scanline;
+        self.dot += 1;
+        //with rendering enabled the pre-render line is one dot shorter on odd NTSC frames
+        if self.scanline == pre_render_scanline
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.odd_frame
+            && self.timing.odd_frame_skip
+            && self.rendering_enabled()
+        {
+            self.dot = DOTS_PER_SCANLINE;
+        }
+        if self.dot >= DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > pre_render_scanline {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+
+        match (self.scanline, self.dot) {
+            (0..=239, 1) => self.render_scanline(),
+            (line, 1) if line == vblank_scanline => {
+                self.status |= STATUS_VBLANK;
+                self.frame_complete = true;
+                if self.ctrl & CTRL_GENERATE_NMI != 0 {
+                    self.nmi_interrupt = true;
+                }
+            }
+            (line, 1) if line == pre_render_scanline => {
+                self.status &= !(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW);
+            }
+            _ => {}
+        }
+
+        if let Some(hit_dot) = self.sprite_zero_hit_dot {
+            if self.dot >= hit_dot {
+                self.status |= STATUS_SPRITE_ZERO_HIT;
+                self.sprite_zero_hit_dot = None;
+            }
+        }
+
+        if self.rendering_enabled() && (self.scanline < 240 || self.scanline == pre_render_scanline)
+        {
+            match self.dot {
+                256 => self.increment_y(),
+                //copy the horizontal scroll bits of t into v
+                257 => self.v = (self.v & !0x041f) | (self.t & 0x041f),
+                280..=304 if self.scanline == pre_render_scanline => {
+                    self.v = (self.v & !0x7be0) | (self.t & 0x7be0)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn poll_nmi_interrupt(&mut self) -> bool {
+        std::mem::replace(&mut self.nmi_interrupt, false)
+    }
+
+    /// True once per frame when vblank starts.
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::replace(&mut self.frame_complete, false)
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask & (MASK_SHOW_BACKGROUND | MASK_SHOW_SPRITES) != 0
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03e0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (coarse_y << 5);
+    }
+
+    pub fn write_to_ctrl(&mut self, value: u8) {
+        let before_nmi_status = self.ctrl & CTRL_GENERATE_NMI != 0;
+        self.ctrl = value;
+        self.t = (self.t & !0x0c00) | (((value & CTRL_NAMETABLE) as u16) << 10);
+        //enabling NMI during vblank triggers it immediately
+        if !before_nmi_status && value & CTRL_GENERATE_NMI != 0 && self.status & STATUS_VBLANK != 0
+        {
+            self.nmi_interrupt = true;
+        }
+    }
+
+    pub fn write_to_mask(&mut self, value: u8) {
+        self.mask = value;
+    }
+
+    pub fn read_status(&mut self) -> u8 {
+        let data = (self.status & 0b1110_0000) | (self.io_latch & 0b0001_1111);
+        self.status &= !STATUS_VBLANK;
+        self.write_toggle = false;
+        data
+    }
+
+    pub fn write_to_oam_addr(&mut self, value: u8) {
+        self.oam_addr = value;
+    }
+
+    pub fn write_to_oam_data(&mut self, value: u8) {
+        self.oam_data[self.oam_addr as usize] = value;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    pub fn read_oam_data(&self) -> u8 {
+        self.oam_data[self.oam_addr as usize]
+    }
+
+    pub fn write_to_scroll(&mut self, value: u8) {
+        if !self.write_toggle {
+            self.t = (self.t & !0x001f) | (value >> 3) as u16;
+            self.fine_x = value & 0x07;
+        } else {
+            self.t = (self.t & !0x73e0)
+                | (((value & 0x07) as u16) << 12)
+                | (((value & 0xf8) as u16) << 2);
+        }
+        self.write_toggle = !self.write_toggle;
+    }
+
+    pub fn write_to_ppu_addr(&mut self, value: u8) {
+        if !self.write_toggle {
+            self.t = (self.t & 0x00ff) | (((value & 0x3f) as u16) << 8);
+        } else {
+            self.t = (self.t & 0xff00) | value as u16;
+            self.v = self.t;
+        }
+        self.write_toggle = !self.write_toggle;
+    }
+
+    pub fn write_oam_dma(&mut self, data: &[u8; 256]) {
+        for byte in data.iter() {
+            self.write_to_oam_data(*byte);
+        }
+    }
+
+    pub fn latch_io(&mut self, value: u8) {
+        self.io_latch = value;
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let step = if self.ctrl & CTRL_VRAM_INCREMENT != 0 {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(step) & 0x7fff;
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        let addr = self.v & 0x3fff;
+        self.increment_vram_addr();
+
+        match addr {
+            0x0000..=0x2fff => {
+                if addr < 0x2000 {
+                    self.log_chr(addr as usize, CHR_READ);
+                }
+                let result = self.read_buffer;
+                self.read_buffer = self.read_vram(addr);
+                result
+            }
+            0x3000..=0x3eff => {
+                let result = self.read_buffer;
+                self.read_buffer = self.read_vram(addr - 0x1000);
+                result
+            }
+            _ => {
+                //palette reads are not buffered, the buffer gets the nametable byte underneath
+                self.read_buffer = self.read_vram(addr - 0x1000);
+                self.palette_table[palette_index(addr)]
+            }
+        }
+    }
+
+    pub fn write_to_data(&mut self, value: u8) {
+        let addr = self.v & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => {
+                if self.chr_ram {
+                    self.chr_rom[addr as usize] = value;
+                }
+            }
+            0x2000..=0x3eff => {
+                let index = self.mirror_vram_addr(addr);
+                self.vram[index] = value;
+            }
+            _ => self.palette_table[palette_index(addr)] = value,
+        }
+        self.increment_vram_addr();
+    }
+
+    fn read_vram(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.chr_rom[addr as usize],
+            _ => self.vram[self.mirror_vram_addr(addr)],
+        }
+    }
+
+    // Horizontal:
+    //   [ A ] [ a ]
+    //   [ B ] [ b ]
+
+    // Vertical:
+    //   [ A ] [ B ]
+    //   [ a ] [ b ]
+    fn mirror_vram_addr(&self, addr: u16) -> usize {
+        let vram_index = (addr & 0x0fff) as usize;
+        let name_table = vram_index / 0x400;
+        let offset = vram_index % 0x400;
+        match (&self.mirroring, name_table) {
+            (Mirroring::FourScreen, _) => vram_index,
+            (Mirroring::Vertical, _) => (name_table % 2) * 0x400 + offset,
+            (Mirroring::Horizontal, _) => (name_table / 2) * 0x400 + offset,
+        }
+    }
+
+    fn render_scanline(&mut self) {
+        let y = self.scanline as usize;
+        let mut background = [0u8; Frame::WIDTH];
+        let mut background_opaque = [false; Frame::WIDTH];
+
+        if self.mask & MASK_SHOW_BACKGROUND != 0 {
+            self.render_background_line(&mut background, &mut background_opaque);
+        }
+
+        let mut line = [self.palette_table[0]; Frame::WIDTH];
+        for x in 0..Frame::WIDTH {
+            if background_opaque[x] {
+                line[x] = self.palette_table[background[x] as usize];
+            }
+        }
+
+        if self.mask & MASK_SHOW_SPRITES != 0 {
+            self.render_sprite_line(y, &background_opaque, &mut line);
+        }
+
+        let grayscale = if self.mask & MASK_GRAYSCALE != 0 {
+            0x30
+        } else {
+            0x3f
+        };
+        let emphasis = ((self.mask >> 5) as u16) << 6;
+        for (x, color) in line.iter().enumerate() {
+            self.frame
+                .set_pixel(x, y, (*color & grayscale) as u16 | emphasis);
+        }
+    }
+
+    fn render_background_line(&mut self, palette: &mut [u8], opaque: &mut [bool]) {
+        let pattern_base = if self.ctrl & CTRL_BACKGROUND_PATTERN != 0 {
+            0x1000
+        } else {
+            0
+        };
+        let fine_y = (self.v >> 12) & 0x07;
+        let coarse_y = (self.v >> 5) & 0x1f;
+
+        for x in 0..Frame::WIDTH {
+            if x < 8 && self.mask & MASK_LEFT_BACKGROUND == 0 {
+                continue;
+            }
+            let scrolled_x = x + self.fine_x as usize;
+            let mut coarse_x = (self.v & 0x1f) + (scrolled_x / 8) as u16;
+            let mut name_table = (self.v >> 10) & 0x03;
+            if coarse_x >= 32 {
+                coarse_x -= 32;
+                name_table ^= 0x01;
+            }
+
+            let tile_addr = 0x2000 | (name_table << 10) | (coarse_y << 5) | coarse_x;
+            let tile = self.read_vram(tile_addr) as u16;
+            let attribute_addr =
+                0x23c0 | (name_table << 10) | ((coarse_y >> 2) << 3) | (coarse_x >> 2);
+            let shift = ((coarse_y & 0x02) << 1) | (coarse_x & 0x02);
+            let palette_number = (self.read_vram(attribute_addr) >> shift) & 0x03;
+
+            let pattern_addr = pattern_base + tile * 16 + fine_y;
+            self.log_chr(pattern_addr as usize, CHR_DRAWN);
+            self.log_chr(pattern_addr as usize + 8, CHR_DRAWN);
+            let bit = 7 - (scrolled_x % 8);
+            let lo = (self.chr_rom[pattern_addr as usize] >> bit) & 1;
+            let hi = (self.chr_rom[pattern_addr as usize + 8] >> bit) & 1;
+            let value = (hi << 1) | lo;
+
+            if value != 0 {
+                palette[x] = palette_number * 4 + value;
+                opaque[x] = true;
+            }
+        }
+    }
+
+    fn render_sprite_line(&mut self, y: usize, background_opaque: &[bool], line: &mut [u8]) {
+        let height = if self.ctrl & CTRL_SPRITE_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+        let mut drawn = [false; Frame::WIDTH];
+        let mut sprites_on_line = 0;
+
+        for index in 0..64 {
+            let mut sprite = [0; 4];
+            sprite.copy_from_slice(&self.oam_data[index * 4..index * 4 + 4]);
+            //OAM holds the Y coordinate minus one
+            let row = y as isize - (sprite[0] as isize + 1);
+            if row < 0 || row >= height {
+                continue;
+            }
+            sprites_on_line += 1;
+            if sprites_on_line > 8 {
+                self.status |= STATUS_SPRITE_OVERFLOW;
+                break;
+            }
+
+            let attributes = sprite[2];
+            let flip_vertical = attributes & 0b1000_0000 != 0;
+            let flip_horizontal = attributes & 0b0100_0000 != 0;
+            let behind_background = attributes & 0b0010_0000 != 0;
+            let palette_number = attributes & 0b11;
+            let row = if flip_vertical { height - 1 - row } else { row } as u16;
+
+            let tile = sprite[1] as u16;
+            let pattern_addr = if height == 16 {
+                let bank = (tile & 1) * 0x1000;
+                let tile = (tile & 0xfe) + row / 8;
+                bank + tile * 16 + row % 8
+            } else {
+                let bank = if self.ctrl & CTRL_SPRITE_PATTERN != 0 {
+                    0x1000
+                } else {
+                    0
+                };
+                bank + tile * 16 + row
+            } as usize;
+            let lo = self.chr_rom[pattern_addr];
+            let hi = self.chr_rom[pattern_addr + 8];
+            self.log_chr(pattern_addr, CHR_DRAWN);
+            self.log_chr(pattern_addr + 8, CHR_DRAWN);
+
+            for column in 0..8 {
+                let x = sprite[3] as usize + column;
+                if x >= Frame::WIDTH || drawn[x] {
+                    continue;
+                }
+                if x < 8 && self.mask & MASK_LEFT_SPRITES == 0 {
+                    continue;
+                }
+                let bit = if flip_horizontal { column } else { 7 - column };
+                let value = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+                if value == 0 {
+                    continue;
+                }
+                //a lower OAM index always wins, even when it is hidden behind the background
+                drawn[x] = true;
+
+                if index == 0
+                    && background_opaque[x]
+                    && x != 255
+                    && self.status & STATUS_SPRITE_ZERO_HIT == 0
+                    && self.sprite_zero_hit_dot.is_none()
+                {
+                    self.sprite_zero_hit_dot = Some(x as u16 + 1);
+                }
+                if !(behind_background && background_opaque[x]) {
+                    line[x] = self.palette_table[0x10 + (palette_number * 4 + value) as usize];
+                }
+            }
+        }
+    }
+}
+
+impl Snapshot for NesPPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        //CHR-ROM comes from the cartridge, only CHR-RAM is state
+        if self.chr_ram {
+            w.write_bytes(&self.chr_rom);
+        }
+        w.write_bytes(&self.palette_table);
+        w.write_bytes(&self.vram);
+        w.write_bytes(&self.oam_data);
+        w.write_u8(self.ctrl);
+        w.write_u8(self.mask);
+        w.write_u8(self.status);
+        w.write_u8(self.oam_addr);
+        w.write_u16(self.v);
+        w.write_u16(self.t);
+        w.write_u8(self.fine_x);
+        w.write_bool(self.write_toggle);
+        w.write_u8(self.read_buffer);
+        w.write_u8(self.io_latch);
+        w.write_u16(self.scanline);
+        w.write_u16(self.dot);
+        w.write_bool(self.odd_frame);
+        w.write_bool(self.nmi_interrupt);
+        w.write_bool(self.frame_complete);
+        w.write_u16(self.sprite_zero_hit_dot.unwrap_or(u16::MAX));
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        if self.chr_ram {
+            r.read_into(&mut self.chr_rom)?;
+        }
+        r.read_into(&mut self.palette_table)?;
+        r.read_into(&mut self.vram)?;
+        r.read_into(&mut self.oam_data)?;
+        self.ctrl = r.read_u8()?;
+        self.mask = r.read_u8()?;
+        self.status = r.read_u8()?;
+        self.oam_addr = r.read_u8()?;
+        self.v = r.read_u16()?;
+        self.t = r.read_u16()?;
+        self.fine_x = r.read_u8()?;
+        self.write_toggle = r.read_bool()?;
+        self.read_buffer = r.read_u8()?;
+        self.io_latch = r.read_u8()?;
+        self.scanline = r.read_u16()?;
+        self.dot = r.read_u16()?;
+        self.odd_frame = r.read_bool()?;
+        self.nmi_interrupt = r.read_bool()?;
+        self.frame_complete = r.read_bool()?;
+        self.sprite_zero_hit_dot = match r.read_u16()? {
+            u16::MAX => None,
+            dot => Some(dot),
+        };
+        Ok(())
+    }
+}
+
+fn palette_index(addr: u16) -> usize {
+    let index = (addr & 0x1f) as usize;
+    //$3F10/$3F14/$3F18/$3F1C mirror the background entries
+    match index {
+        0x10 | 0x14 | 0x18 | 0x1c => index - 0x10,
+        _ => index,
+    }
+}
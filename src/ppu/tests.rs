@@ -0,0 +1,158 @@
+use crate::cartridge::Mirroring;
+use crate::ppu::NesPPU;
+
+fn set_addr(ppu: &mut NesPPU, addr: u16) {
+    ppu.write_to_ppu_addr((addr >> 8) as u8);
+    ppu.write_to_ppu_addr((addr & 0xff) as u8);
+}
+
+#[test]
+fn test_ppu_vram_writes() {
+    let mut ppu = NesPPU::new_empty_rom();
+    set_addr(&mut ppu, 0x2305);
+    ppu.write_to_data(0x66);
+
+    assert_eq!(ppu.vram[0x0305], 0x66);
+}
+
+#[test]
+fn test_ppu_vram_reads_are_buffered() {
+    let mut ppu = NesPPU::new_empty_rom();
+    ppu.vram[0x0305] = 0x66;
+    ppu.vram[0x0306] = 0x77;
+    set_addr(&mut ppu, 0x2305);
+
+    assert_eq!(ppu.read_data(), 0x00);
+    assert_eq!(ppu.read_data(), 0x66);
+    assert_eq!(ppu.read_data(), 0x77);
+}
+
+#[test]
+fn test_ppu_vram_reads_step_32() {
+    let mut ppu = NesPPU::new_empty_rom();
+    ppu.write_to_ctrl(0b0000_0100);
+    ppu.vram[0x01ff] = 0x66;
+    ppu.vram[0x01ff + 32] = 0x77;
+    set_addr(&mut ppu, 0x21ff);
+
+    ppu.read_data();
+    assert_eq!(ppu.read_data(), 0x66);
+    assert_eq!(ppu.read_data(), 0x77);
+}
+
+// Horizontal: https://wiki.nesdev.com/w/index.php/Mirroring
+//   [0x2000 A ] [0x2400 a ]
+//   [0x2800 B ] [0x2C00 b ]
+#[test]
+fn test_vram_horizontal_mirror() {
+    let mut ppu = NesPPU::new(vec![0; 0x2000], Mirroring::Horizontal);
+    set_addr(&mut ppu, 0x2405);
+    ppu.write_to_data(0x66);
+    set_addr(&mut ppu, 0x2805);
+    ppu.write_to_data(0x77);
+
+    set_addr(&mut ppu, 0x2005);
+    ppu.read_data();
+    assert_eq!(ppu.read_data(), 0x66);
+
+    set_addr(&mut ppu, 0x2c05);
+    ppu.read_data();
+    assert_eq!(ppu.read_data(), 0x77);
+}
+
+// Vertical:
+//   [0x2000 A ] [0x2400 B ]
+//   [0x2800 a ] [0x2C00 b ]
+#[test]
+fn test_vram_vertical_mirror() {
+    let mut ppu = NesPPU::new(vec![0; 0x2000], Mirroring::Vertical);
+    set_addr(&mut ppu, 0x2005);
+    ppu.write_to_data(0x66);
+    set_addr(&mut ppu, 0x2c05);
+    ppu.write_to_data(0x77);
+
+    set_addr(&mut ppu, 0x2805);
+    ppu.read_data();
+    assert_eq!(ppu.read_data(), 0x66);
+
+    set_addr(&mut ppu, 0x2405);
+    ppu.read_data();
+    assert_eq!(ppu.read_data(), 0x77);
+}
+
+#[test]
+fn test_palette_mirrors() {
+    let mut ppu = NesPPU::new_empty_rom();
+    set_addr(&mut ppu, 0x3f10);
+    ppu.write_to_data(0x2c);
+
+    set_addr(&mut ppu, 0x3f00);
+    assert_eq!(ppu.read_data(), 0x2c);
+}
+
+#[test]
+fn test_read_status_resets_latch_and_vblank() {
+    let mut ppu = NesPPU::new_empty_rom();
+    ppu.status = 0b1000_0000;
+    ppu.write_to_ppu_addr(0x21);
+
+    assert_eq!(ppu.read_status() >> 7, 1);
+    assert_eq!(ppu.status >> 7, 0);
+    assert!(!ppu.write_toggle);
+}
+
+#[test]
+fn test_vblank_nmi() {
+    let mut ppu = NesPPU::new_empty_rom();
+    ppu.write_to_ctrl(0b1000_0000);
+    //scanline 241, dot 1
+    for _ in 0..241 {
+        ppu.tick(341);
+    }
+    assert!(!ppu.poll_nmi_interrupt());
+    ppu.tick(1);
+    assert!(ppu.poll_nmi_interrupt());
+    assert!(ppu.take_frame_complete());
+    assert_eq!(ppu.status & 0b1000_0000, 0b1000_0000);
+
+    for _ in 0..20 {
+        ppu.tick(341);
+    }
+    assert_eq!(ppu.status & 0b1000_0000, 0);
+}
+
+#[test]
+fn test_render_background_tile() {
+    let mut chr = vec![0; 0x2000];
+    //tile 1, every pixel uses color 3
+    for byte in chr[16..32].iter_mut() {
+        *byte = 0xff;
+    }
+    let mut ppu = NesPPU::new(chr, Mirroring::Horizontal);
+    ppu.vram[0] = 1;
+    ppu.palette_table[0] = 0x0f;
+    ppu.palette_table[3] = 0x30;
+    ppu.write_to_mask(0b0000_1010);
+
+    ppu.tick(2 * 341);
+    assert_eq!(ppu.frame.get_pixel(0, 0), 0x30);
+    assert_eq!(ppu.frame.get_pixel(7, 1), 0x30);
+    assert_eq!(ppu.frame.get_pixel(8, 0), 0x0f);
+}
+
+#[test]
+fn test_sprite_zero_hit() {
+    let mut chr = vec![0; 0x2000];
+    for byte in chr[16..24].iter_mut() {
+        *byte = 0xff;
+    }
+    let mut ppu = NesPPU::new(chr, Mirroring::Horizontal);
+    ppu.vram[0] = 1;
+    ppu.oam_data[0..4].copy_from_slice(&[0, 1, 0, 4]);
+    ppu.write_to_mask(0b0001_1110);
+
+    ppu.tick(341 + 4);
+    assert_eq!(ppu.status & 0b0100_0000, 0);
+    ppu.tick(2);
+    assert_eq!(ppu.status & 0b0100_0000, 0b0100_0000);
+}
@@ -0,0 +1,384 @@
+use crate::cpu::{CpuError, CPU};
+use crate::debugger::{track_call, CallFrame};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+#[cfg(test)]
+mod tests;
+
+//rows in each table of the text report
+const REPORT_LINES: usize = 30;
+//what runs outside of any call seen by the profiler, usually the reset code's main loop
+const TOP_LEVEL: &str = "[top]";
+
+/// Reads and writes made by the CPU per address.
+pub struct AccessCounts {
+    pub reads: Vec<u64>,
+    pub writes: Vec<u64>,
+}
+
+impl Default for AccessCounts {
+    fn default() -> Self {
+        AccessCounts {
+            reads: vec![0; 0x10000],
+            writes: vec![0; 0x10000],
+        }
+    }
+}
+
+impl AccessCounts {
+    pub fn add(&mut self, addr: u16, write: bool) {
+        if write {
+            self.writes[addr as usize] += 1;
+        } else {
+            self.reads[addr as usize] += 1;
+        }
+    }
+}
+
+/// Cycles spent in one function, which is everything from a JSR or interrupt to its return.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionStats {
+    //None for the top level
+    pub address: Option<u16>,
+    pub calls: u64,
+    //with the functions it calls, recursion counted once
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+//call tree, one node per distinct call path
+struct Node {
+    function: Option<u16>,
+    parent: usize,
+    calls: u64,
+    cycles: u64,
+    children: HashMap<u16, usize>,
+}
+
+/// Attributes the CPU's cycles to instructions and functions, attached with `Nes::set_profiler`.
+pub struct Profiler {
+    pc_cycles: Vec<u64>,
+    pc_counts: Vec<u64>,
+    nodes: Vec<Node>,
+    call_stack: Vec<CallFrame>,
+    //call tree node of every frame of `call_stack`
+    path: Vec<usize>,
+    access_counts: Box<AccessCounts>,
+    total_cycles: u64,
+    instructions: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            pc_cycles: vec![0; 0x10000],
+            pc_counts: vec![0; 0x10000],
+            nodes: vec![Node {
+                function: None,
+                parent: 0,
+                calls: 0,
+                cycles: 0,
+                children: HashMap::new(),
+            }],
+            call_stack: Vec::new(),
+            path: Vec::new(),
+            access_counts: Box::default(),
+            total_cycles: 0,
+            instructions: 0,
+        }
+    }
+
+    /// Executes one instruction on `cpu` and records where its cycles went.
+    pub fn step(&mut self, cpu: &mut CPU) -> Result<u16, CpuError> {
+        let pc = cpu.program_counter;
+        let sp = cpu.stack_pointer;
+        let opcode = cpu.bus.peek(pc);
+        let cycles = cpu.step()?;
+
+        //the cycles of a call or return belong to the caller
+        self.pc_cycles[pc as usize] += cycles as u64;
+        self.pc_counts[pc as usize] += 1;
+        let node = self.path.last().copied().unwrap_or(0);
+        self.nodes[node].cycles += cycles as u64;
+        self.total_cycles += cycles as u64;
+        self.instructions += 1;
+
+        track_call(&mut self.call_stack, cpu, pc, sp, opcode);
+        self.path.truncate(self.call_stack.len());
+        if let Some(frame) = self.call_stack.get(self.path.len()) {
+            let child = self.child(node, frame.target);
+            self.nodes[child].calls += 1;
+            self.path.push(child);
+        }
+        Ok(cycles)
+    }
+
+    fn child(&mut self, parent: usize, function: u16) -> usize {
+        if let Some(&child) = self.nodes[parent].children.get(&function) {
+            return child;
+        }
+        let child = self.nodes.len();
+        self.nodes.push(Node {
+            function: Some(function),
+            parent,
+            calls: 0,
+            cycles: 0,
+            children: HashMap::new(),
+        });
+        self.nodes[parent].children.insert(function, child);
+        child
+    }
+
+    pub fn add_access_counts(&mut self, counts: &AccessCounts) {
+        for (total, count) in self.access_counts.reads.iter_mut().zip(&counts.reads) {
+            *total += count;
+        }
+        for (total, count) in self.access_counts.writes.iter_mut().zip(&counts.writes) {
+            *total += count;
+        }
+    }
+
+    pub fn access_counts(&self) -> &AccessCounts {
+        &self.access_counts
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Cycles of the instructions at `pc`.
+    pub fn cycles_at(&self, pc: u16) -> u64 {
+        self.pc_cycles[pc as usize]
+    }
+
+    /// Cycles of all instructions starting in `range`.
+    pub fn cycles_in(&self, range: RangeInclusive<u16>) -> u64 {
+        self.pc_cycles[*range.start() as usize..=*range.end() as usize]
+            .iter()
+            .sum()
+    }
+
+    /// Every function seen, most inclusive cycles first.
+    pub fn functions(&self) -> Vec<FunctionStats> {
+        //children always come after their parent
+        let mut totals: Vec<u64> = self.nodes.iter().map(|node| node.cycles).collect();
+        for (index, node) in self.nodes.iter().enumerate().skip(1).rev() {
+            totals[node.parent] += totals[index];
+        }
+
+        let mut functions: HashMap<Option<u16>, FunctionStats> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let stats = functions
+                .entry(node.function)
+                .or_insert_with(|| FunctionStats {
+                    address: node.function,
+                    calls: 0,
+                    inclusive: 0,
+                    exclusive: 0,
+                });
+            stats.calls += node.calls;
+            stats.exclusive += node.cycles;
+            if !self.is_recursive(index) {
+                stats.inclusive += totals[index];
+            }
+        }
+        let mut functions: Vec<FunctionStats> = functions.into_values().collect();
+        functions.sort_by(|a, b| {
+            b.inclusive
+                .cmp(&a.inclusive)
+                .then(a.address.cmp(&b.address))
+        });
+        functions
+    }
+
+    //true when the node's function is already further up its call path
+    fn is_recursive(&self, index: usize) -> bool {
+        let function = self.nodes[index].function;
+        let mut ancestor = index;
+        while ancestor != 0 {
+            ancestor = self.nodes[ancestor].parent;
+            if self.nodes[ancestor].function == function {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Call stacks with their exclusive cycles in the collapsed format of flamegraph.pl and inferno.
+    pub fn collapsed_stacks<F: Fn(u16) -> String>(&self, name: F) -> String {
+        let mut out = String::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.cycles == 0 {
+                continue;
+            }
+            let mut frames = Vec::new();
+            let mut current = index;
+            while current != 0 {
+                frames.push(name(self.nodes[current].function.unwrap_or(0)));
+                current = self.nodes[current].parent;
+            }
+            frames.push(TOP_LEVEL.to_string());
+            frames.reverse();
+            writeln!(out, "{} {}", frames.join(";"), node.cycles).unwrap();
+        }
+        out
+    }
+
+    /// Human readable report of the hottest functions, instructions and addresses.
+    pub fn text_report<F: Fn(u16) -> String>(&self, name: F) -> String {
+        let total = self.total_cycles.max(1) as f64;
+        let percent = |cycles: u64| cycles as f64 * 100.0 / total;
+        let function_name = |address: Option<u16>| match address {
+            Some(address) => name(address),
+            None => TOP_LEVEL.to_string(),
+        };
+        let mut out = format!(
+            "{} cycles in {} instructions\n\nFunctions\n{:>12} {:>6} {:>12} {:>6} {:>8}  function\n",
+            self.total_cycles, self.instructions, "inclusive", "%", "exclusive", "%", "calls"
+        );
+        for function in self.functions().iter().take(REPORT_LINES) {
+            writeln!(
+                out,
+                "{:>12} {:>6.2} {:>12} {:>6.2} {:>8}  {}",
+                function.inclusive,
+                percent(function.inclusive),
+                function.exclusive,
+                percent(function.exclusive),
+                function.calls,
+                function_name(function.address)
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "\nInstructions\n{:>12} {:>6} {:>10}  address",
+            "cycles", "%", "count"
+        )
+        .unwrap();
+        for pc in self.hot_spots().into_iter().take(REPORT_LINES) {
+            writeln!(
+                out,
+                "{:>12} {:>6.2} {:>10}  {}",
+                self.pc_cycles[pc as usize],
+                percent(self.pc_cycles[pc as usize]),
+                self.pc_counts[pc as usize],
+                name(pc)
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\nMemory\n{:>12} {:>12}  address", "reads", "writes").unwrap();
+        for addr in self.busiest_addresses().into_iter().take(REPORT_LINES) {
+            writeln!(
+                out,
+                "{:>12} {:>12}  {}",
+                self.access_counts.reads[addr as usize],
+                self.access_counts.writes[addr as usize],
+                name(addr)
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// The whole profile as JSON, addresses are numbers and `name` fills in the names.
+    pub fn json_report<F: Fn(u16) -> String>(&self, name: F) -> String {
+        let functions: Vec<String> = self
+            .functions()
+            .iter()
+            .map(|function| {
+                let (address, name) = match function.address {
+                    Some(address) => (address.to_string(), name(address)),
+                    None => ("null".to_string(), TOP_LEVEL.to_string()),
+                };
+                format!(
+                    "{{\"address\":{},\"name\":{},\"calls\":{},\"inclusive\":{},\"exclusive\":{}}}",
+                    address,
+                    json_string(&name),
+                    function.calls,
+                    function.inclusive,
+                    function.exclusive
+                )
+            })
+            .collect();
+        let instructions: Vec<String> = self
+            .hot_spots()
+            .into_iter()
+            .map(|pc| {
+                format!(
+                    "{{\"address\":{},\"name\":{},\"cycles\":{},\"count\":{}}}",
+                    pc,
+                    json_string(&name(pc)),
+                    self.pc_cycles[pc as usize],
+                    self.pc_counts[pc as usize]
+                )
+            })
+            .collect();
+        let memory: Vec<String> = self
+            .busiest_addresses()
+            .into_iter()
+            .map(|addr| {
+                format!(
+                    "{{\"address\":{},\"name\":{},\"reads\":{},\"writes\":{}}}",
+                    addr,
+                    json_string(&name(addr)),
+                    self.access_counts.reads[addr as usize],
+                    self.access_counts.writes[addr as usize]
+                )
+            })
+            .collect();
+        format!(
+            "{{\"total_cycles\":{},\"instructions\":{},\"functions\":[{}],\"instructions_by_address\":[{}],\"memory\":[{}]}}\n",
+            self.total_cycles,
+            self.instructions,
+            functions.join(","),
+            instructions.join(","),
+            memory.join(",")
+        )
+    }
+
+    //addresses of executed instructions, most cycles first
+    fn hot_spots(&self) -> Vec<u16> {
+        let mut pcs: Vec<u16> = (0..=0xffff)
+            .filter(|&pc| self.pc_counts[pc as usize] > 0)
+            .collect();
+        pcs.sort_by_key(|&pc| std::cmp::Reverse(self.pc_cycles[pc as usize]));
+        pcs
+    }
+
+    //accessed addresses, most reads and writes first
+    fn busiest_addresses(&self) -> Vec<u16> {
+        let counts = &self.access_counts;
+        let total = |addr: u16| counts.reads[addr as usize] + counts.writes[addr as usize];
+        let mut addrs: Vec<u16> = (0..=0xffff).filter(|&addr| total(addr) > 0).collect();
+        addrs.sort_by_key(|&addr| std::cmp::Reverse(total(addr)));
+        addrs
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,108 @@
+use crate::profiler::*;
+use crate::test_util::test_nes_with;
+
+//8000 JSR $8010
+//8003 JMP $8000
+//8010 JSR $8020
+//8013 LDA $0200
+//8016 RTS
+//8020 STA $0201
+//8023 RTS
+const PROGRAM: [(usize, &[u8]); 3] = [
+    (0x00, &[0x20, 0x10, 0x80, 0x4c, 0x00, 0x80]),
+    (0x10, &[0x20, 0x20, 0x80, 0xad, 0x00, 0x02, 0x60]),
+    (0x20, &[0x8d, 0x01, 0x02, 0x60]),
+];
+
+//two rounds of the main loop, 6 + 3 + 6 + 4 + 6 + 4 + 6 cycles each
+fn profile() -> Profiler {
+    let mut nes = test_nes_with(&PROGRAM);
+    nes.set_profiler(Some(Profiler::new()));
+    for _ in 0..14 {
+        nes.step_instruction().unwrap();
+    }
+    nes.take_profiler().unwrap()
+}
+
+fn name(addr: u16) -> String {
+    format!("${:04x}", addr)
+}
+
+#[test]
+fn test_cycles_by_instruction() {
+    let profiler = profile();
+    assert_eq!(profiler.instructions(), 14);
+    assert_eq!(profiler.total_cycles(), 70);
+    assert_eq!(profiler.cycles_at(0x8000), 12);
+    assert_eq!(profiler.cycles_at(0x8020), 8);
+    assert_eq!(profiler.cycles_in(0x8010..=0x8016), 32);
+}
+
+#[test]
+fn test_function_cycles() {
+    let profiler = profile();
+    let functions = profiler.functions();
+    let find = |address| {
+        functions
+            .iter()
+            .find(|f| f.address == address)
+            .cloned()
+            .unwrap()
+    };
+    let top = find(None);
+    assert_eq!((top.inclusive, top.exclusive), (70, 18));
+    let outer = find(Some(0x8010));
+    assert_eq!((outer.calls, outer.inclusive, outer.exclusive), (2, 52, 32));
+    let inner = find(Some(0x8020));
+    assert_eq!((inner.calls, inner.inclusive, inner.exclusive), (2, 20, 20));
+    assert_eq!(functions[0].address, None);
+}
+
+#[test]
+fn test_memory_counts() {
+    let profiler = profile();
+    let counts = profiler.access_counts();
+    assert_eq!(counts.reads[0x0200], 2);
+    assert_eq!(counts.writes[0x0201], 2);
+    assert_eq!(counts.writes[0x0200], 0);
+    //opcode fetches are reads too
+    assert_eq!(counts.reads[0x8020], 2);
+}
+
+#[test]
+fn test_reports() {
+    let profiler = profile();
+    let collapsed = profiler.collapsed_stacks(name);
+    let mut lines: Vec<&str> = collapsed.lines().collect();
+    lines.sort_unstable();
+    assert_eq!(
+        lines,
+        vec!["[top] 18", "[top];$8010 32", "[top];$8010;$8020 20"]
+    );
+
+    let text = profiler.text_report(name);
+    assert!(text.starts_with("70 cycles in 14 instructions"));
+    assert!(text.contains("52  74.29           32  45.71        2  $8010"));
+
+    let json = profiler.json_report(|addr| format!("\"{}\"", addr));
+    assert!(json.starts_with("{\"total_cycles\":70,\"instructions\":14,"));
+    assert!(json.contains("{\"address\":32800,\"name\":\"\\\"32800\\\"\",\"calls\":2,"));
+}
+
+#[test]
+fn test_recursion_counted_once() {
+    //8000 JSR $8000
+    let mut nes = test_nes_with(&[(0x00, &[0x20, 0x00, 0x80])]);
+    nes.set_profiler(Some(Profiler::new()));
+    for _ in 0..3 {
+        nes.step_instruction().unwrap();
+    }
+    let functions = nes.take_profiler().unwrap().functions();
+    let recursive = functions
+        .iter()
+        .find(|f| f.address == Some(0x8000))
+        .unwrap();
+    assert_eq!(recursive.calls, 3);
+    assert_eq!(recursive.exclusive, 12);
+    assert_eq!(recursive.inclusive, 12);
+}
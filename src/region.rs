@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod tests;
+
+/// TV system the game was made for, the CPU and PPU run at different rates on each.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    //runs on both, usually by checking the console at boot, emulated as NTSC
+    Multi,
+    //Famiclone with PAL frame rate and NTSC CPU timing
+    Dendy,
+}
+
+/// Clock rates and table values that differ between regions.
+#[derive(Debug, PartialEq)]
+pub struct Timing {
+    pub cpu_clock_rate: f64,
+    pub frame_rate: f64,
+    //PPU dots per CPU cycle as a fraction, 3.2 on PAL
+    pub ppu_dots: u16,
+    pub cpu_cycles: u16,
+    //including the pre-render line, which is the last one
+    pub scanlines: u16,
+    pub vblank_scanline: u16,
+    //NTSC skips a dot of the pre-render line on odd frames while rendering
+    pub odd_frame_skip: bool,
+    //APU frame counter steps in CPU cycles, the 4-step mode uses the first 4
+    pub frame_sequence: [u32; 5],
+    pub noise_periods: [u16; 16],
+    pub dmc_rates: [u16; 16],
+}
+
+pub const NTSC: Timing = Timing {
+    cpu_clock_rate: 1_789_773.0,
+    frame_rate: 60.0988,
+    ppu_dots: 3,
+    cpu_cycles: 1,
+    scanlines: 262,
+    vblank_scanline: 241,
+    odd_frame_skip: true,
+    frame_sequence: [7457, 14913, 22371, 29829, 37281],
+    noise_periods: [
+        4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+    ],
+    dmc_rates: [
+        428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+    ],
+};
+
+pub const PAL: Timing = Timing {
+    cpu_clock_rate: 1_662_607.0,
+    frame_rate: 50.0070,
+    ppu_dots: 16,
+    cpu_cycles: 5,
+    scanlines: 312,
+    vblank_scanline: 241,
+    odd_frame_skip: false,
+    frame_sequence: [8313, 16627, 24939, 33253, 41565],
+    noise_periods: [
+        4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+    ],
+    dmc_rates: [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+    ],
+};
+
+//the vblank NMI comes 50 lines after rendering, so NTSC games get their usual vblank length
+pub const DENDY: Timing = Timing {
+    cpu_clock_rate: 1_773_448.0,
+    frame_rate: 50.0070,
+    ppu_dots: 3,
+    cpu_cycles: 1,
+    scanlines: 312,
+    vblank_scanline: 291,
+    odd_frame_skip: false,
+    frame_sequence: NTSC.frame_sequence,
+    noise_periods: NTSC.noise_periods,
+    dmc_rates: NTSC.dmc_rates,
+};
+
+impl Region {
+    /// Decodes the 2 bit timing field of NES 2.0 headers and the ROM database.
+    pub fn from_timing_bits(timing: u8) -> Region {
+        match timing & 0b11 {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            2 => Region::Multi,
+            _ => Region::Dendy,
+        }
+    }
+
+    /// Reads `ntsc`, `pal` or `dendy`.
+    pub fn parse(name: &str) -> Result<Region, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!("Unknown region {}, use ntsc, pal or dendy", name)),
+        }
+    }
+
+    pub fn timing(self) -> &'static Timing {
+        match self {
+            Region::Ntsc | Region::Multi => &NTSC,
+            Region::Pal => &PAL,
+            Region::Dendy => &DENDY,
+        }
+    }
+
+    /// The reverse of `from_timing_bits`.
+    pub fn timing_bits(self) -> u8 {
+        match self {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+            Region::Multi => 2,
+            Region::Dendy => 3,
+        }
+    }
+}
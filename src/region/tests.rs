@@ -0,0 +1,87 @@
+use crate::apu::Apu;
+use crate::cartridge::Rom;
+use crate::nes::Nes;
+use crate::region::*;
+use crate::test_util::test_nes;
+
+//CPU cycles of the next 10 frames, rendering stays off so there is no odd frame skip
+fn cycles_per_10_frames(region: Region) -> u64 {
+    let mut nes = test_nes(&[]);
+    nes.set_region(region);
+    nes.run_frame().unwrap();
+    let start = nes.cpu.cycles;
+    for _ in 0..10 {
+        nes.run_frame().unwrap();
+    }
+    assert_eq!(nes.cpu.bus.ppu.scanline, region.timing().vblank_scanline);
+    nes.cpu.cycles - start
+}
+
+#[test]
+fn test_frame_length() {
+    //341 dots per line divided by the PPU dots per CPU cycle, within an instruction
+    let expected = |timing: &Timing| {
+        10.0 * timing.scanlines as f64 * 341.0 * timing.cpu_cycles as f64 / timing.ppu_dots as f64
+    };
+    for region in [Region::Ntsc, Region::Pal, Region::Dendy] {
+        let cycles = cycles_per_10_frames(region) as f64;
+        assert!(
+            (cycles - expected(region.timing())).abs() <= 2.0,
+            "{:?} ran {} cycles",
+            region,
+            cycles
+        );
+    }
+}
+
+#[test]
+fn test_region_from_header() {
+    let nes = test_nes(&[]);
+    assert_eq!(nes.region(), Region::Ntsc);
+    assert!((nes.frame_rate() - 60.0988).abs() < 1e-9);
+
+    let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x08];
+    raw.extend([0, 0, 0, 0, 0x01, 0, 0, 0]);
+    raw.extend(vec![0xEA; 16384]);
+    raw.extend(vec![0x00; 8192]);
+    let nes = Nes::new(Rom::new(&raw).unwrap());
+    assert_eq!(nes.region(), Region::Pal);
+    assert!((nes.frame_rate() - 50.007).abs() < 1e-9);
+}
+
+#[test]
+fn test_pal_frame_counter() {
+    let mut apu = Apu::new();
+    apu.set_timing(Region::Pal.timing());
+    apu.write_register(0x4017, 0x00);
+    for _ in 0..33252 {
+        apu.tick();
+    }
+    assert!(!apu.frame_irq);
+    apu.tick();
+    assert!(apu.frame_irq);
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(Region::parse("PAL"), Ok(Region::Pal));
+    assert_eq!(Region::parse("dendy"), Ok(Region::Dendy));
+    assert!(Region::parse("secam").is_err());
+    for bits in 0..4 {
+        assert_eq!(Region::from_timing_bits(bits).timing_bits(), bits);
+    }
+}
+
+#[test]
+fn test_save_state_keeps_region() {
+    let mut nes = test_nes(&[]);
+    nes.set_region(Region::Pal);
+    nes.run_frame().unwrap();
+    let state = nes.save_state();
+
+    let mut other = test_nes(&[]);
+    assert!(other.load_state(&state).is_err());
+    other.set_region(Region::Pal);
+    other.load_state(&state).unwrap();
+    assert_eq!(other.save_state(), state);
+}
@@ -0,0 +1,247 @@
+//! Screenshot regression tests: ROMs run for a number of frames with scripted input, then
+//! the picture is compared with the one recorded in a manifest.
+//!
+//! Each manifest line is `ROM FRAMES INPUT HASH`. INPUT is an input script in the format
+//! of `headless::InputScript` or `-` for none, HASH is the SHA-1 of the frame's palette
+//! indices, `-` until the first update. Paths are relative to the manifest and `#` starts
+//! a comment. Updating also keeps the pictures in `screenshots/` next to the manifest, a
+//! failing test writes what it got and a diff there as well.
+
+use crate::cartridge::{self, Rom};
+use crate::frame::Frame;
+use crate::headless::{self, HeadlessOptions, InputScript};
+use crate::nes::Nes;
+use crate::palette::Palette;
+use crate::screenshot;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub rom: PathBuf,
+    pub frames: u32,
+    pub input: Option<PathBuf>,
+    pub hash: Option<String>,
+}
+
+impl Entry {
+    /// File name for the pictures of this test, from the ROM, frame count and input.
+    pub fn name(&self) -> String {
+        let stem = |path: &Path| {
+            path.file_stem()
+                .map_or(String::new(), |stem| stem.to_string_lossy().into_owned())
+        };
+        match self.input.as_ref() {
+            Some(input) => format!("{}_{}_{}", stem(&self.rom), self.frames, stem(input)),
+            None => format!("{}_{}", stem(&self.rom), self.frames),
+        }
+    }
+
+    fn to_line(&self) -> String {
+        let path = |path: &Path| path.to_string_lossy().into_owned();
+        format!(
+            "{} {} {} {}",
+            path(&self.rom),
+            self.frames,
+            self.input.as_deref().map_or("-".to_string(), path),
+            self.hash.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    //the manifest had no hash yet when it is None
+    Failed {
+        expected: Option<String>,
+        actual: String,
+    },
+    Updated,
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+/// The tests and the text around them, comments survive updates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    lines: Vec<String>,
+    //with the line they are on
+    entries: Vec<(usize, Entry)>,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Manifest, String> {
+        let mut manifest = Manifest::default();
+        for (number, line) in text.lines().enumerate() {
+            manifest.lines.push(line.to_string());
+            let content = line.split('#').next().unwrap_or("").trim();
+            if content.is_empty() {
+                continue;
+            }
+            let error = |msg: &str| format!("line {}: {}", number + 1, msg);
+            let fields: Vec<&str> = content.split_whitespace().collect();
+            if fields.len() != 4 {
+                return Err(error("expected ROM FRAMES INPUT HASH"));
+            }
+            let frames = fields[1]
+                .parse()
+                .map_err(|_| error(&format!("invalid frame count {}", fields[1])))?;
+            let optional = |field| Some(field).filter(|&field| field != "-");
+            let hash = optional(fields[3]).map(str::to_ascii_lowercase);
+            if hash.as_ref().is_some_and(|hash| {
+                hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit())
+            }) {
+                return Err(error(&format!("invalid hash {}", fields[3])));
+            }
+            manifest.entries.push((
+                number,
+                Entry {
+                    rom: PathBuf::from(fields[0]),
+                    frames,
+                    input: optional(fields[2]).map(PathBuf::from),
+                    hash,
+                },
+            ));
+        }
+        Ok(manifest)
+    }
+
+    pub fn open(path: &Path) -> Result<Manifest, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Manifest::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = self.lines.clone();
+        for (line, entry) in self.entries.iter() {
+            lines[*line] = entry.to_line();
+        }
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().map(|(_, entry)| entry)
+    }
+
+    /// Runs every test with paths relative to `dir`. With `update` the hashes and pictures
+    /// are replaced by what the emulator produces now.
+    pub fn run(&mut self, dir: &Path, update: bool) -> Vec<CaseResult> {
+        let screenshots = dir.join(SCREENSHOT_DIR);
+        let mut results = Vec::new();
+        for (_, entry) in self.entries.iter_mut() {
+            let name = entry.name();
+            let outcome = match run_entry(entry, dir) {
+                Ok(frame) => check(entry, &frame, &screenshots.join(&name), update)
+                    .unwrap_or_else(|e| Outcome::Error(e.to_string())),
+                Err(e) => Outcome::Error(e),
+            };
+            results.push(CaseResult { name, outcome });
+        }
+        results
+    }
+}
+
+fn run_entry(entry: &Entry, dir: &Path) -> Result<Frame, String> {
+    let rom = Rom::from_file(&dir.join(&entry.rom))?;
+    let mut options = HeadlessOptions::new();
+    options.frames = entry.frames;
+    if let Some(input) = entry.input.as_ref() {
+        options.input_script = Some(InputScript::from_file(&dir.join(input))?);
+    }
+    let mut nes = Nes::new(rom);
+    headless::run(&mut nes, &options).map_err(|e| e.to_string())?;
+    let mut frame = Frame::new();
+    frame.data.copy_from_slice(&nes.frame().data);
+    Ok(frame)
+}
+
+//`base` is the screenshot path without the extension
+fn check(entry: &mut Entry, frame: &Frame, base: &Path, update: bool) -> io::Result<Outcome> {
+    let actual = frame_hash(frame);
+    let with_suffix = |suffix: &str| {
+        let mut name = base.as_os_str().to_os_string();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    if let Some(dir) = base.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if update {
+        screenshot::save_png(frame, &Palette::new(), &with_suffix(".png"))?;
+        let changed = entry.hash.as_ref() != Some(&actual);
+        entry.hash = Some(actual);
+        return Ok(if changed {
+            Outcome::Updated
+        } else {
+            Outcome::Passed
+        });
+    }
+    if entry.hash.as_ref() == Some(&actual) {
+        return Ok(Outcome::Passed);
+    }
+
+    let rgb = screenshot::frame_rgb(frame, &Palette::new());
+    screenshot::save_rgb_png(
+        &rgb,
+        Frame::WIDTH,
+        Frame::HEIGHT,
+        &with_suffix(".actual.png"),
+    )?;
+    //the picture from the last update, when it was kept, pictures of another size or
+    //format are left without a diff
+    match screenshot::load_rgb_png(&with_suffix(".png")) {
+        Ok(expected) if expected.len() == rgb.len() => screenshot::save_rgb_png(
+            &diff_image(&expected, &rgb),
+            Frame::WIDTH,
+            Frame::HEIGHT,
+            &with_suffix(".diff.png"),
+        )?,
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("{}: no diff, {}", base.display(), e),
+    }
+    Ok(Outcome::Failed {
+        expected: entry.hash.clone(),
+        actual,
+    })
+}
+
+/// SHA-1 of the palette indices, the same whichever palette turns them into colors.
+pub fn frame_hash(frame: &Frame) -> String {
+    let bytes: Vec<u8> = frame.data.iter().flat_map(|p| p.to_le_bytes()).collect();
+    cartridge::sha1(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Pixels that differ in red over a dimmed grey copy of `actual`, both RGB.
+pub fn diff_image(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    let mut diff = Vec::with_capacity(actual.len());
+    for (expected, actual) in expected.chunks_exact(3).zip(actual.chunks_exact(3)) {
+        if expected == actual {
+            let grey = ((actual[0] as u16 + actual[1] as u16 + actual[2] as u16) / 9) as u8;
+            diff.extend([grey; 3]);
+        } else {
+            diff.extend([0xff, 0x00, 0x00]);
+        }
+    }
+    diff
+}
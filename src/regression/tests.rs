@@ -0,0 +1,153 @@
+use crate::regression::*;
+use crate::test_util::{temp_path, test_rom_with};
+
+//NROM image that shows the backdrop color picked by the controller: it waits for vblank,
+//reads port 1 and writes $0f (black) or, with A held, $21 (blue) to the palette
+fn write_rom(path: &Path) {
+    let program = [
+        0xad, 0x02, 0x20, //wait: LDA $2002
+        0x10, 0xfb, //BPL wait
+        0xa9, 0x01, 0x8d, 0x16, 0x40, //LDA #1, STA $4016
+        0xa9, 0x00, 0x8d, 0x16, 0x40, //LDA #0, STA $4016
+        0xad, 0x16, 0x40, //LDA $4016
+        0x29, 0x01, //AND #1
+        0xaa, //TAX
+        0xa9, 0x3f, 0x8d, 0x06, 0x20, //LDA #$3f, STA $2006
+        0xa9, 0x00, 0x8d, 0x06, 0x20, //LDA #0, STA $2006
+        0xbd, 0x30, 0x80, //LDA colors,X
+        0x8d, 0x07, 0x20, //STA $2007
+        0x4c, 0x00, 0x80, //JMP wait
+    ];
+    let raw = test_rom_with(&[(0, &program), (0x30, &[0x0f, 0x21])]);
+    fs::write(path, raw).unwrap();
+}
+
+#[test]
+fn test_parse_manifest() {
+    let text = "# smoke tests\ngame.nes 60 - -\ngame.nes 120 start.txt 0123456789ABCDEF0123456789abcdef01234567 # title\n";
+    let manifest = Manifest::parse(text).unwrap();
+    let entries: Vec<&Entry> = manifest.entries().collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].input, None);
+    assert_eq!(entries[0].hash, None);
+    assert_eq!(entries[0].name(), "game_60");
+    assert_eq!(entries[1].input, Some(PathBuf::from("start.txt")));
+    assert_eq!(
+        entries[1].hash.as_deref(),
+        Some("0123456789abcdef0123456789abcdef01234567")
+    );
+    assert_eq!(entries[1].name(), "game_120_start");
+    assert_eq!(
+        manifest.to_text(),
+        "# smoke tests\ngame.nes 60 - -\ngame.nes 120 start.txt 0123456789abcdef0123456789abcdef01234567\n"
+    );
+
+    assert_eq!(
+        Manifest::parse("game.nes 60 -").unwrap_err(),
+        "line 1: expected ROM FRAMES INPUT HASH"
+    );
+    assert_eq!(
+        Manifest::parse("\ngame.nes 60 - abc").unwrap_err(),
+        "line 2: invalid hash abc"
+    );
+}
+
+#[test]
+fn test_update_then_pass_then_fail() {
+    let dir = temp_path("regression");
+    fs::create_dir_all(&dir).unwrap();
+    write_rom(&dir.join("color.nes"));
+    fs::write(dir.join("press_a.txt"), "0 A\n").unwrap();
+    let text = "color.nes 3 - -\ncolor.nes 3 press_a.txt -\n";
+    let mut manifest = Manifest::parse(text).unwrap();
+
+    let outcomes = |results: Vec<CaseResult>| -> Vec<Outcome> {
+        results.into_iter().map(|result| result.outcome).collect()
+    };
+    let results = manifest.clone().run(&dir, false);
+    assert!(matches!(
+        results[0].outcome,
+        Outcome::Failed { expected: None, .. }
+    ));
+
+    assert_eq!(
+        outcomes(manifest.run(&dir, true)),
+        vec![Outcome::Updated, Outcome::Updated]
+    );
+    let hashes: Vec<String> = manifest
+        .entries()
+        .map(|e| e.hash.clone().unwrap())
+        .collect();
+    //the input changed the picture
+    assert_ne!(hashes[0], hashes[1]);
+    assert!(dir.join(SCREENSHOT_DIR).join("color_3.png").exists());
+    assert_eq!(
+        outcomes(manifest.run(&dir, false)),
+        vec![Outcome::Passed, Outcome::Passed]
+    );
+
+    //the black reference now gets compared with the blue picture
+    let swapped = format!("color.nes 3 press_a.txt {}\n", hashes[0]);
+    let mut manifest = Manifest::parse(&swapped).unwrap();
+    fs::copy(
+        dir.join(SCREENSHOT_DIR).join("color_3.png"),
+        dir.join(SCREENSHOT_DIR).join("color_3_press_a.png"),
+    )
+    .unwrap();
+    let results = manifest.run(&dir, false);
+    assert_eq!(
+        results[0].outcome,
+        Outcome::Failed {
+            expected: Some(hashes[0].clone()),
+            actual: hashes[1].clone()
+        }
+    );
+    let diff = screenshot::load_rgb_png(&dir.join(SCREENSHOT_DIR).join("color_3_press_a.diff.png"))
+        .unwrap();
+    assert_eq!(&diff[..3], &[0xff, 0x00, 0x00]);
+    assert!(dir
+        .join(SCREENSHOT_DIR)
+        .join("color_3_press_a.actual.png")
+        .exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_screenshot_of_another_size_fails_without_a_diff() {
+    let dir = temp_path("regression_size");
+    fs::create_dir_all(dir.join(SCREENSHOT_DIR)).unwrap();
+    write_rom(&dir.join("color.nes"));
+    screenshot::save_rgb_png(
+        &[0; 16 * 16 * 3],
+        16,
+        16,
+        &dir.join(SCREENSHOT_DIR).join("color_3.png"),
+    )
+    .unwrap();
+    let text = format!("color.nes 3 - {}\n", "0".repeat(40));
+    let results = Manifest::parse(&text).unwrap().run(&dir, false);
+    assert!(matches!(
+        results[0].outcome,
+        Outcome::Failed {
+            expected: Some(_),
+            ..
+        }
+    ));
+    assert!(dir.join(SCREENSHOT_DIR).join("color_3.actual.png").exists());
+    assert!(!dir.join(SCREENSHOT_DIR).join("color_3.diff.png").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_missing_rom_is_an_error() {
+    let mut manifest = Manifest::parse("missing.nes 1 - -").unwrap();
+    let results = manifest.run(&temp_path("no_such_dir"), false);
+    assert!(matches!(results[0].outcome, Outcome::Error(_)));
+}
+
+#[test]
+fn test_diff_image() {
+    let expected = [10, 20, 30, 0, 0, 0];
+    let actual = [10, 20, 30, 1, 0, 0];
+    assert_eq!(diff_image(&expected, &actual), vec![6, 6, 6, 0xff, 0, 0]);
+}
@@ -0,0 +1,232 @@
+use crate::joypad::JoypadButton;
+use crate::nes::Nes;
+use std::collections::VecDeque;
+use std::mem;
+
+#[cfg(test)]
+mod tests;
+
+pub const DEFAULT_INTERVAL: u32 = 10;
+pub const DEFAULT_MEMORY_BUDGET: usize = 32 * 1024 * 1024;
+//snapshots between two full ones, the others are stored as deltas against it
+const KEYFRAME_INTERVAL: usize = 30;
+
+enum Snapshot {
+    Keyframe(Vec<u8>),
+    //XOR against the last keyframe before it, zero runs compressed
+    Delta(Vec<u8>),
+}
+
+impl Snapshot {
+    fn size(&self) -> usize {
+        match self {
+            Snapshot::Keyframe(data) | Snapshot::Delta(data) => data.len(),
+        }
+    }
+}
+
+/// Ring buffer of save states taken every `interval` frames within a memory budget.
+///
+/// Rewinding loads the nearest snapshot at or before the target frame and replays the
+/// recorded controller input from there, so any frame in the buffer can be reached.
+pub struct Rewind {
+    interval: u32,
+    memory_budget: usize,
+    //frames recorded since the buffer was created
+    frame: u64,
+    snapshots: VecDeque<(u64, Snapshot)>,
+    //joypad buttons of every frame since the oldest snapshot
+    inputs: VecDeque<[JoypadButton; 2]>,
+    snapshot_usage: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Rewind::new(DEFAULT_INTERVAL, DEFAULT_MEMORY_BUDGET)
+    }
+}
+
+impl Rewind {
+    pub fn new(interval: u32, memory_budget: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            memory_budget,
+            frame: 0,
+            snapshots: VecDeque::new(),
+            inputs: VecDeque::new(),
+            snapshot_usage: 0,
+        }
+    }
+
+    /// Bytes used by the stored snapshots and the input recorded since the oldest one.
+    pub fn memory_usage(&self) -> usize {
+        self.snapshot_usage + self.inputs.len() * mem::size_of::<[JoypadButton; 2]>()
+    }
+
+    /// How many frames back `rewind` can go.
+    pub fn available_frames(&self) -> u64 {
+        self.snapshots
+            .front()
+            .map_or(0, |&(frame, _)| self.frame - frame)
+    }
+
+    /// Call after every emulated frame, with the input of that frame still held.
+    pub fn record(&mut self, nes: &Nes) {
+        let buttons = |port| {
+            nes.joypad(port)
+                .map_or(JoypadButton::empty(), |j| j.buttons())
+        };
+        self.inputs.push_back([buttons(0), buttons(1)]);
+        self.frame += 1;
+        if self.snapshots.is_empty() || self.frame.is_multiple_of(self.interval as u64) {
+            self.push_snapshot(nes.save_state());
+        }
+    }
+
+    /// Goes back `frames` frames, or as far as the buffer reaches, and returns how far it went.
+    pub fn rewind(&mut self, nes: &mut Nes, frames: u64) -> Result<u64, String> {
+        let frames = frames.min(self.available_frames());
+        let target = self.frame - frames;
+        let index = match self
+            .snapshots
+            .iter()
+            .rposition(|&(frame, _)| frame <= target)
+        {
+            Some(index) => index,
+            None => return Ok(0),
+        };
+        let start = self.snapshots[index].0;
+        nes.load_state(&self.decode(index))?;
+
+        let first_input = self.inputs.len() - (self.frame - start) as usize;
+        for i in 0..(target - start) as usize {
+            let buttons = self.inputs[first_input + i];
+            for (port, &buttons) in buttons.iter().enumerate() {
+                if let Some(joypad) = nes.joypad_mut(port) {
+                    joypad.set_buttons(buttons);
+                }
+            }
+            nes.run_frame().map_err(|e| e.to_string())?;
+        }
+        nes.take_audio_samples();
+
+        //the future is recorded again from here
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|&(frame, _)| frame > target)
+        {
+            let (_, snapshot) = self.snapshots.pop_back().unwrap();
+            self.snapshot_usage -= snapshot.size();
+        }
+        self.inputs.truncate(self.inputs.len() - frames as usize);
+        self.frame = target;
+        Ok(frames)
+    }
+
+    fn push_snapshot(&mut self, state: Vec<u8>) {
+        let keyframe = self
+            .snapshots
+            .iter()
+            .rposition(|(_, snapshot)| matches!(snapshot, Snapshot::Keyframe(_)));
+        let snapshot = match keyframe {
+            Some(index) if self.snapshots.len() - index < KEYFRAME_INTERVAL => {
+                match &self.snapshots[index].1 {
+                    Snapshot::Keyframe(base) if base.len() == state.len() => {
+                        Snapshot::Delta(compress_delta(base, &state))
+                    }
+                    _ => Snapshot::Keyframe(state),
+                }
+            }
+            _ => Snapshot::Keyframe(state),
+        };
+        self.snapshot_usage += snapshot.size();
+        self.snapshots.push_back((self.frame, snapshot));
+        self.evict();
+    }
+
+    //drops the oldest keyframe with its deltas until the budget is met, keeping the newest one
+    fn evict(&mut self) {
+        while self.memory_usage() > self.memory_budget {
+            let next_keyframe = self
+                .snapshots
+                .iter()
+                .skip(1)
+                .position(|(_, snapshot)| matches!(snapshot, Snapshot::Keyframe(_)));
+            let count = match next_keyframe {
+                Some(position) => position + 1,
+                None => return,
+            };
+            for (_, snapshot) in self.snapshots.drain(..count) {
+                self.snapshot_usage -= snapshot.size();
+            }
+            let oldest = self.snapshots[0].0;
+            let dropped = self.inputs.len() - (self.frame - oldest) as usize;
+            self.inputs.drain(..dropped);
+        }
+    }
+
+    fn decode(&self, index: usize) -> Vec<u8> {
+        match &self.snapshots[index].1 {
+            Snapshot::Keyframe(state) => state.clone(),
+            Snapshot::Delta(delta) => {
+                let base = self
+                    .snapshots
+                    .range(..index)
+                    .rev()
+                    .find_map(|(_, s)| match s {
+                        Snapshot::Keyframe(base) => Some(base),
+                        Snapshot::Delta(_) => None,
+                    });
+                decompress_delta(base.expect("delta without keyframe"), delta)
+            }
+        }
+    }
+}
+
+// The delta is `state XOR base`, which is mostly zeros between nearby frames. It is
+// stored as a sequence of (zero run: u16, literal count: u16, literal bytes).
+fn compress_delta(base: &[u8], state: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = base.iter().zip(state).map(|(a, b)| a ^ b).collect();
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < xor.len() {
+        let zeros = xor[pos..]
+            .iter()
+            .take(u16::MAX as usize)
+            .take_while(|&&b| b == 0)
+            .count();
+        pos += zeros;
+        let literals = xor[pos..]
+            .iter()
+            .take(u16::MAX as usize)
+            .take_while(|&&b| b != 0)
+            .count();
+        out.extend_from_slice(&(zeros as u16).to_le_bytes());
+        out.extend_from_slice(&(literals as u16).to_le_bytes());
+        out.extend_from_slice(&xor[pos..pos + literals]);
+        pos += literals;
+    }
+    out
+}
+
+fn decompress_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut state = base.to_vec();
+    let mut pos = 0;
+    let mut i = 0;
+    while i + 4 <= delta.len() {
+        let zeros = u16::from_le_bytes([delta[i], delta[i + 1]]) as usize;
+        let literals = u16::from_le_bytes([delta[i + 2], delta[i + 3]]) as usize;
+        i += 4;
+        pos += zeros;
+        for (byte, change) in state[pos..pos + literals]
+            .iter_mut()
+            .zip(&delta[i..i + literals])
+        {
+            *byte ^= change;
+        }
+        pos += literals;
+        i += literals;
+    }
+    state
+}
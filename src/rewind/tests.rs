@@ -0,0 +1,81 @@
+use crate::joypad::JoypadButton;
+use crate::nes::Nes;
+use crate::rewind::*;
+use crate::test_util::test_nes;
+
+//loop: LDA #$01; STA $4016; LDA #$00; STA $4016; LDA $4016; ADC $10; STA $10;
+//INC $11; LDA $11; STA $4002; JMP loop
+const PROGRAM: [u8; 28] = [
+    0xa9, 0x01, 0x8d, 0x16, 0x40, 0xa9, 0x00, 0x8d, 0x16, 0x40, 0xad, 0x16, 0x40, 0x65, 0x10, 0x85,
+    0x10, 0xe6, 0x11, 0xa5, 0x11, 0x8d, 0x02, 0x40, 0x4c, 0x00, 0x80, 0xea,
+];
+
+//presses A on every third frame so the replay has to use the recorded input
+fn play(nes: &mut Nes, rewind: &mut Rewind, frames: u32) {
+    for _ in 0..frames {
+        let frame = nes.cpu.cycles / 29780;
+        let buttons = if frame.is_multiple_of(3) {
+            JoypadButton::BUTTON_A
+        } else {
+            JoypadButton::empty()
+        };
+        nes.joypad_mut(0).unwrap().set_buttons(buttons);
+        nes.run_frame().unwrap();
+        rewind.record(nes);
+    }
+}
+
+#[test]
+fn test_rewind_replays_input() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut rewind = Rewind::new(10, DEFAULT_MEMORY_BUDGET);
+    play(&mut nes, &mut rewind, 47);
+    let expected = nes.save_state();
+    play(&mut nes, &mut rewind, 36);
+
+    assert_eq!(rewind.rewind(&mut nes, 36).unwrap(), 36);
+    assert_eq!(nes.save_state(), expected);
+    assert_eq!(rewind.available_frames(), 46);
+}
+
+#[test]
+fn test_rewind_stops_at_oldest_snapshot() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut rewind = Rewind::new(5, DEFAULT_MEMORY_BUDGET);
+    play(&mut nes, &mut rewind, 12);
+    assert_eq!(rewind.rewind(&mut nes, 100).unwrap(), 11);
+    assert_eq!(rewind.available_frames(), 0);
+    assert_eq!(rewind.rewind(&mut nes, 1).unwrap(), 0);
+}
+
+#[test]
+fn test_memory_budget() {
+    let mut nes = test_nes(&PROGRAM);
+    let state_size = nes.save_state().len();
+    let budget = state_size * 3;
+    let mut rewind = Rewind::new(1, budget);
+    play(&mut nes, &mut rewind, 200);
+
+    assert!(rewind.memory_usage() <= budget);
+    assert!(rewind.available_frames() > 30);
+    assert!(rewind.available_frames() < 200);
+
+    let frames = rewind.available_frames() - 3;
+    let mut replay = test_nes(&PROGRAM);
+    let mut unused = Rewind::new(1000, DEFAULT_MEMORY_BUDGET);
+    play(&mut replay, &mut unused, 200 - frames as u32);
+    rewind.rewind(&mut nes, frames).unwrap();
+    assert_eq!(nes.save_state(), replay.save_state());
+}
+
+#[test]
+fn test_memory_usage_counts_inputs() {
+    let mut nes = test_nes(&PROGRAM);
+    let state_size = nes.save_state().len();
+    let mut rewind = Rewind::new(1000, DEFAULT_MEMORY_BUDGET);
+    play(&mut nes, &mut rewind, 10);
+    assert_eq!(
+        rewind.memory_usage(),
+        state_size + 10 * std::mem::size_of::<[JoypadButton; 2]>()
+    );
+}
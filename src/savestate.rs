@@ -0,0 +1,253 @@
+//! Save states, a snapshot of everything the console needs to continue where it left off.
+//!
+//! Format, all integers little endian:
+//!
+//! ```text
+//! magic          4 bytes  "NESS"
+//! major version  u16      loading rejects any other major version
+//! minor version  u16      minor versions only add chunks or append fields to one
+//! chunks         until the end of the data, each one is
+//!     tag        4 bytes  "CPU ", "BUS ", "PPU ", "APU ", "CART" or "INPT"
+//!     length     u32      payload size in bytes
+//!     payload    length bytes
+//! ```
+//!
+//! Unknown chunks and bytes after the fields a reader knows are skipped, so states from
+//! newer minor versions load as well. Fields a minor version adds are read only when
+//! `StateReader::minor_version` says they are present, older states keep the reset value.
+//! Booleans are one byte, byte arrays are a u32 length followed by the bytes.
+
+use crate::nes::Nes;
+use std::convert::TryInto;
+
+#[cfg(test)]
+mod tests;
+
+pub const MAGIC: [u8; 4] = *b"NESS";
+pub const MAJOR_VERSION: u16 = 1;
+pub const MINOR_VERSION: u16 = 1;
+
+/// A piece of the machine that can be written to and restored from a save state.
+pub trait Snapshot {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Writes a chunk header followed by whatever `f` writes, patching the length afterwards.
+    pub fn chunk<F: FnOnce(&mut StateWriter)>(&mut self, tag: &[u8; 4], f: F) {
+        self.data.extend_from_slice(tag);
+        let length_pos = self.data.len();
+        self.write_u32(0);
+        f(self);
+        let length = (self.data.len() - length_pos - 4) as u32;
+        self.data[length_pos..length_pos + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(value.to_bits());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+//tag and payload
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    minor_version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8], minor_version: u16) -> Self {
+        StateReader {
+            data,
+            pos: 0,
+            minor_version,
+        }
+    }
+
+    /// Minor version the state was written with.
+    pub fn minor_version(&self) -> u16 {
+        self.minor_version
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err("Save state is truncated".to_string());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_bits(self.read_u64()?))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    /// Reads a byte array that has to be exactly as long as `out`.
+    pub fn read_into(&mut self, out: &mut [u8]) -> Result<(), String> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != out.len() {
+            return Err(format!(
+                "Save state holds {} bytes where {} are expected",
+                bytes.len(),
+                out.len()
+            ));
+        }
+        out.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<Chunk<'a>>, String> {
+        if self.pos == self.data.len() {
+            return Ok(None);
+        }
+        let tag = self.take(4)?.try_into().unwrap();
+        let len = self.read_u32()? as usize;
+        Ok(Some((tag, self.take(len)?)))
+    }
+}
+
+/// Serializes the whole console.
+pub fn save(nes: &Nes) -> Vec<u8> {
+    let mut w = StateWriter::new();
+    w.data.extend_from_slice(&MAGIC);
+    w.write_u16(MAJOR_VERSION);
+    w.write_u16(MINOR_VERSION);
+
+    let bus = &nes.cpu.bus;
+    w.chunk(b"CPU ", |w| nes.cpu.save_state(w));
+    w.chunk(b"BUS ", |w| bus.save_state(w));
+    w.chunk(b"PPU ", |w| bus.ppu.save_state(w));
+    w.chunk(b"APU ", |w| bus.apu.save_state(w));
+    if let Some(rom) = bus.rom() {
+        w.chunk(b"CART", |w| rom.save_state(w));
+    }
+    w.chunk(b"INPT", |w| {
+        for port in bus.ports.iter() {
+            port.save_state(w);
+        }
+    });
+    w.into_inner()
+}
+
+/// Restores a state written by `save`. On error the console is left as it was.
+pub fn load(nes: &mut Nes, data: &[u8]) -> Result<(), String> {
+    let mut header = StateReader::new(data, 0);
+    if header.take(4).ok() != Some(&MAGIC[..]) {
+        return Err("Not a save state".to_string());
+    }
+    let major = header.read_u16()?;
+    let minor = header.read_u16()?;
+    if major != MAJOR_VERSION {
+        return Err(format!(
+            "Save state version {}.{} is not supported, expected {}.x",
+            major, minor, MAJOR_VERSION
+        ));
+    }
+
+    let backup = save(nes);
+    let result = load_chunks(nes, &mut header, minor);
+    if result.is_err() {
+        let mut r = StateReader::new(&backup, MINOR_VERSION);
+        r.pos = 8;
+        load_chunks(nes, &mut r, MINOR_VERSION).expect("restoring the previous state failed");
+    }
+    result
+}
+
+fn load_chunks(nes: &mut Nes, chunks: &mut StateReader, minor: u16) -> Result<(), String> {
+    while let Some((tag, payload)) = chunks.next_chunk()? {
+        let mut r = StateReader::new(payload, minor);
+        let bus = &mut nes.cpu.bus;
+        match &tag {
+            b"CPU " => nes.cpu.load_state(&mut r)?,
+            b"BUS " => bus.load_state(&mut r)?,
+            b"PPU " => bus.ppu.load_state(&mut r)?,
+            b"APU " => bus.apu.load_state(&mut r)?,
+            b"CART" => match bus.rom_mut() {
+                Some(rom) => rom.load_state(&mut r)?,
+                None => return Err("Save state is for a cartridge".to_string()),
+            },
+            b"INPT" => {
+                for port in bus.ports.iter_mut() {
+                    port.load_state(&mut r)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use crate::cartridge::Rom;
+use crate::nes::Nes;
+use crate::savestate::*;
+use crate::test_util::test_rom_with;
+
+//LDA #$0f; STA $4015; loop: INC $10; LDA $10; STA $4002; STA $4003; JMP loop
+const PROGRAM: [u8; 17] = [
+    0xa9, 0x0f, 0x8d, 0x15, 0x40, 0xe6, 0x10, 0xa5, 0x10, 0x8d, 0x02, 0x40, 0x8d, 0x03, 0x40, 0x4c,
+    0x05,
+];
+
+fn test_rom(flags_6: u8) -> Rom {
+    let mut raw = test_rom_with(&[(0, &PROGRAM), (PROGRAM.len(), &[0x80])]);
+    raw[6] = flags_6;
+    Rom::new(&raw).unwrap()
+}
+
+fn run_frames(nes: &mut Nes, frames: usize) -> (Vec<u8>, Vec<f32>) {
+    for _ in 0..frames {
+        nes.run_frame().unwrap();
+    }
+    (nes.cpu.bus.ram().to_vec(), nes.take_audio_samples())
+}
+
+#[test]
+fn test_round_trip_is_deterministic() {
+    let mut nes = Nes::new(test_rom(0));
+    run_frames(&mut nes, 3);
+    let state = nes.save_state();
+    nes.take_audio_samples();
+    let expected = run_frames(&mut nes, 2);
+    let cycles = nes.cpu.cycles;
+
+    let mut other = Nes::new(test_rom(0));
+    other.load_state(&state).unwrap();
+    assert_eq!(run_frames(&mut other, 2), expected);
+    assert_eq!(other.cpu.cycles, cycles);
+    assert_eq!(other.save_state(), nes.save_state());
+}
+
+#[test]
+fn test_header_is_checked() {
+    let mut nes = Nes::new(test_rom(0));
+    let mut state = nes.save_state();
+    assert_eq!(&state[..4], b"NESS");
+    assert!(nes.load_state(b"junk").is_err());
+
+    state[4] = (MAJOR_VERSION + 1) as u8;
+    assert!(nes.load_state(&state).is_err());
+}
+
+#[test]
+fn test_newer_minor_version_loads() {
+    let mut nes = Nes::new(test_rom(0));
+    run_frames(&mut nes, 1);
+    let mut state = nes.save_state();
+    state[6] = (MINOR_VERSION + 1) as u8;
+    //a chunk this version does not know about
+    state.extend_from_slice(b"NEW ");
+    state.extend_from_slice(&2u32.to_le_bytes());
+    state.extend_from_slice(&[1, 2]);
+
+    let mut other = Nes::new(test_rom(0));
+    other.load_state(&state).unwrap();
+    assert_eq!(other.cpu.program_counter, nes.cpu.program_counter);
+}
+
+#[test]
+fn test_failed_load_keeps_state() {
+    let mut nes = Nes::new(test_rom(0));
+    run_frames(&mut nes, 2);
+    let state = nes.save_state();
+
+    let mut other = Nes::new(test_rom(0));
+    let before = other.save_state();
+    assert!(other.load_state(&state[..state.len() - 3]).is_err());
+    assert_eq!(other.save_state(), before);
+}
+
+#[test]
+fn test_cartridge_ram_is_saved() {
+    let mut nes = Nes::new(test_rom(0b0000_0010));
+    nes.cpu.bus.mem_write(0x6000, 0x55);
+    let state = nes.save_state();
+
+    let mut other = Nes::new(test_rom(0b0000_0010));
+    other.load_state(&state).unwrap();
+    assert_eq!(other.cpu.bus.mem_read(0x6000), 0x55);
+}
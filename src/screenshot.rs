@@ -0,0 +1,58 @@
+use crate::frame::Frame;
+use crate::palette::Palette;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+/// Converts the frame to RGB and writes it as a PNG.
+pub fn save_png(frame: &Frame, palette: &Palette, path: &Path) -> io::Result<()> {
+    save_rgb_png(
+        &frame_rgb(frame, palette),
+        Frame::WIDTH,
+        Frame::HEIGHT,
+        path,
+    )
+}
+
+/// The frame as 8 bit RGB triples.
+pub fn frame_rgb(frame: &Frame, palette: &Palette) -> Vec<u8> {
+    let mut rgb = vec![0; Frame::WIDTH * Frame::HEIGHT * 3];
+    palette.frame_to_rgb(frame, &mut rgb);
+    rgb
+}
+
+/// Writes a picture of RGB triples, a frame or the output of a filter.
+pub fn save_rgb_png(rgb: &[u8], width: usize, height: usize, path: &Path) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Reads a frame sized RGB PNG like the ones `save_png` writes.
+pub fn load_rgb_png(path: &Path) -> io::Result<Vec<u8>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let mut reader = decoder.read_info().map_err(|e| invalid(e.to_string()))?;
+    let mut rgb = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut rgb)
+        .map_err(|e| invalid(e.to_string()))?;
+    if (info.width as usize, info.height as usize) != (Frame::WIDTH, Frame::HEIGHT)
+        || info.color_type != png::ColorType::Rgb
+        || info.bit_depth != png::BitDepth::Eight
+    {
+        return Err(invalid(format!(
+            "{} is not a {}x{} RGB picture",
+            path.display(),
+            Frame::WIDTH,
+            Frame::HEIGHT
+        )));
+    }
+    rgb.truncate(info.buffer_size());
+    Ok(rgb)
+}
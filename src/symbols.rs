@@ -0,0 +1,363 @@
+use crate::bus::Bus;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+const INES_HEADER_SIZE: u32 = 16;
+//FCEUX numbers its .nl files by 16 KiB bank
+const NL_BANK_SIZE: u32 = 0x4000;
+
+/// What a label or source line belongs to. Code and data in the cartridge are keyed by
+/// their PRG ROM offset, so the same CPU address in two banks can have different names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Location {
+    Cpu(u16),
+    Prg(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Label {
+    name: String,
+    //bytes covered, arrays and tables are shown as `name+offset`
+    size: u32,
+}
+
+/// Labels and source lines from cc65 .dbg, Mesen .mlb and FCEUX .nl files.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    labels: BTreeMap<Location, Label>,
+    //with the CPU address the label was defined at when the file has it
+    names: HashMap<String, (Location, Option<u16>)>,
+    lines: BTreeMap<Location, SourceLine>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.lines.is_empty()
+    }
+
+    /// Number of labels loaded.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Adds the symbols of a .dbg, .mlb or .nl file, picked by the extension.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let result = match path.extension().and_then(|e| e.to_str()) {
+            Some("dbg") => self.parse_dbg(&text),
+            Some("mlb") => self.parse_mlb(&text),
+            //game.nes.ram.nl has RAM labels, game.nes.N.nl those of PRG bank N (hex)
+            Some("nl") => {
+                let stem = name.trim_end_matches(".nl");
+                let bank = stem
+                    .rsplit_once('.')
+                    .filter(|(rom, _)| rom.ends_with(".nes"))
+                    .and_then(|(_, bank)| u32::from_str_radix(bank, 16).ok());
+                self.parse_nl(&text, bank)
+            }
+            _ => Err("Unknown symbol file type, use .dbg, .mlb or .nl".to_string()),
+        };
+        result.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Adds a label, `addr` is where the program sees it if the file says so.
+    pub fn add_label(&mut self, location: Location, addr: Option<u16>, name: &str, size: u32) {
+        self.names.insert(name.to_string(), (location, addr));
+        //the first name of a location is the one displayed
+        self.labels.entry(location).or_insert(Label {
+            name: name.to_string(),
+            size: size.max(1),
+        });
+    }
+
+    pub fn add_line(&mut self, location: Location, line: SourceLine) {
+        self.lines.entry(location).or_insert(line);
+    }
+
+    /// Where `addr` is currently mapped from.
+    pub fn location(bus: &Bus, addr: u16) -> Location {
+        match bus.prg_rom_offset(addr) {
+            Some(offset) => Location::Prg(offset as u32),
+            None => Location::Cpu(addr),
+        }
+    }
+
+    /// Name of the byte at `addr`, `name+N` inside an array.
+    pub fn label(&self, bus: &Bus, addr: u16) -> Option<String> {
+        let location = Symbols::location(bus, addr);
+        self.find_label(location)
+            .or_else(|| self.find_label(Location::Cpu(addr)))
+    }
+
+    /// Name of a label starting exactly at `addr`.
+    pub fn label_at(&self, bus: &Bus, addr: u16) -> Option<&str> {
+        let location = Symbols::location(bus, addr);
+        self.labels
+            .get(&location)
+            .or_else(|| self.labels.get(&Location::Cpu(addr)))
+            .map(|label| label.name.as_str())
+    }
+
+    pub fn source_line(&self, bus: &Bus, addr: u16) -> Option<&SourceLine> {
+        self.lines.get(&Symbols::location(bus, addr))
+    }
+
+    /// CPU address of the label `name`.
+    pub fn address(&self, bus: &Bus, name: &str) -> Option<u16> {
+        match *self.names.get(name)? {
+            (Location::Cpu(addr), _) | (_, Some(addr)) => Some(addr),
+            (Location::Prg(offset), None) => bus.prg_rom_addr(offset as usize),
+        }
+    }
+
+    fn find_label(&self, location: Location) -> Option<String> {
+        let (start, label) = self.labels.range(..=location).next_back()?;
+        let offset = match (*start, location) {
+            (Location::Cpu(start), Location::Cpu(addr)) => (addr - start) as u32,
+            (Location::Prg(start), Location::Prg(offset)) => offset - start,
+            _ => return None,
+        };
+        match offset {
+            0 => Some(label.name.clone()),
+            _ if offset < label.size => Some(format!("{}+{}", label.name, offset)),
+            _ => None,
+        }
+    }
+
+    // ld65 debug info, one record per line like
+    // `sym id=3,name="reset",addrsize=absolute,scope=0,def=5,val=0xC000,seg=1,type=lab`
+    pub fn parse_dbg(&mut self, text: &str) -> Result<(), String> {
+        let mut files = HashMap::new();
+        let mut segments = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut symbols = Vec::new();
+        let mut lines = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |msg: String| format!("line {}: {}", index + 1, msg);
+            let (kind, fields) = match line.split_once(char::is_whitespace) {
+                Some((kind, fields)) => (kind, parse_dbg_fields(fields)),
+                None => continue,
+            };
+            let number = |key: &str| -> Result<Option<u32>, String> {
+                fields
+                    .get(key)
+                    .map(|value| {
+                        parse_dbg_number(value)
+                            .ok_or_else(|| error(format!("invalid {} {}", key, value)))
+                    })
+                    .transpose()
+            };
+            let id = number("id")?;
+            match (kind, id) {
+                ("file", Some(id)) => {
+                    files.insert(id, fields.get("name").cloned().unwrap_or_default());
+                }
+                ("seg", Some(id)) => {
+                    let start = number("start")?.unwrap_or(0);
+                    //only segments written to the ROM image have a file offset
+                    let offset = number("ooffs")?.filter(|_| fields.contains_key("oname"));
+                    segments.insert(id, (start, offset));
+                }
+                ("span", Some(id)) => {
+                    spans.insert(id, (number("seg")?, number("start")?.unwrap_or(0)));
+                }
+                ("sym", _) => {
+                    if fields.get("type").map(String::as_str) != Some("lab") {
+                        continue;
+                    }
+                    let name = fields.get("name").cloned().unwrap_or_default();
+                    let value = number("val")?.unwrap_or(0);
+                    let (segment, size) = (number("seg")?, number("size")?.unwrap_or(1));
+                    symbols.push((index, name, value, segment, size));
+                }
+                ("line", _) => {
+                    //type 2 lines point into macro definitions
+                    if number("type")? == Some(2) {
+                        continue;
+                    }
+                    let file = number("file")?.unwrap_or(0);
+                    let line = number("line")?.unwrap_or(0);
+                    let span_ids = fields.get("span").cloned().unwrap_or_default();
+                    lines.push((index, file, line, span_ids));
+                }
+                _ => {}
+            }
+        }
+
+        //None when `addr` is not inside the segment
+        let resolve = |segment: Option<u32>, addr: u32| match segment.and_then(|s| segments.get(&s))
+        {
+            Some(&(start, Some(file_offset))) => {
+                let offset = addr.checked_sub(start)?.checked_add(file_offset)?;
+                Some(match offset.checked_sub(INES_HEADER_SIZE) {
+                    Some(offset) => Location::Prg(offset),
+                    None => Location::Cpu(addr as u16),
+                })
+            }
+            _ => Some(Location::Cpu(addr as u16)),
+        };
+        let outside = |index: usize, what: String| {
+            format!("line {}: {} is outside its segment", index + 1, what)
+        };
+        for (index, name, value, segment, size) in symbols {
+            let location = resolve(segment, value).ok_or_else(|| outside(index, name.clone()))?;
+            self.add_label(location, Some(value as u16), &name, size);
+        }
+        for (index, file, line, span_ids) in lines {
+            let file = match files.get(&file) {
+                Some(file) => file,
+                None => continue,
+            };
+            for span in span_ids.split('+').filter_map(parse_dbg_number) {
+                if let Some(&(segment, start)) = spans.get(&span) {
+                    let segment_start = segment
+                        .and_then(|s| segments.get(&s))
+                        .map_or(0, |&(start, _)| start);
+                    let location = segment_start
+                        .checked_add(start)
+                        .and_then(|addr| resolve(segment, addr))
+                        .ok_or_else(|| outside(index, format!("span {}", span)))?;
+                    self.add_line(
+                        location,
+                        SourceLine {
+                            file: file.clone(),
+                            line,
+                        },
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Mesen labels, `TYPE:ADDR[-END]:NAME[:COMMENT]` where P is a PRG ROM offset, R the
+    // internal RAM, S and W PRG-RAM at $6000 and G a CPU address like a register
+    pub fn parse_mlb(&mut self, text: &str) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let error = |msg: String| format!("line {}: {}", number + 1, msg);
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.splitn(4, ':');
+            let (kind, range, name) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(kind), Some(range), Some(name)) => (kind, range, name),
+                _ => return Err(error(format!("invalid label {}", line))),
+            };
+            //comment-only entries
+            if name.is_empty() {
+                continue;
+            }
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (start, end),
+                None => (range, range),
+            };
+            let parse = |text: &str| {
+                u32::from_str_radix(text, 16)
+                    .map_err(|_| error(format!("invalid address {}", text)))
+            };
+            let (start, end) = (parse(start)?, parse(end)?);
+            let location = match kind {
+                "P" | "NesPrgRom" => Location::Prg(start),
+                "R" | "NesInternalRam" => Location::Cpu(start as u16 & 0x07ff),
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => {
+                    let addr = u16::try_from(start)
+                        .ok()
+                        .and_then(|s| s.checked_add(0x6000));
+                    Location::Cpu(
+                        addr.ok_or_else(|| error(format!("invalid address {:x}", start)))?,
+                    )
+                }
+                "G" | "NesMemory" => Location::Cpu(start as u16),
+                //CHR, palette and other PPU side labels
+                _ => continue,
+            };
+            self.add_label(location, None, name, end.saturating_sub(start) + 1);
+        }
+        Ok(())
+    }
+
+    // FCEUX name lists, `$ADDR[/SIZE]#NAME#COMMENT`, the PRG addresses of a bank file
+    // belong to that 16 KiB bank
+    pub fn parse_nl(&mut self, text: &str, bank: Option<u32>) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let error = |msg: String| format!("line {}: {}", number + 1, msg);
+            let line = line.trim_end_matches('\r');
+            let mut fields = line.splitn(3, '#');
+            let (addr, name) = match (fields.next(), fields.next()) {
+                (Some(addr), Some(name)) if addr.starts_with('$') => (&addr[1..], name),
+                _ => continue,
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let (addr, size) = match addr.split_once('/') {
+                Some((addr, size)) => (
+                    addr,
+                    u32::from_str_radix(size, 16)
+                        .map_err(|_| error(format!("invalid size {}", size)))?,
+                ),
+                None => (addr, 1),
+            };
+            let addr = u16::from_str_radix(addr, 16)
+                .map_err(|_| error(format!("invalid address {}", addr)))?;
+            let location = match bank {
+                Some(bank) if addr >= 0x8000 => {
+                    Location::Prg(bank * NL_BANK_SIZE + (addr as u32 % NL_BANK_SIZE))
+                }
+                _ => Location::Cpu(addr),
+            };
+            self.add_label(location, Some(addr), name, size);
+        }
+        Ok(())
+    }
+}
+
+//comma separated key=value pairs, values may be quoted and contain commas
+fn parse_dbg_fields(text: &str) -> HashMap<&str, String> {
+    let mut fields = HashMap::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let (key, value) = match rest.split_once('=') {
+            Some(pair) => pair,
+            None => break,
+        };
+        let (value, next) = if let Some(quoted) = value.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            let next = quoted.get(end + 1..).unwrap_or("");
+            (&quoted[..end], next.strip_prefix(',').unwrap_or(next))
+        } else {
+            value.split_once(',').unwrap_or((value, ""))
+        };
+        fields.insert(key.trim(), value.to_string());
+        rest = next;
+    }
+    fields
+}
+
+fn parse_dbg_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
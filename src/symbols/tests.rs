@@ -0,0 +1,152 @@
+use crate::debugger::Debugger;
+use crate::symbols::*;
+use crate::test_util::test_nes;
+
+//8000 LDX #$00
+//8002 JSR $800c
+//8005 INX
+//8006 STX $0300
+//8009 JMP $8002
+//800c LDA #$05
+//800e STA $10
+//8010 RTS
+const PROGRAM: [u8; 17] = [
+    0xa2, 0x00, 0x20, 0x0c, 0x80, 0xe8, 0x8e, 0x00, 0x03, 0x4c, 0x02, 0x80, 0xa9, 0x05, 0x85, 0x10,
+    0x60,
+];
+
+const DBG: &str = r#"version	major=2,minor=0
+file	id=0,name="main.s",size=100,mtime=0x5E000000,mod=0
+line	id=0,file=0,line=5,span=0
+line	id=1,file=0,line=6,span=1+2
+line	id=2,file=0,line=40,span=3,type=2
+seg	id=0,name="HEADER",start=0x000000,size=0x0010,addrsize=absolute,type=ro,oname="game.nes",ooffs=0
+seg	id=1,name="CODE",start=0x008000,size=0x0100,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+seg	id=2,name="BSS",start=0x000300,size=0x0010,addrsize=absolute,type=rw
+span	id=0,seg=1,start=0,size=2
+span	id=1,seg=1,start=2,size=3
+span	id=2,seg=1,start=5,size=1
+span	id=3,seg=1,start=12,size=2
+sym	id=0,name="reset",addrsize=absolute,scope=0,def=0,ref=1,val=0x8000,seg=1,type=lab
+sym	id=1,name="sub",addrsize=absolute,scope=0,def=1,val=0x800C,seg=1,type=lab
+sym	id=2,name="buffer",addrsize=absolute,size=16,scope=0,def=2,val=0x300,seg=2,type=lab
+sym	id=3,name="COUNT",addrsize=zeropage,scope=0,def=3,val=0x10,type=equ
+"#;
+
+#[test]
+fn test_parse_dbg() {
+    let nes = test_nes(&PROGRAM);
+    let bus = &nes.cpu.bus;
+    let mut symbols = Symbols::new();
+    symbols.parse_dbg(DBG).unwrap();
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.label(bus, 0x8000).as_deref(), Some("reset"));
+    //the 16 KiB bank is mirrored, both copies are the same code
+    assert_eq!(symbols.label(bus, 0xc00c).as_deref(), Some("sub"));
+    assert_eq!(symbols.label(bus, 0x0305).as_deref(), Some("buffer+5"));
+    assert_eq!(symbols.label(bus, 0x0310), None);
+    assert_eq!(symbols.label_at(bus, 0x0305), None);
+    assert_eq!(symbols.address(bus, "sub"), Some(0x800c));
+    assert_eq!(symbols.address(bus, "COUNT"), None);
+
+    let line = |addr| {
+        symbols
+            .source_line(bus, addr)
+            .map(|l| (l.file.as_str(), l.line))
+    };
+    assert_eq!(line(0x8000), Some(("main.s", 5)));
+    assert_eq!(line(0x8005), Some(("main.s", 6)));
+    assert_eq!(line(0x800c), None);
+}
+
+#[test]
+fn test_dbg_symbol_outside_its_segment() {
+    let dbg = "seg\tid=1,name=\"CODE\",start=0x008000,size=0x0100,oname=\"game.nes\",ooffs=16\n\
+               sym\tid=0,name=\"early\",val=0x7FF0,seg=1,type=lab\n";
+    assert_eq!(
+        Symbols::new().parse_dbg(dbg).unwrap_err(),
+        "line 2: early is outside its segment"
+    );
+
+    let dbg = "file\tid=0,name=\"main.s\"\n\
+               seg\tid=1,name=\"CODE\",start=0xFFFFFFFF,size=0x0100,oname=\"game.nes\",ooffs=16\n\
+               span\tid=0,seg=1,start=2,size=1\n\
+               line\tid=0,file=0,line=5,span=0\n";
+    assert_eq!(
+        Symbols::new().parse_dbg(dbg).unwrap_err(),
+        "line 4: span 0 is outside its segment"
+    );
+}
+
+#[test]
+fn test_parse_mlb() {
+    let nes = test_nes(&PROGRAM);
+    let bus = &nes.cpu.bus;
+    let mut symbols = Symbols::new();
+    symbols
+        .parse_mlb(
+            "P:000C:sub\nR:0010:counter:frames\nW:0000-0003:table\nG:2000:PPUCTRL\nP:0020::note\n",
+        )
+        .unwrap();
+    assert_eq!(symbols.label(bus, 0x800c).as_deref(), Some("sub"));
+    assert_eq!(symbols.label(bus, 0x0010).as_deref(), Some("counter"));
+    assert_eq!(symbols.label(bus, 0x6002).as_deref(), Some("table+2"));
+    assert_eq!(symbols.label(bus, 0x2000).as_deref(), Some("PPUCTRL"));
+    assert_eq!(symbols.label(bus, 0x8020), None);
+    //no CPU address in the file, the upper mirror is used
+    assert_eq!(symbols.address(bus, "sub"), Some(0xc00c));
+    assert!(symbols
+        .parse_mlb("P:zz:broken")
+        .unwrap_err()
+        .contains("line 1"));
+    //save RAM past the end of the address space
+    assert!(symbols
+        .parse_mlb("W:0000:ok\nS:A000:broken")
+        .unwrap_err()
+        .contains("line 2"));
+}
+
+#[test]
+fn test_parse_nl() {
+    let nes = test_nes(&PROGRAM);
+    let bus = &nes.cpu.bus;
+    let mut symbols = Symbols::new();
+    symbols
+        .parse_nl("$C00C#sub#returns 5\n$C000#reset#\n", Some(0))
+        .unwrap();
+    symbols.parse_nl("$0300/10#buffer#\n", None).unwrap();
+    assert_eq!(symbols.label(bus, 0x800c).as_deref(), Some("sub"));
+    assert_eq!(symbols.label(bus, 0x8000).as_deref(), Some("reset"));
+    assert_eq!(symbols.label(bus, 0x030f).as_deref(), Some("buffer+15"));
+    assert_eq!(symbols.address(bus, "sub"), Some(0xc00c));
+}
+
+#[test]
+fn test_debugger_shows_labels() {
+    let mut nes = test_nes(&PROGRAM);
+    let mut debugger = Debugger::new();
+    debugger.symbols.parse_dbg(DBG).unwrap();
+
+    let disassembly = debugger.execute(&mut nes, "disasm reset 4").unwrap();
+    assert!(disassembly.starts_with("reset:\n> 8000"));
+    assert!(disassembly.contains("JSR sub  ; main.s:6"));
+    assert!(disassembly.contains("STX buffer\n"));
+
+    assert!(debugger
+        .execute(&mut nes, "break sub")
+        .unwrap()
+        .contains("$800c"));
+    let stop = debugger.execute(&mut nes, "continue").unwrap();
+    assert!(stop.contains("Breakpoint at $800c <sub>"));
+    assert!(debugger
+        .execute(&mut nes, "bt")
+        .unwrap()
+        .contains("$800c <sub> from $8002 (JSR)"));
+
+    let trace = debugger.execute(&mut nes, "trace 2").unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines[0], "sub:");
+    assert!(lines[1].starts_with("800c  a9 05     LDA #$05"));
+    assert!(lines[2].starts_with("800e  85 10     STA $10"));
+    assert!(lines[2].contains("A:05"));
+}
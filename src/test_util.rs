@@ -0,0 +1,36 @@
+//! Helpers shared by the unit tests.
+
+use crate::cartridge::Rom;
+use crate::nes::Nes;
+use std::path::PathBuf;
+
+/// A file in the temp directory, unique to this test run.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("nes_emulator_{}_{}", std::process::id(), name))
+}
+
+/// iNES image of an NROM cartridge with each piece at its offset into 16 KiB of NOPs,
+/// the reset vector pointing at $8000 and empty CHR ROM.
+pub fn test_rom_with(pieces: &[(usize, &[u8])]) -> Vec<u8> {
+    let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+    raw.extend(vec![0x00; 8]);
+    let mut prg = vec![0xEA; 16384];
+    for (offset, bytes) in pieces.iter() {
+        prg[*offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+    prg[0x3ffc] = 0x00;
+    prg[0x3ffd] = 0x80;
+    raw.extend(prg);
+    raw.extend(vec![0x00; 8192]);
+    raw
+}
+
+/// A console running `program` from $8000.
+pub fn test_nes(program: &[u8]) -> Nes {
+    test_nes_with(&[(0, program)])
+}
+
+/// A console with `test_rom_with(pieces)` inserted.
+pub fn test_nes_with(pieces: &[(usize, &[u8])]) -> Nes {
+    Nes::new(Rom::new(&test_rom_with(pieces)).unwrap())
+}
@@ -0,0 +1,40 @@
+//! Runs blargg's test ROMs (instr_test-v5, cpu_timing_test, ppu_vbl_nmi, apu_test, ...)
+//! and fails when any of them does not pass.
+//!
+//! The ROMs are not part of the repository, put them anywhere under `tests/roms/blargg/`
+//! or point `BLARGG_ROMS` at a directory of them. They run for minutes of emulated time
+//! each, so the test only runs when asked for, in a release build and with `--nocapture`
+//! for the results of every ROM:
+//!
+//!     cargo test --release --test blargg -- --ignored --nocapture
+
+use nes_emulator::blargg::{self, DEFAULT_MAX_FRAMES};
+use std::path::PathBuf;
+
+#[test]
+#[ignore = "needs blargg's test ROMs in tests/roms/blargg or BLARGG_ROMS"]
+fn blargg_roms() {
+    let dir = std::env::var_os("BLARGG_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms/blargg"));
+    assert!(dir.is_dir(), "{} not found", dir.display());
+    let roms = blargg::find_roms(&dir).unwrap();
+    assert!(!roms.is_empty(), "no ROMs in {}", dir.display());
+
+    let mut failed = Vec::new();
+    for rom in roms.iter() {
+        let name = rom.strip_prefix(&dir).unwrap_or(rom).display().to_string();
+        let result = blargg::run_file(rom, DEFAULT_MAX_FRAMES);
+        println!("{}: {}", name, result.to_string().replace('\n', "\n    "));
+        if !result.passed() {
+            failed.push(name);
+        }
+    }
+    assert!(
+        failed.is_empty(),
+        "{} of {} ROMs did not pass: {}",
+        failed.len(),
+        roms.len(),
+        failed.join(", ")
+    );
+}
@@ -0,0 +1,37 @@
+//! Screenshot regression tests from `tests/regression/manifest.txt`, or the manifest
+//! `REGRESSION_MANIFEST` points at. The ROMs are not part of the repository, so the test
+//! only runs when asked for:
+//!
+//!     cargo test --release --test regression -- --ignored
+//!
+//! See `regression` for the format, `nes_emulator regress --update MANIFEST` records new
+//! hashes.
+
+use nes_emulator::regression::{Manifest, Outcome};
+use std::path::{Path, PathBuf};
+
+#[test]
+#[ignore = "needs a manifest in tests/regression or REGRESSION_MANIFEST"]
+fn screenshot_regressions() {
+    let path = std::env::var_os("REGRESSION_MANIFEST")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/regression/manifest.txt")
+        });
+    let mut manifest = Manifest::open(&path).unwrap();
+    let results = manifest.run(path.parent().unwrap_or_else(|| Path::new("")), false);
+    assert!(!results.is_empty(), "no tests in {}", path.display());
+
+    let failed: Vec<String> = results
+        .iter()
+        .filter(|result| result.outcome != Outcome::Passed)
+        .map(|result| format!("{}: {:?}", result.name, result.outcome))
+        .collect();
+    assert!(
+        failed.is_empty(),
+        "{} of {} screenshots differ, see screenshots/ next to the manifest:\n{}",
+        failed.len(),
+        results.len(),
+        failed.join("\n")
+    );
+}